@@ -25,6 +25,12 @@ class CommandMaker:
         assert isinstance(filename, str)
         return f'./node generate_keys --filename {filename}'
 
+    @staticmethod
+    def deal_keys(filenames):
+        assert isinstance(filenames, list)
+        assert all(isinstance(x, str) for x in filenames)
+        return f'./node deal_keys --filenames {" ".join(filenames)}'
+
     @staticmethod
     def run_primary(keys, committee, store, parameters, debug=False):
         assert isinstance(keys, str)
@@ -168,16 +168,44 @@ class NodeParameters:
             inputs += [json['header_size']]
             inputs += [json['max_header_delay']]
             inputs += [json['gc_depth']]
+            inputs += [json['retention_depth']]
+            inputs += [json['max_round_lookahead']]
+            inputs += [json['payload_cache_size']]
             inputs += [json['sync_retry_delay']]
             inputs += [json['sync_retry_nodes']]
             inputs += [json['batch_size']]
             inputs += [json['max_batch_delay']]
+            inputs += [json['max_batch_size']]
+            inputs += [json['dedup_window']]
+            inputs += [json['max_pending_batches']]
+            inputs += [json['max_transaction_size']]
+            inputs += [json['max_client_rate']]
+            inputs += [json['store_cache_size']]
+            compression = json['batch_compression']
+            dissemination = json['batch_dissemination']
+            store_compression = json['store_compression']
+            wal_sync = json['store_wal_sync']
         except KeyError as e:
             raise ConfigError(f'Malformed parameters: missing key {e}')
 
         if not all(isinstance(x, int) for x in inputs):
             raise ConfigError('Invalid parameters type')
 
+        if json['retention_depth'] < json['gc_depth']:
+            raise ConfigError('Retention depth must be at least gc depth')
+
+        if compression not in ['none', 'lz4', 'zstd']:
+            raise ConfigError(f'Unknown batch compression {compression}')
+
+        if dissemination not in ['full', 'erasure_coded']:
+            raise ConfigError(f'Unknown batch dissemination {dissemination}')
+
+        if store_compression not in ['none', 'snappy', 'lz4', 'zstd']:
+            raise ConfigError(f'Unknown store compression {store_compression}')
+
+        if wal_sync not in ['sync', 'buffered', 'disabled']:
+            raise ConfigError(f'Unknown store WAL sync {wal_sync}')
+
         self.json = json
 
     def print(self, filename):
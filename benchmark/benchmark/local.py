@@ -68,9 +68,9 @@ class LocalBench:
             # Generate configuration files.
             keys = []
             key_files = [PathMaker.key_file(i) for i in range(nodes)]
+            cmd = CommandMaker.deal_keys(key_files).split()
+            subprocess.run(cmd, check=True)
             for filename in key_files:
-                cmd = CommandMaker.generate_key(filename).split()
-                subprocess.run(cmd, check=True)
                 keys += [Key.from_file(filename)]
 
             names = [x.name for x in keys]
@@ -24,10 +24,23 @@ def local(ctx, debug=True):
         'header_size': 1_000,  # bytes
         'max_header_delay': 200,  # ms
         'gc_depth': 50,  # rounds
+        'retention_depth': 1_000,  # rounds
+        'max_round_lookahead': 100,  # rounds
+        'payload_cache_size': 10_000_000,  # bytes
         'sync_retry_delay': 10_000,  # ms
         'sync_retry_nodes': 3,  # number of nodes
         'batch_size': 500_000,  # bytes
-        'max_batch_delay': 200  # ms
+        'max_batch_delay': 200,  # ms
+        'max_batch_size': 1_000_000,  # bytes
+        'dedup_window': 0,  # rounds
+        'max_pending_batches': 100,  # number of batches
+        'max_transaction_size': 100_000,  # bytes
+        'max_client_rate': 0,  # tx/s
+        'batch_compression': 'none',  # none, lz4, or zstd
+        'batch_dissemination': 'full',  # full or erasure_coded
+        'store_cache_size': 32_000_000,  # bytes
+        'store_compression': 'snappy',  # none, snappy, lz4, or zstd
+        'store_wal_sync': 'buffered'  # sync, buffered, or disabled
     }
     try:
         ret = LocalBench(bench_params, node_params).run(debug)
@@ -107,10 +120,23 @@ def remote(ctx, debug=False):
         'header_size': 1_000,  # bytes
         'max_header_delay': 200,  # ms
         'gc_depth': 50,  # rounds
+        'retention_depth': 1_000,  # rounds
+        'max_round_lookahead': 100,  # rounds
+        'payload_cache_size': 10_000_000,  # bytes
         'sync_retry_delay': 10_000,  # ms
         'sync_retry_nodes': 3,  # number of nodes
         'batch_size': 500_000,  # bytes
-        'max_batch_delay': 200  # ms
+        'max_batch_delay': 200,  # ms
+        'max_batch_size': 1_000_000,  # bytes
+        'dedup_window': 0,  # rounds
+        'max_pending_batches': 100,  # number of batches
+        'max_transaction_size': 100_000,  # bytes
+        'max_client_rate': 0,  # tx/s
+        'batch_compression': 'none',  # none, lz4, or zstd
+        'batch_dissemination': 'full',  # full or erasure_coded
+        'store_cache_size': 32_000_000,  # bytes
+        'store_compression': 'snappy',  # none, snappy, lz4, or zstd
+        'store_wal_sync': 'buffered'  # sync, buffered, or disabled
     }
     try:
         Bench(ctx).run(bench_params, node_params, debug)
@@ -81,10 +81,9 @@ echo "--- Stage 2: Configuration File Generation ---"
 echo "INFO: Generating key files..."
 key_files=()
 for i in $(seq 0 $((NODES-1))); do
-    key_file="$BENCHMARK_DIR/.node-$i.json"
-    $NODE_BINARY generate_keys --filename "$key_file"
-    key_files+=("$key_file")
+    key_files+=("$BENCHMARK_DIR/.node-$i.json")
 done
+$NODE_BINARY deal_keys --filenames "${key_files[@]}"
 
 echo "INFO: Creating parameters file ($PARAMETERS_FILE)..."
 jq -n \
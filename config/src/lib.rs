@@ -1,5 +1,8 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
-use crypto::{generate_production_keypair, PublicKey, SecretKey};
+use crypto::{
+    deal_production_threshold_keys, generate_production_keypair, PublicKey, SecretKey,
+    ThresholdPublicKey, ThresholdSecretKey,
+};
 use log::info;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -10,6 +13,10 @@ use std::io::Write as _;
 use std::net::SocketAddr;
 use thiserror::Error;
 
+#[cfg(test)]
+#[path = "tests/config_tests.rs"]
+pub mod config_tests;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Node {0} is not in the committee")]
@@ -23,6 +30,9 @@ pub enum ConfigError {
 
     #[error("Failed to write config file '{file}': {message}")]
     ExportError { file: String, message: String },
+
+    #[error("Invalid parameters: {0}")]
+    InvalidParameters(String),
 }
 
 pub trait Import: DeserializeOwned {
@@ -58,7 +68,53 @@ pub trait Export: Serialize {
 pub type Stake = u32;
 pub type WorkerId = u32;
 
+/// The encoding of the batches the workers send to each other.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+/// How the workers disseminate their batches.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchDissemination {
+    /// Send the whole batch to every other worker.
+    Full,
+    /// Reed-Solomon encode the batch into one chunk per authority, any f+1 of which suffice to
+    /// reconstruct it, and send every other worker its chunk only.
+    ErasureCoded,
+}
+
+/// The compression RocksDB applies to the data of the store.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreCompression {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+/// How RocksDB writes its write-ahead log to disk.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WalSync {
+    /// Sync the log on every write: a crash of the machine loses no write.
+    Sync,
+    /// Leave the log to the OS: a crash of the machine (but not of the process) may lose the last
+    /// writes.
+    Buffered,
+    /// Do not write a log: a crash of the process may lose the writes not yet flushed to disk.
+    Disabled,
+}
+
+/// The parameters of the nodes. The missing ones (e.g. in the files of older versions) take their
+/// default value.
 #[derive(Deserialize, Clone)]
+#[serde(default)]
 pub struct Parameters {
     /// The preferred header size. The primary creates a new header when it has enough parents and
     /// enough batches' digests to reach `header_size`. Denominated in bytes.
@@ -68,6 +124,16 @@ pub struct Parameters {
     pub max_header_delay: u64,
     /// The depth of the garbage collection (Denominated in number of rounds).
     pub gc_depth: u64,
+    /// The number of rounds (before the last committed round) during which the primaries keep
+    /// their headers and certificates, and the workers their batches, in the store. Older ones are
+    /// deleted. It must be at least `gc_depth`. Denominated in number of rounds.
+    pub retention_depth: u64,
+    /// How far ahead of the highest round we received a certificate for we accept headers and
+    /// certificates (and sync their dependencies). Denominated in number of rounds.
+    pub max_round_lookahead: u64,
+    /// The maximum memory the primary uses to cache the digests of the batches its workers hold.
+    /// Denominated in bytes.
+    pub payload_cache_size: usize,
     /// The delay after which the synchronizer retries to send sync requests. Denominated in ms.
     pub sync_retry_delay: u64,
     /// Determine with how many nodes to sync when re-trying to send sync-request. These nodes
@@ -76,9 +142,36 @@ pub struct Parameters {
     /// The preferred batch size. The workers seal a batch of transactions when it reaches this size.
     /// Denominated in bytes.
     pub batch_size: usize,
-    /// The delay after which the workers seal a batch of transactions, even if `max_batch_size`
+    /// The delay after which the workers seal a batch of transactions, even if `batch_size`
     /// is not reached. Denominated in ms.
     pub max_batch_delay: u64,
+    /// The maximum size of the batches the workers accept from each other (before and after
+    /// decompression). It must exceed `batch_size` plus `max_transaction_size`. Denominated in
+    /// bytes.
+    pub max_batch_size: usize,
+    /// The number of rounds during which the committed transactions are remembered, so that a
+    /// transaction committed twice within that window is only output once. Zero disables the
+    /// deduplication. Denominated in number of rounds.
+    pub dedup_window: u64,
+    /// The maximum number of sealed batches awaiting acknowledgements from the other workers. The
+    /// workers reply to clients that they are overloaded above this limit.
+    pub max_pending_batches: usize,
+    /// The maximum size of a client transaction. Denominated in bytes.
+    pub max_transaction_size: usize,
+    /// The maximum rate at which a client connection may submit transactions. Zero disables the
+    /// limit. Denominated in transactions per second.
+    pub max_client_rate: u64,
+    /// How the workers compress the batches they send to each other. All authorities must use
+    /// the same setting. The digest of a batch is always computed over its uncompressed form.
+    pub batch_compression: BatchCompression,
+    /// How the workers disseminate their batches. All authorities must use the same setting.
+    pub batch_dissemination: BatchDissemination,
+    /// The size of the block cache of the store (RocksDB). Denominated in bytes.
+    pub store_cache_size: usize,
+    /// How the store (RocksDB) compresses its data.
+    pub store_compression: StoreCompression,
+    /// How the store (RocksDB) writes its write-ahead log to disk.
+    pub store_wal_sync: WalSync,
 }
 
 impl Default for Parameters {
@@ -87,10 +180,23 @@ impl Default for Parameters {
             header_size: 1_000,
             max_header_delay: 100,
             gc_depth: 50,
+            retention_depth: 1_000,
+            max_round_lookahead: 100,
+            payload_cache_size: 10_000_000,
             sync_retry_delay: 5_000,
             sync_retry_nodes: 3,
             batch_size: 500_000,
             max_batch_delay: 100,
+            max_batch_size: 1_000_000,
+            dedup_window: 0,
+            max_pending_batches: 100,
+            max_transaction_size: 100_000,
+            max_client_rate: 0,
+            batch_compression: BatchCompression::None,
+            batch_dissemination: BatchDissemination::Full,
+            store_cache_size: 32_000_000,
+            store_compression: StoreCompression::Snappy,
+            store_wal_sync: WalSync::Buffered,
         }
     }
 }
@@ -98,14 +204,45 @@ impl Default for Parameters {
 impl Import for Parameters {}
 
 impl Parameters {
+    /// Checks the constraints between the parameters.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // The pruner would delete the certificates the core and the consensus still read.
+        if self.retention_depth < self.gc_depth {
+            return Err(ConfigError::InvalidParameters(format!(
+                "retention_depth ({}) must be at least gc_depth ({})",
+                self.retention_depth, self.gc_depth
+            )));
+        }
+        Ok(())
+    }
+
     pub fn log(&self) {
         info!("Header size set to {} B", self.header_size);
         info!("Max header delay set to {} ms", self.max_header_delay);
         info!("Garbage collection depth set to {} rounds", self.gc_depth);
+        info!("Retention depth set to {} rounds", self.retention_depth);
+        info!(
+            "Max round lookahead set to {} rounds",
+            self.max_round_lookahead
+        );
+        info!("Payload cache size set to {} B", self.payload_cache_size);
         info!("Sync retry delay set to {} ms", self.sync_retry_delay);
         info!("Sync retry nodes set to {} nodes", self.sync_retry_nodes);
         info!("Batch size set to {} B", self.batch_size);
         info!("Max batch delay set to {} ms", self.max_batch_delay);
+        info!("Max batch size set to {} B", self.max_batch_size);
+        info!("Deduplication window set to {} rounds", self.dedup_window);
+        info!("Max pending batches set to {}", self.max_pending_batches);
+        info!(
+            "Max transaction size set to {} B",
+            self.max_transaction_size
+        );
+        info!("Max client rate set to {} tx/s", self.max_client_rate);
+        info!("Batch compression set to {:?}", self.batch_compression);
+        info!("Batch dissemination set to {:?}", self.batch_dissemination);
+        info!("Store cache size set to {} B", self.store_cache_size);
+        info!("Store compression set to {:?}", self.store_compression);
+        info!("Store WAL sync set to {:?}", self.store_wal_sync);
     }
 }
 
@@ -246,12 +383,45 @@ impl Committee {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CoinKeys {
+    /// The public key of the threshold scheme of the common coin.
+    pub public_key: ThresholdPublicKey,
+    /// The public key of the share of each authority. The index of a share is the position of its
+    /// authority in this (ordered) map.
+    pub shares: BTreeMap<PublicKey, ThresholdPublicKey>,
+    /// The number of shares needed to flip the coin.
+    pub threshold: usize,
+}
+
+impl CoinKeys {
+    /// Returns the index and the public key of the share of a specific authority.
+    pub fn share(&self, name: &PublicKey) -> Option<(usize, &ThresholdPublicKey)> {
+        self.shares
+            .iter()
+            .enumerate()
+            .find(|(_, (x, _))| x == &name)
+            .map(|(i, (_, key))| (i, key))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CoinShare {
+    /// The node's share of the secret key of the common coin.
+    pub secret: ThresholdSecretKey,
+    /// The public keys needed to verify and combine the shares of the committee.
+    pub keys: CoinKeys,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct KeyPair {
     /// The node's public key (and identifier).
     pub name: PublicKey,
     /// The node's secret key.
     pub secret: SecretKey,
+    /// The node's share of the common coin. Only key pairs made by `KeyPair::deal` have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin: Option<CoinShare>,
 }
 
 impl Import for KeyPair {}
@@ -260,7 +430,42 @@ impl Export for KeyPair {}
 impl KeyPair {
     pub fn new() -> Self {
         let (name, secret) = generate_production_keypair();
-        Self { name, secret }
+        Self {
+            name,
+            secret,
+            coin: None,
+        }
+    }
+
+    /// Generates the key pairs of a committee of `size` authorities, and deals them the shares
+    /// of the common coin (any f+1 of which flip the coin).
+    pub fn deal(size: usize) -> Vec<Self> {
+        let mut keys: Vec<_> = (0..size).map(|_| generate_production_keypair()).collect();
+        keys.sort_by_key(|(name, _)| *name);
+
+        let threshold = size.div_ceil(3);
+        let (public_key, shares) = deal_production_threshold_keys(size, threshold);
+        let coin = CoinKeys {
+            public_key,
+            shares: keys
+                .iter()
+                .zip(shares.iter())
+                .map(|((name, _), (share, _))| (*name, *share))
+                .collect(),
+            threshold,
+        };
+
+        keys.into_iter()
+            .zip(shares)
+            .map(|((name, secret), (_, share))| Self {
+                name,
+                secret,
+                coin: Some(CoinShare {
+                    secret: share,
+                    keys: coin.clone(),
+                }),
+            })
+            .collect()
     }
 }
 
@@ -0,0 +1,45 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+
+#[test]
+fn import_legacy_parameters() {
+    // A parameters file holding only the original parameters.
+    let path = std::env::temp_dir().join(".test_import_legacy_parameters.json");
+    let data = r#"{
+        "batch_size": 1000,
+        "gc_depth": 25,
+        "header_size": 50,
+        "max_batch_delay": 200,
+        "max_header_delay": 300,
+        "sync_retry_delay": 10000,
+        "sync_retry_nodes": 2
+    }"#;
+    fs::write(&path, data).unwrap();
+
+    // The file sets the original parameters, and the others take their default value.
+    let parameters = Parameters::import(path.to_str().unwrap()).unwrap();
+    assert_eq!(parameters.batch_size, 1_000);
+    assert_eq!(parameters.gc_depth, 25);
+    assert_eq!(parameters.header_size, 50);
+    assert_eq!(parameters.max_batch_delay, 200);
+    assert_eq!(parameters.max_header_delay, 300);
+    assert_eq!(parameters.sync_retry_delay, 10_000);
+    assert_eq!(parameters.sync_retry_nodes, 2);
+
+    let default = Parameters::default();
+    assert_eq!(parameters.retention_depth, default.retention_depth);
+    assert_eq!(parameters.max_batch_size, default.max_batch_size);
+    assert_eq!(parameters.batch_compression, default.batch_compression);
+    assert_eq!(parameters.store_wal_sync, default.store_wal_sync);
+}
+
+#[test]
+fn reject_short_retention_depth() {
+    assert!(Parameters::default().validate().is_ok());
+    let parameters = Parameters {
+        gc_depth: 50,
+        retention_depth: 49,
+        ..Parameters::default()
+    };
+    assert!(parameters.validate().is_err());
+}
@@ -2,19 +2,20 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use config::{Committee, Stake};
+use config::{CoinKeys, Committee, Stake};
 use crypto::Hash as _;
-use crypto::{Digest, PublicKey};
+use crypto::{Digest, PublicKey, ThresholdSignature};
 use log::{debug, error, info, log_enabled, warn};
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
-use store::Store;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use store::{Store, WriteBatch, CONSENSUS};
 use thiserror::Error;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::RwLock;
-use primary::{Certificate, Round};
+use primary::{Certificate, CertificateIndex, Header, Round, LAST_COMMITTED_ROUND_KEY};
 
 // ====================
 // ERROR DEFINITIONS
@@ -54,12 +55,80 @@ pub struct ConsensusMetrics {
     pub failed_leader_elections: u64,
 }
 
+/// The type of the votes an authority casts during a Bullshark wave.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteType {
+    /// Votes for the two steady-state leaders of the wave.
+    Steady,
+    /// Votes for the fallback leader elected at the end of the wave.
+    Fallback,
+}
+
+/// A sub-DAG committed by the consensus: the causal history of a leader that was not committed
+/// before, in the order in which it is committed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommittedSubDag {
+    /// The leader committing the sub-DAG (also its last certificate).
+    pub leader: Certificate,
+    /// The certificates of the sub-DAG, in commit order.
+    pub certificates: Vec<Certificate>,
+    /// The sequence number of the sub-DAG, starting at 1. It increases by one with each sub-DAG
+    /// and survives restarts, so it is the same on all nodes.
+    pub commit_index: u64,
+    /// The round of the leader.
+    pub leader_round: Round,
+    /// The local time at which the sub-DAG was committed (in milliseconds since the Unix epoch).
+    pub timestamp: u64,
+}
+
+impl CommittedSubDag {
+    pub fn new(leader: Certificate, certificates: Vec<Certificate>, commit_index: u64) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_millis() as u64);
+        Self {
+            leader_round: leader.round(),
+            leader,
+            certificates,
+            commit_index,
+            timestamp,
+        }
+    }
+
+    /// Returns the number of certificates of the sub-DAG.
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+}
+
 /// Trạng thái cần được lưu trữ để phục hồi sau sự cố.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConsensusState {
     pub last_committed_round: Round,
     pub last_committed: HashMap<PublicKey, Round>,
     pub dag: Dag,
+    /// The vote type of each authority, indexed by wave (only used by Bullshark).
+    pub vote_types: HashMap<Round, HashMap<PublicKey, VoteType>>,
+    /// The reputation of the authorities (only used by the `ReputationSchedule`).
+    pub reputation: Reputation,
+    /// The number of sub-DAGs committed so far.
+    pub commit_index: u64,
+}
+
+/// The consensus state persisted once the sub-DAGs of each commit are output. It leaves out the DAG, which is rebuilt on startup from the certificates that the primary stored (and
+/// indexed by round) before delivering them to the consensus: the record stays small whatever the
+/// size of the DAG.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitRecord {
+    pub last_committed_round: Round,
+    pub last_committed: HashMap<PublicKey, Round>,
+    pub vote_types: HashMap<Round, HashMap<PublicKey, VoteType>>,
+    pub reputation: Reputation,
+    pub commit_index: u64,
 }
 
 impl ConsensusState {
@@ -77,6 +146,32 @@ impl ConsensusState {
                 .map(|(x, (_, y))| (*x, y.round()))
                 .collect(),
             dag: [(0, genesis_map)].iter().cloned().collect(),
+            vote_types: HashMap::new(),
+            reputation: Reputation::default(),
+            commit_index: 0,
+        }
+    }
+
+    /// Makes the record to persist after a commit.
+    pub fn record(&self) -> CommitRecord {
+        CommitRecord {
+            last_committed_round: self.last_committed_round,
+            last_committed: self.last_committed.clone(),
+            vote_types: self.vote_types.clone(),
+            reputation: self.reputation.clone(),
+            commit_index: self.commit_index,
+        }
+    }
+
+    /// Restores the state from a record and the DAG rebuilt from the store.
+    pub fn from_record(record: CommitRecord, dag: Dag) -> Self {
+        Self {
+            last_committed_round: record.last_committed_round,
+            last_committed: record.last_committed,
+            dag,
+            vote_types: record.vote_types,
+            reputation: record.reputation,
+            commit_index: record.commit_index,
         }
     }
 
@@ -120,6 +215,258 @@ impl ConsensusState {
     }
 }
 
+// ====================
+// LEADER SCHEDULE
+// ====================
+
+/// Elects the leader of each round. A schedule may change as sub-DAGs get committed, but it must
+/// only depend on the committed sequence so that all nodes elect the same leaders.
+pub trait LeaderSchedule: Send + Sync {
+    /// Returns the leader of `round`. The `seed` selects an authority of the committee: it is the
+    /// round itself for round-robin elections and the common coin for random ones.
+    fn leader(&self, state: &ConsensusState, round: Round, seed: u64) -> PublicKey;
+
+    /// Records the sub-DAG committed by `leader`, before `state` is updated with it. Returns
+    /// whether the schedule changed, in which case the leaders that are not committed yet must be
+    /// elected again.
+    fn update(
+        &self,
+        state: &mut ConsensusState,
+        leader: &Certificate,
+        sub_dag: &[Certificate],
+    ) -> bool;
+}
+
+/// Returns the keys of the committee, sorted.
+fn sorted_keys(committee: &Committee) -> Vec<PublicKey> {
+    let mut keys: Vec<_> = committee.authorities.keys().cloned().collect();
+    keys.sort();
+    keys
+}
+
+/// Elects the authorities in turn, regardless of their behaviour.
+pub struct RoundRobin {
+    keys: Vec<PublicKey>,
+}
+
+impl RoundRobin {
+    pub fn new(committee: &Committee) -> Self {
+        Self {
+            keys: sorted_keys(committee),
+        }
+    }
+}
+
+impl LeaderSchedule for RoundRobin {
+    fn leader(&self, _state: &ConsensusState, _round: Round, seed: u64) -> PublicKey {
+        self.keys[seed as usize % self.keys.len()]
+    }
+
+    fn update(
+        &self,
+        _state: &mut ConsensusState,
+        _leader: &Certificate,
+        _sub_dag: &[Certificate],
+    ) -> bool {
+        false
+    }
+}
+
+/// The reputation of the authorities, derived from the committed sequence (only used by the
+/// `ReputationSchedule`).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Reputation {
+    /// The score of each authority during the current period.
+    pub scores: HashMap<PublicKey, u64>,
+    /// The number of sub-DAGs committed during the current period.
+    pub commits: u64,
+    /// The authorities elected in place of the `bad` ones.
+    pub good: Vec<PublicKey>,
+    /// The authorities that are never elected.
+    pub bad: Vec<PublicKey>,
+}
+
+/// Reputation-based leader schedule (as in Shoal and HammerHead). An authority scores a point each
+/// time one of its certificates is referenced by a committed certificate, so crashed and slow
+/// authorities score low. Every `period` committed sub-DAGs, the lowest scorers (holding at most f
+/// stake) are swapped out for the highest scorers until the end of the next period.
+pub struct ReputationSchedule {
+    committee: Committee,
+    keys: Vec<PublicKey>,
+    period: u64,
+}
+
+impl ReputationSchedule {
+    /// The number of committed sub-DAGs between two updates of the schedule.
+    pub const DEFAULT_PERIOD: u64 = 100;
+
+    pub fn new(committee: Committee, period: u64) -> Self {
+        Self {
+            keys: sorted_keys(&committee),
+            committee,
+            period: period.max(1),
+        }
+    }
+
+    /// Picks the authorities holding at most f stake, in the given order.
+    fn pick<'a>(&self, authorities: impl Iterator<Item = &'a PublicKey>) -> Vec<PublicKey> {
+        let max_stake = self.committee.validity_threshold() - 1;
+        let mut stake = 0;
+        let mut picked = Vec::new();
+        for name in authorities {
+            stake += self.committee.stake(name);
+            if stake > max_stake {
+                break;
+            }
+            picked.push(*name);
+        }
+        picked
+    }
+}
+
+impl LeaderSchedule for ReputationSchedule {
+    fn leader(&self, state: &ConsensusState, _round: Round, seed: u64) -> PublicKey {
+        let leader = self.keys[seed as usize % self.keys.len()];
+        let reputation = &state.reputation;
+        if reputation.bad.contains(&leader) && !reputation.good.is_empty() {
+            return reputation.good[seed as usize % reputation.good.len()];
+        }
+        leader
+    }
+
+    fn update(
+        &self,
+        state: &mut ConsensusState,
+        _leader: &Certificate,
+        sub_dag: &[Certificate],
+    ) -> bool {
+        for certificate in sub_dag {
+            let parents = match state.dag.get(&certificate.round().saturating_sub(1)) {
+                Some(x) => x,
+                None => continue,
+            };
+            for (digest, parent) in parents.values() {
+                if certificate.header.parents.contains(digest) {
+                    *state.reputation.scores.entry(parent.origin()).or_default() += 1;
+                }
+            }
+        }
+
+        let reputation = &mut state.reputation;
+        reputation.commits += 1;
+        if reputation.commits < self.period {
+            return false;
+        }
+
+        // Rank the authorities by score, breaking ties by key.
+        let mut ranking = self.keys.clone();
+        ranking.sort_by_key(|x| (reputation.scores.get(x).copied().unwrap_or(0), *x));
+        let bad = self.pick(ranking.iter());
+        let good: Vec<_> = self
+            .pick(ranking.iter().rev())
+            .into_iter()
+            .filter(|x| !bad.contains(x))
+            .collect();
+        let changed = bad != reputation.bad || good != reputation.good;
+
+        info!(
+            "Leader schedule updated, swapping out {:?} for {:?} (scores: {:?})",
+            bad, good, reputation.scores
+        );
+        *reputation = Reputation {
+            good,
+            bad,
+            ..Reputation::default()
+        };
+        changed
+    }
+}
+
+// ====================
+// COMMON COIN
+// ====================
+
+/// The common coin electing the leaders. The coin of a round is the threshold signature over the
+/// round number, combined from the shares carried by the headers of that round: any f+1 valid
+/// shares give the same value, and nobody can predict it before f+1 authorities reach the round.
+/// Without dealt keys, the coin falls back to a public pseudo-random function of the round: this
+/// is only meant for tests, since anybody can then predict the leaders (the node refuses to run
+/// without its coin share).
+pub struct Coin {
+    keys: Option<CoinKeys>,
+    /// The coins flipped so far (checking the shares is expensive).
+    flipped: Mutex<HashMap<Round, u64>>,
+}
+
+impl Coin {
+    pub fn new(keys: Option<CoinKeys>) -> Self {
+        if keys.is_none() {
+            warn!("No common coin keys, leaders are predictable");
+        }
+        Self {
+            keys,
+            flipped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Flips the coin of `round`. Returns `None` if the DAG does not hold enough valid shares of
+    /// that round yet.
+    pub fn flip(&self, round: Round, dag: &Dag) -> Option<u64> {
+        let keys = match &self.keys {
+            Some(keys) => keys,
+            None => return Some(Self::mix(round)),
+        };
+        if let Some(coin) = self.flipped.lock().unwrap().get(&round) {
+            return Some(*coin);
+        }
+
+        let message = Header::coin_message(round);
+        let shares: Vec<_> = dag
+            .get(&round)?
+            .values()
+            .filter_map(|(_, certificate)| {
+                let share = certificate.header.coin.as_ref()?;
+                let (index, key) = keys.share(&certificate.origin())?;
+                match share.verify(&message, key) {
+                    Ok(()) => Some((index, share.clone())),
+                    Err(_) => {
+                        warn!("Invalid coin share from {}", certificate.origin());
+                        None
+                    }
+                }
+            })
+            .take(keys.threshold)
+            .collect();
+        if shares.len() < keys.threshold {
+            debug!("Not enough coin shares for round {}", round);
+            return None;
+        }
+
+        let signature = ThresholdSignature::combine(&shares).ok()?;
+        let digest = signature.digest();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest.0[..8]);
+        let coin = u64::from_le_bytes(bytes);
+
+        debug!("Flipped coin of round {}: {}", round, coin);
+        self.flipped.lock().unwrap().insert(round, coin);
+        Some(coin)
+    }
+
+    /// Forgets the coins of the rounds below `round`.
+    pub fn cleanup(&self, round: Round) {
+        self.flipped.lock().unwrap().retain(|r, _| r >= &round);
+    }
+
+    /// Public pseudo-random function of the round (SplitMix64 finalizer).
+    fn mix(round: Round) -> u64 {
+        let mut z = round.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
 // ====================
 // UTILITY MODULE
 // ====================
@@ -128,67 +475,118 @@ mod utils {
     use super::*;
 
     /// Sắp xếp các leader trong quá khứ chưa được commit.
-    pub fn order_leaders<'a, LeaderElector>(
+    ///
+    /// Walks back from `leader` over every other round down to the last committed round. At each
+    /// round, the first candidate returned by `get_leaders` that is linked to the last ordered
+    /// leader and that `supported` accepts (given that leader and the candidate) is ordered before
+    /// it. Rounds without such a candidate are skipped.
+    pub fn order_leaders<'a, LeaderElector, Support>(
         leader: &Certificate,
         state: &'a ConsensusState,
-        get_leader: LeaderElector,
+        get_leaders: LeaderElector,
+        supported: Support,
     ) -> Vec<Certificate>
     where
-        LeaderElector: Fn(Round, &'a Dag) -> Option<&'a (Digest, Certificate)>,
+        LeaderElector: Fn(Round, &'a ConsensusState) -> Vec<&'a (Digest, Certificate)>,
+        Support: Fn(&Certificate, &Certificate) -> bool,
     {
         let mut to_commit = vec![leader.clone()];
         let mut current_leader = leader;
-        
-        for r in (state.last_committed_round + 2..current_leader.round())
+
+        let rounds = (state.last_committed_round + 1..leader.round())
             .rev()
-            .step_by(2)
-        {
-            if let Some((_, prev_leader)) = get_leader(r, &state.dag) {
-                if linked(current_leader, prev_leader, &state.dag) {
+            .filter(|r| (leader.round() - r).is_multiple_of(2));
+        for r in rounds {
+            let candidate = get_leaders(r, state)
+                .into_iter()
+                .find(|(_, prev_leader)| {
+                    linked(current_leader, prev_leader, &state.dag)
+                        && supported(current_leader, prev_leader)
+                });
+            match candidate {
+                Some((_, prev_leader)) => {
                     debug!("Found linked leader at round {}", r);
                     to_commit.push(prev_leader.clone());
                     current_leader = prev_leader;
-                } else {
-                    debug!("Leader at round {} is not linked, stopping", r);
-                    break;
                 }
-            } else {
-                debug!("No leader found at round {}, stopping", r);
-                break;
+                None => debug!("No linked leader at round {}, skipping", r),
             }
         }
         to_commit
     }
 
-    /// Kiểm tra xem có đường đi giữa hai leader hay không.
-    fn linked(leader: &Certificate, prev_leader: &Certificate, dag: &Dag) -> bool {
-        let mut parents = vec![leader];
-        
-        for r in (prev_leader.round()..leader.round()).rev() {
+    /// Commits the sub-DAGs of `leaders` (starting from the oldest) into `sequence`. Stops after
+    /// the sub-DAG changing the leader schedule, since the next leaders were elected with the
+    /// previous schedule, and returns whether that happened.
+    pub fn commit_leaders(
+        leaders: &[Certificate],
+        state: &mut ConsensusState,
+        schedule: &dyn LeaderSchedule,
+        gc_depth: Round,
+        sequence: &mut Vec<CommittedSubDag>,
+    ) -> bool {
+        for leader in leaders.iter().rev() {
+            let sub_dag = order_dag(gc_depth, leader, state);
+            let changed = schedule.update(state, leader, &sub_dag);
+            for x in &sub_dag {
+                state.update(x, gc_depth);
+            }
+            state.commit_index += 1;
+            sequence.push(CommittedSubDag::new(
+                leader.clone(),
+                sub_dag,
+                state.commit_index,
+            ));
+            if changed {
+                debug!("Leader schedule changed at round {}", leader.round());
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the certificates of `round` that are in the causal history of `certificate`.
+    pub fn ancestors_at<'a>(
+        certificate: &'a Certificate,
+        round: Round,
+        dag: &'a Dag,
+    ) -> Vec<&'a Certificate> {
+        if round > certificate.round() {
+            return Vec::new();
+        }
+        let mut parents = vec![certificate];
+
+        for r in (round..certificate.round()).rev() {
             let round_certificates = match dag.get(&r) {
                 Some(certs) => certs,
                 None => {
                     debug!(
-                        "Missing round {} in DAG during path check (leader round {}, prev_leader round {})",
-                        r, leader.round(), prev_leader.round()
+                        "Missing round {} in DAG during path check (from round {} to round {})",
+                        r,
+                        certificate.round(),
+                        round
                     );
-                    return false;
+                    return Vec::new();
                 }
             };
-            
+
             parents = round_certificates
                 .values()
                 .filter(|(digest, _)| parents.iter().any(|x| x.header.parents.contains(digest)))
                 .map(|(_, certificate)| certificate)
                 .collect();
-                
+
             if parents.is_empty() {
                 debug!("No parents found at round {}, path broken", r);
-                return false;
+                break;
             }
         }
-        
-        parents.contains(&prev_leader)
+        parents
+    }
+
+    /// Kiểm tra xem có đường đi giữa hai leader hay không.
+    pub fn linked(leader: &Certificate, prev_leader: &Certificate, dag: &Dag) -> bool {
+        ancestors_at(leader, prev_leader.round(), dag).contains(&prev_leader)
     }
 
     /// Trải phẳng DAG con được tham chiếu bởi chứng chỉ đầu vào.
@@ -260,7 +658,7 @@ pub trait ConsensusAlgorithm: Send + Sync {
         state: &mut ConsensusState,
         certificate: Certificate,
         metrics: &mut ConsensusMetrics,
-    ) -> Result<(Vec<Certificate>, bool), ConsensusError>;
+    ) -> Result<(Vec<CommittedSubDag>, bool), ConsensusError>;
     
     fn name(&self) -> &'static str;
 }
@@ -269,26 +667,37 @@ pub trait ConsensusAlgorithm: Send + Sync {
 pub struct Tusk {
     pub committee: Committee,
     pub gc_depth: Round,
+    coin: Coin,
+    schedule: Box<dyn LeaderSchedule>,
 }
 
 impl Tusk {
-    pub fn new(committee: Committee, gc_depth: Round) -> Self {
+    pub fn new(
+        committee: Committee,
+        gc_depth: Round,
+        coin_keys: Option<CoinKeys>,
+        schedule: Box<dyn LeaderSchedule>,
+    ) -> Self {
         info!("Initializing Tusk consensus with gc_depth={}", gc_depth);
-        Self { committee, gc_depth }
+        Self {
+            committee,
+            gc_depth,
+            coin: Coin::new(coin_keys),
+            schedule,
+        }
     }
 
-    fn leader<'a>(&self, round: Round, dag: &'a Dag) -> Option<&'a (Digest, Certificate)> {
-        // Simple round-robin leader election
-        #[cfg(test)]
-        let coin = 0;
-        #[cfg(not(test))]
-        let coin = round;
-
-        let mut keys: Vec<_> = self.committee.authorities.keys().cloned().collect();
-        keys.sort();
-        let leader = keys[coin as usize % self.committee.size()];
+    fn leader<'a>(
+        &self,
+        round: Round,
+        state: &'a ConsensusState,
+    ) -> Option<&'a (Digest, Certificate)> {
+        // The leader of a round is elected by the coin of the round closing its wave (two rounds
+        // later), once the round is settled in the DAG.
+        let coin = self.coin.flip(round + 2, &state.dag)?;
 
-        dag.get(&round).and_then(|x| x.get(&leader))
+        let leader = self.schedule.leader(state, round, coin);
+        state.dag.get(&round).and_then(|x| x.get(&leader))
     }
 }
 
@@ -298,7 +707,7 @@ impl ConsensusAlgorithm for Tusk {
         state: &mut ConsensusState,
         certificate: Certificate,
         metrics: &mut ConsensusMetrics,
-    ) -> Result<(Vec<Certificate>, bool), ConsensusError> {
+    ) -> Result<(Vec<CommittedSubDag>, bool), ConsensusError> {
         let round = certificate.round();
         metrics.total_certificates_processed += 1;
 
@@ -315,61 +724,68 @@ impl ConsensusAlgorithm for Tusk {
             return Ok((Vec::new(), false));
         }
 
+        // Elect the leader again whenever committing changes the leader schedule.
         let leader_round = r - 2;
-        if leader_round <= state.last_committed_round {
-            return Ok((Vec::new(), false));
-        }
+        let mut sequence = Vec::new();
+        while leader_round > state.last_committed_round {
+            debug!("Checking for leader at round {}", leader_round);
+            let (leader_digest, leader) = match self.leader(leader_round, state) {
+                Some(x) => {
+                    debug!("Found leader {:?} at round {}", x.1.digest(), leader_round);
+                    x.clone()
+                }
+                None => {
+                    metrics.failed_leader_elections += 1;
+                    debug!("No leader found at round {}", leader_round);
+                    break;
+                }
+            };
 
-        debug!("Checking for leader at round {}", leader_round);
-        let (leader_digest, leader) = match self.leader(leader_round, &state.dag) {
-            Some(x) => {
-                debug!("Found leader {:?} at round {}", x.1.digest(), leader_round);
-                x.clone()
-            }
-            None => {
-                metrics.failed_leader_elections += 1;
-                debug!("No leader found at round {}", leader_round);
-                return Ok((Vec::new(), false));
-            }
-        };
+            // Check f+1 support
+            let stake: Stake = state
+                .dag
+                .get(&(r - 1))
+                .ok_or(ConsensusError::MissingRound(r - 1))?
+                .values()
+                .filter(|(_, x)| x.header.parents.contains(&leader_digest))
+                .map(|(_, x)| self.committee.stake(&x.origin()))
+                .sum();
 
-        // Check f+1 support
-        let stake: Stake = state
-            .dag
-            .get(&(r - 1))
-            .ok_or(ConsensusError::MissingRound(r - 1))?
-            .values()
-            .filter(|(_, x)| x.header.parents.contains(&leader_digest))
-            .map(|(_, x)| self.committee.stake(&x.origin()))
-            .sum();
+            let required_stake = self.committee.validity_threshold();
 
-        let required_stake = self.committee.validity_threshold();
-        
-        if stake < required_stake {
-            debug!(
-                "Leader at round {} has insufficient stake ({}/{})",
+            if stake < required_stake {
+                debug!(
+                    "Leader at round {} has insufficient stake ({}/{})",
+                    leader_round, stake, required_stake
+                );
+                break;
+            }
+
+            info!(
+                "Committing leader at round {} with stake {}/{}",
                 leader_round, stake, required_stake
             );
-            return Ok((Vec::new(), false));
-        }
-
-        info!("Committing leader at round {} with stake {}/{}", leader_round, stake, required_stake);
 
-        // Order and commit
-        let mut sequence = Vec::new();
-        for leader_cert in utils::order_leaders(&leader, state, |r, d| self.leader(r, d))
-            .iter()
-            .rev()
-        {
-            for x in utils::order_dag(self.gc_depth, leader_cert, state) {
-                state.update(&x, self.gc_depth);
-                sequence.push(x);
+            // Order and commit
+            let leaders = utils::order_leaders(
+                &leader,
+                state,
+                |r, s| self.leader(r, s).into_iter().collect(),
+                |_, _| true,
+            );
+            let schedule = &*self.schedule;
+            if !utils::commit_leaders(&leaders, state, schedule, self.gc_depth, &mut sequence) {
+                break;
             }
         }
 
+        self.coin
+            .cleanup(state.last_committed_round.saturating_sub(self.gc_depth));
+
         let committed = !sequence.is_empty();
         if committed {
-            metrics.total_certificates_committed += sequence.len() as u64;
+            metrics.total_certificates_committed +=
+                sequence.iter().map(|x| x.len() as u64).sum::<u64>();
             metrics.last_committed_round = state.last_committed_round;
         }
 
@@ -381,41 +797,282 @@ impl ConsensusAlgorithm for Tusk {
     }
 }
 
-/// Logic đồng thuận Bullshark - IMPLEMENTATION CHÍNH XÁC THEO PAPER
+/// Logic đồng thuận Bullshark.
+///
+/// The DAG is divided into waves of four rounds. Each wave has two steady-state leaders (in its
+/// first and third rounds), committed once 2f+1 steady-state votes of the next round reference
+/// them, and a fallback leader (in its first round) elected by a coin at the end of the wave and
+/// committed once 2f+1 fallback votes of the last round of the wave have a path to it. The vote
+/// type of an authority for a wave is fixed by its certificate of the first round of that wave.
 pub struct Bullshark {
     pub committee: Committee,
     pub gc_depth: Round,
+    coin: Coin,
+    schedule: Box<dyn LeaderSchedule>,
 }
 
 impl Bullshark {
-    pub fn new(committee: Committee, gc_depth: Round) -> Self {
+    /// The number of rounds of a wave.
+    const WAVE_LENGTH: Round = 4;
+
+    pub fn new(
+        committee: Committee,
+        gc_depth: Round,
+        coin_keys: Option<CoinKeys>,
+        schedule: Box<dyn LeaderSchedule>,
+    ) -> Self {
         info!("Initializing Bullshark consensus with gc_depth={}", gc_depth);
-        Self { committee, gc_depth }
+        Self {
+            committee,
+            gc_depth,
+            coin: Coin::new(coin_keys),
+            schedule,
+        }
+    }
+
+    /// Returns the wave of a (non-genesis) round.
+    fn wave(round: Round) -> Round {
+        round.div_ceil(Self::WAVE_LENGTH)
+    }
+
+    /// Returns the first round of a wave.
+    fn first_round(wave: Round) -> Round {
+        (wave - 1) * Self::WAVE_LENGTH + 1
     }
 
-    /// Chọn leader theo round-robin deterministic
-    /// ĐÂY LÀ CÁCH CHÍNH THỨC CỦA BULLSHARK/SUI
-    /// 
+    /// Returns the round holding the votes of the given type for a leader of `round`.
+    fn vote_round(round: Round, vote_type: VoteType) -> Round {
+        match vote_type {
+            VoteType::Steady => round + 1,
+            VoteType::Fallback => round + Self::WAVE_LENGTH - 1,
+        }
+    }
+
+    /// Chọn steady-state leader của một round.
+    ///
     /// Bullshark sử dụng predefined leader selection cho steady-state leaders:
     /// - Round 1 của mỗi wave: steady-state leader #1
     /// - Round 3 của mỗi wave: steady-state leader #2
-    /// 
-    /// Leader được chọn bằng round-robin để:
-    /// - Đảm bảo fairness giữa các validators
-    /// - Deterministic: tất cả nodes đều tính ra cùng leader
-    /// - Byzantine-resistant: không thể manipulate vì predefined
-    fn leader<'a>(&self, round: Round, dag: &'a Dag) -> Option<&'a (Digest, Certificate)> {
-        // Lấy tất cả public keys và sắp xếp để đảm bảo deterministic order
-        let mut keys: Vec<_> = self.committee.authorities.keys().cloned().collect();
-        keys.sort();
-        
-        // Round-robin selection - ĐÚNG THEO PAPER BULLSHARK
-        let leader_pk = &keys[round as usize % self.committee.size()];
-        
+    ///
+    /// The leader schedule elects them with the round as seed, so that every node elects the same
+    /// leader given the same committed sequence.
+    fn steady_leader_name(&self, round: Round, state: &ConsensusState) -> PublicKey {
+        self.schedule.leader(state, round, round)
+    }
+
+    fn leader<'a>(
+        &self,
+        round: Round,
+        state: &'a ConsensusState,
+    ) -> Option<&'a (Digest, Certificate)> {
+        let leader_pk = self.steady_leader_name(round, state);
         debug!("Selected leader for round {}: {:?}", round, leader_pk);
-        
-        // Tìm certificate của leader trong DAG
-        dag.get(&round).and_then(|x| x.get(leader_pk))
+        state.dag.get(&round).and_then(|x| x.get(&leader_pk))
+    }
+
+    /// Elects the fallback leader of a wave with the coin flipped in the last round of the wave.
+    fn fallback_leader_name(&self, wave: Round, state: &ConsensusState) -> Option<PublicKey> {
+        let first = Self::first_round(wave);
+        let coin = self.coin.flip(first + Self::WAVE_LENGTH - 1, &state.dag)?;
+        Some(self.schedule.leader(state, first, coin))
+    }
+
+    /// Returns the certificate of the fallback leader of a wave (from the first round of the wave).
+    fn fallback_leader<'a>(
+        &self,
+        wave: Round,
+        state: &'a ConsensusState,
+    ) -> Option<&'a (Digest, Certificate)> {
+        let leader_pk = self.fallback_leader_name(wave, state)?;
+        state
+            .dag
+            .get(&Self::first_round(wave))
+            .and_then(|x| x.get(&leader_pk))
+    }
+
+    /// Checks whether a certificate is the leader of its round for the given vote type.
+    fn is_leader(
+        &self,
+        certificate: &Certificate,
+        vote_type: VoteType,
+        state: &ConsensusState,
+    ) -> bool {
+        let round = certificate.round();
+        let position = (round - 1) % Self::WAVE_LENGTH;
+        match vote_type {
+            VoteType::Steady => {
+                (position == 0 || position == 2)
+                    && certificate.origin() == self.steady_leader_name(round, state)
+            }
+            VoteType::Fallback => {
+                position == 0
+                    && self.fallback_leader_name(Self::wave(round), state)
+                        == Some(certificate.origin())
+            }
+        }
+    }
+
+    /// Returns all the leaders (steady-state or fallback) of a round.
+    fn leaders<'a>(
+        &self,
+        round: Round,
+        state: &'a ConsensusState,
+    ) -> Vec<&'a (Digest, Certificate)> {
+        let mut leaders = Vec::new();
+        if round == 0 {
+            return leaders;
+        }
+        match (round - 1) % Self::WAVE_LENGTH {
+            0 => {
+                leaders.extend(self.leader(round, state));
+                if let Some(fallback) = self.fallback_leader(Self::wave(round), state) {
+                    if !leaders.contains(&fallback) {
+                        leaders.push(fallback);
+                    }
+                }
+            }
+            2 => leaders.extend(self.leader(round, state)),
+            _ => (),
+        }
+        leaders
+    }
+
+    /// Returns the stake of the `voters` casting a vote of the given type for `leader`. Steady-state
+    /// votes must reference the leader directly; fallback votes only need a path to it.
+    fn votes(
+        &self,
+        state: &ConsensusState,
+        leader: &Certificate,
+        vote_type: VoteType,
+        voters: &[&Certificate],
+    ) -> Stake {
+        let wave = Self::wave(leader.round());
+        voters
+            .iter()
+            .filter(|x| {
+                state
+                    .vote_types
+                    .get(&wave)
+                    .and_then(|types| types.get(&x.origin()))
+                    == Some(&vote_type)
+            })
+            .filter(|x| match vote_type {
+                VoteType::Steady => x.header.parents.contains(&leader.digest()),
+                VoteType::Fallback => utils::linked(x, leader, &state.dag),
+            })
+            .map(|x| self.committee.stake(&x.origin()))
+            .sum()
+    }
+
+    /// Computes the vote type of the author of a certificate of the first round of a wave. The
+    /// author votes steady-state if the causal history of that certificate commits the second
+    /// steady-state leader or the fallback leader of the previous wave, and fallback otherwise.
+    fn vote_type(&self, state: &ConsensusState, certificate: &Certificate) -> VoteType {
+        let wave = Self::wave(certificate.round());
+        if wave == 1 {
+            return VoteType::Steady;
+        }
+
+        let previous = wave - 1;
+        let voters = utils::ancestors_at(certificate, certificate.round() - 1, &state.dag);
+        let threshold = self.committee.quorum_threshold();
+
+        let steady = self
+            .leader(Self::first_round(previous) + 2, state)
+            .is_some_and(|(_, leader)| {
+                self.votes(state, leader, VoteType::Steady, &voters) >= threshold
+            });
+        let fallback = self
+            .fallback_leader(previous, state)
+            .is_some_and(|(_, leader)| {
+                self.votes(state, leader, VoteType::Fallback, &voters) >= threshold
+            });
+
+        if steady || fallback {
+            VoteType::Steady
+        } else {
+            VoteType::Fallback
+        }
+    }
+
+    /// Computes again the vote types of the waves still in the DAG (from the oldest), since they
+    /// depend on the leaders elected by the leader schedule.
+    fn update_vote_types(&self, state: &mut ConsensusState) {
+        let mut waves: Vec<_> = state
+            .dag
+            .keys()
+            .filter(|r| *r % Self::WAVE_LENGTH == 1)
+            .map(|r| Self::wave(*r))
+            .collect();
+        waves.sort_unstable();
+
+        for wave in waves {
+            let types: Vec<_> = state.dag[&Self::first_round(wave)]
+                .values()
+                .map(|(_, x)| (x.origin(), self.vote_type(state, x)))
+                .collect();
+            state.vote_types.entry(wave).or_default().extend(types);
+        }
+    }
+
+    /// Returns the highest uncommitted leader of the waves of `round` and of the previous wave
+    /// that gathered 2f+1 votes of its type.
+    fn committable_leader(&self, state: &ConsensusState, round: Round) -> Option<Certificate> {
+        let threshold = self.committee.quorum_threshold();
+        let wave = Self::wave(round);
+
+        for w in (wave.saturating_sub(1).max(1)..=wave).rev() {
+            let first = Self::first_round(w);
+            let candidates = [
+                (self.leader(first + 2, state), VoteType::Steady),
+                (self.fallback_leader(w, state), VoteType::Fallback),
+                (self.leader(first, state), VoteType::Steady),
+            ];
+
+            for (leader, vote_type) in candidates {
+                let leader = match leader {
+                    Some((_, x)) if x.round() > state.last_committed_round => x,
+                    _ => continue,
+                };
+                let voters: Vec<_> = state
+                    .dag
+                    .get(&Self::vote_round(leader.round(), vote_type))
+                    .map(|x| x.values().map(|(_, certificate)| certificate).collect())
+                    .unwrap_or_default();
+
+                let stake = self.votes(state, leader, vote_type, &voters);
+                if stake >= threshold {
+                    debug!(
+                        "Leader at round {} has {} {:?} votes",
+                        leader.round(),
+                        stake,
+                        vote_type
+                    );
+                    return Some(leader.clone());
+                }
+            }
+        }
+        None
+    }
+
+    /// Checks whether the causal history of `leader` holds f+1 votes of the right type for
+    /// `candidate`, a leader of a previous round. Any leader committed by another node has such
+    /// support, so it is ordered by everyone.
+    fn supported(
+        &self,
+        state: &ConsensusState,
+        leader: &Certificate,
+        candidate: &Certificate,
+    ) -> bool {
+        let threshold = self.committee.validity_threshold();
+        [VoteType::Steady, VoteType::Fallback]
+            .into_iter()
+            .filter(|vote_type| self.is_leader(candidate, *vote_type, state))
+            .any(|vote_type| {
+                let vote_round = Self::vote_round(candidate.round(), vote_type);
+                let voters = utils::ancestors_at(leader, vote_round, &state.dag);
+                self.votes(state, candidate, vote_type, &voters) >= threshold
+            })
     }
 }
 
@@ -425,78 +1082,71 @@ impl ConsensusAlgorithm for Bullshark {
         state: &mut ConsensusState,
         certificate: Certificate,
         metrics: &mut ConsensusMetrics,
-    ) -> Result<(Vec<Certificate>, bool), ConsensusError> {
+    ) -> Result<(Vec<CommittedSubDag>, bool), ConsensusError> {
         let round = certificate.round();
+        let origin = certificate.origin();
         metrics.total_certificates_processed += 1;
 
+        // The certificate of the first round of a wave fixes the vote type of its author.
+        if round % Self::WAVE_LENGTH == 1 {
+            let vote_type = self.vote_type(state, &certificate);
+            debug!(
+                "{} casts {:?} votes in wave {}",
+                origin,
+                vote_type,
+                Self::wave(round)
+            );
+            state
+                .vote_types
+                .entry(Self::wave(round))
+                .or_default()
+                .insert(origin, vote_type);
+        }
+
         // Add to DAG
         state
             .dag
             .entry(round)
             .or_insert_with(HashMap::new)
-            .insert(certificate.origin(), (certificate.digest(), certificate));
-
-        // Bullshark commits leaders every 2 rounds (vs Tusk's 4)
-        let r = round - 1;
-        if r % 2 != 0 || r < 2 {
-            return Ok((Vec::new(), false));
-        }
-
-        let leader_round = r;
-        if leader_round <= state.last_committed_round {
-            return Ok((Vec::new(), false));
-        }
+            .insert(origin, (certificate.digest(), certificate));
 
-        debug!("Checking for leader at round {}", leader_round);
-        let (leader_digest, leader) = match self.leader(leader_round, &state.dag) {
-            Some(x) => {
-                debug!("Found leader {:?} at round {}", x.1.digest(), leader_round);
-                x.clone()
-            }
-            None => {
-                metrics.failed_leader_elections += 1;
-                debug!("No leader found at round {}", leader_round);
-                return Ok((Vec::new(), false));
-            }
-        };
-
-        // Check support from current round (2f+1 votes required)
-        let stake: Stake = state
-            .dag
-            .get(&round)
-            .ok_or(ConsensusError::MissingRound(round))?
-            .values()
-            .filter(|(_, x)| x.header.parents.contains(&leader_digest))
-            .map(|(_, x)| self.committee.stake(&x.origin()))
-            .sum();
-
-        let required_stake = self.committee.validity_threshold();
-
-        if stake < required_stake {
-            debug!(
-                "Leader at round {} has insufficient stake ({}/{})",
-                leader_round, stake, required_stake
-            );
-            return Ok((Vec::new(), false));
+        // Count missing steady-state leaders once, when their votes start to arrive.
+        if round.is_multiple_of(2)
+            && state.dag.get(&round).map_or(0, |x| x.len()) == 1
+            && self.leader(round - 1, state).is_none()
+        {
+            metrics.failed_leader_elections += 1;
+            debug!("No leader found at round {}", round - 1);
         }
 
-        info!("Committing leader at round {} with stake {}/{}", leader_round, stake, required_stake);
-
-        // Order and commit
+        // Order and commit, electing the leaders again whenever the leader schedule changes.
         let mut sequence = Vec::new();
-        for leader_cert in utils::order_leaders(&leader, state, |r, d| self.leader(r, d))
-            .iter()
-            .rev()
-        {
-            for x in utils::order_dag(self.gc_depth, leader_cert, state) {
-                state.update(&x, self.gc_depth);
-                sequence.push(x);
+        while let Some(leader) = self.committable_leader(state, round) {
+            info!("Committing leader at round {}", leader.round());
+
+            let leaders = utils::order_leaders(
+                &leader,
+                state,
+                |r, s| self.leaders(r, s),
+                |l, c| self.supported(state, l, c),
+            );
+            let schedule = &*self.schedule;
+            if !utils::commit_leaders(&leaders, state, schedule, self.gc_depth, &mut sequence) {
+                break;
             }
+            self.update_vote_types(state);
         }
 
+        // Keep the vote types and coins of the waves that may still be ordered.
+        let gc_round = state.last_committed_round.saturating_sub(self.gc_depth);
+        let gc_wave = Self::wave(gc_round);
+        state.vote_types.retain(|w, _| w + 1 >= gc_wave);
+        self.coin.cleanup(gc_round);
+
         let committed = !sequence.is_empty();
         if committed {
-            metrics.total_certificates_committed += sequence.len() as u64;
+            metrics.total_certificates_committed +=
+                sequence.iter().map(|x| x.len() as u64).sum::<u64>();
             metrics.last_committed_round = state.last_committed_round;
         }
 
@@ -519,7 +1169,7 @@ impl ConsensusAlgorithm for ConsensusProtocol {
         state: &mut ConsensusState,
         certificate: Certificate,
         metrics: &mut ConsensusMetrics,
-    ) -> Result<(Vec<Certificate>, bool), ConsensusError> {
+    ) -> Result<(Vec<CommittedSubDag>, bool), ConsensusError> {
         match self {
             ConsensusProtocol::Tusk(tusk) => {
                 tusk.process_certificate(state, certificate, metrics)
@@ -543,11 +1193,15 @@ impl ConsensusAlgorithm for ConsensusProtocol {
 // ====================
 
 pub struct Consensus {
+    /// The consensus state (see `CommitRecord`).
     store: Store,
+    /// The certificates the primary stored, by round (to rebuild the DAG).
+    certificate_index: CertificateIndex,
     rx_primary: Receiver<Certificate>,
     tx_primary: Sender<Certificate>,
-    tx_output: Sender<Certificate>,
+    tx_output: Sender<CommittedSubDag>,
     protocol: Box<dyn ConsensusAlgorithm>,
+    gc_depth: Round,
     genesis: Vec<Certificate>,
     metrics: Arc<RwLock<ConsensusMetrics>>,
 }
@@ -561,7 +1215,7 @@ impl Consensus {
         store: Store,
         rx_primary: Receiver<Certificate>,
         tx_primary: Sender<Certificate>,
-        tx_output: Sender<Certificate>,
+        tx_output: Sender<CommittedSubDag>,
         protocol_selection: ConsensusProtocol,
     ) -> Arc<RwLock<ConsensusMetrics>> {
         let protocol: Box<dyn ConsensusAlgorithm> = match protocol_selection {
@@ -576,11 +1230,13 @@ impl Consensus {
 
         tokio::spawn(async move {
             Self {
-                store,
+                store: store.column(CONSENSUS),
+                certificate_index: CertificateIndex::new(&store),
                 rx_primary,
                 tx_primary,
                 tx_output,
                 protocol,
+                gc_depth,
                 genesis: Certificate::genesis(&committee),
                 metrics: metrics_clone,
             }
@@ -592,36 +1248,80 @@ impl Consensus {
     }
 
     async fn load_state(&mut self) -> ConsensusState {
-        match self.store.read(Self::STATE_KEY.to_vec()).await {
-            Ok(Some(bytes)) => {
-                match bincode::deserialize(&bytes) {
-                    Ok(state) => {
-                        info!("Loaded consensus state from store");
-                        state
-                    }
-                    Err(e) => {
-                        error!("Failed to deserialize consensus state: {}. Starting from genesis.", e);
-                        ConsensusState::new(self.genesis.clone())
-                    }
+        let record = match self.store.read(Self::STATE_KEY.to_vec()).await {
+            Ok(Some(bytes)) => match bincode::deserialize::<CommitRecord>(&bytes) {
+                Ok(record) => record,
+                Err(e) => {
+                    error!("Failed to deserialize consensus state: {}. Starting from genesis.", e);
+                    return ConsensusState::new(self.genesis.clone());
                 }
-            }
+            },
             Ok(None) => {
                 info!("No consensus state found in store. Starting from genesis.");
-                ConsensusState::new(self.genesis.clone())
+                return ConsensusState::new(self.genesis.clone());
             }
             Err(e) => {
                 error!("Failed to read from store: {:?}. Starting from genesis.", e);
+                return ConsensusState::new(self.genesis.clone());
+            }
+        };
+
+        match self.rebuild_dag(&record).await {
+            Ok(dag) => {
+                info!(
+                    "Loaded consensus state from store (commit {}, {} certificates in the DAG)",
+                    record.commit_index,
+                    dag.values().map(|x| x.len()).sum::<usize>()
+                );
+                ConsensusState::from_record(record, dag)
+            }
+            Err(e) => {
+                error!("Failed to rebuild the DAG: {}. Starting from genesis.", e);
                 ConsensusState::new(self.genesis.clone())
             }
         }
     }
 
+    /// Rebuilds the DAG of a record from all the certificates the primary stored since the garbage
+    /// collection round, whether or not later certificates reference them yet.
+    async fn rebuild_dag(&mut self, record: &CommitRecord) -> Result<Dag, ConsensusError> {
+        let gc_round = record.last_committed_round.saturating_sub(self.gc_depth);
+        let certificates = self
+            .certificate_index
+            .rounds(gc_round..=Round::MAX)
+            .await
+            .map_err(|e| ConsensusError::StoreError(e.to_string()))?;
+
+        let mut dag = Dag::new();
+        for certificate in self.genesis.iter().cloned().chain(certificates) {
+            if certificate.round() < gc_round {
+                continue;
+            }
+
+            // Skip the certificates that the garbage collector already removed.
+            let last_committed = record.last_committed.get(&certificate.origin());
+            if last_committed.is_some_and(|r| &certificate.round() < r) {
+                continue;
+            }
+            dag.entry(certificate.round())
+                .or_default()
+                .insert(certificate.origin(), (certificate.digest(), certificate));
+        }
+        Ok(dag)
+    }
+
+    /// Persists the state, once its sub-DAGs are output, along with the last committed round (read
+    /// by the primary) in a single (atomic) write.
     async fn save_state(&mut self, state: &ConsensusState) -> Result<(), ConsensusError> {
-        let serialized = bincode::serialize(state)?;
+        let serialized = bincode::serialize(&state.record())?;
+        let round = bincode::serialize(&state.last_committed_round)?;
+        let mut batch = WriteBatch::default();
+        batch.put(&self.store, Self::STATE_KEY.to_vec(), serialized);
+        batch.put(&self.store, LAST_COMMITTED_ROUND_KEY.to_vec(), round);
         self.store
-            .write(Self::STATE_KEY.to_vec(), serialized)
-            .await;
-        Ok(())
+            .write_all(batch)
+            .await
+            .map_err(|e| ConsensusError::StoreError(e.to_string()))
     }
 
     async fn run(&mut self) {
@@ -644,13 +1344,6 @@ impl Consensus {
                 Ok((sequence, committed)) => {
                     drop(metrics); // Release lock before I/O operations
 
-                    // Persist state if committed
-                    if committed {
-                        if let Err(e) = self.save_state(&state).await {
-                            error!("Failed to save state: {}", e);
-                        }
-                    }
-
                     // Log commit status
                     if log_enabled!(log::Level::Debug) {
                         for (name, round) in &state.last_committed {
@@ -658,25 +1351,39 @@ impl Consensus {
                         }
                     }
 
-                    // Output committed certificates
-                    for certificate in sequence {
-                        #[cfg(not(feature = "benchmark"))]
-                        info!("Committed {}", certificate.header);
-
-                        #[cfg(feature = "benchmark")]
-                        for digest in certificate.header.payload.keys() {
-                            // NOTE: This log entry is used to compute performance.
-                            info!("Committed {} -> {:?}", certificate.header, digest);
+                    // Output committed sub-DAGs
+                    for sub_dag in sequence {
+                        debug!(
+                            "Committed sub-DAG {} of leader {}",
+                            sub_dag.commit_index, sub_dag.leader.header
+                        );
+                        for certificate in &sub_dag.certificates {
+                            #[cfg(not(feature = "benchmark"))]
+                            info!("Committed {}", certificate.header);
+
+                            #[cfg(feature = "benchmark")]
+                            for digest in certificate.header.payload.keys() {
+                                // NOTE: This log entry is used to compute performance.
+                                info!("Committed {} -> {:?}", certificate.header, digest);
+                            }
+
+                            // Send to primary
+                            if let Err(e) = self.tx_primary.send(certificate.clone()).await {
+                                error!("Failed to send certificate to primary: {}", e);
+                            }
                         }
 
-                        // Send to primary
-                        if let Err(e) = self.tx_primary.send(certificate.clone()).await {
-                            error!("Failed to send certificate to primary: {}", e);
+                        // Send to output
+                        if let Err(e) = self.tx_output.send(sub_dag).await {
+                            warn!("Failed to output sub-DAG: {}", e);
                         }
+                    }
 
-                        // Send to output
-                        if let Err(e) = self.tx_output.send(certificate).await {
-                            warn!("Failed to output certificate: {}", e);
+                    // Persist the state only once its sub-DAGs are output: a crash in between
+                    // makes the restarted consensus commit (and output) them again.
+                    if committed {
+                        if let Err(e) = self.save_state(&state).await {
+                            error!("Failed to save state: {}", e);
                         }
                     }
                 }
@@ -721,10 +1428,36 @@ mod tests {
     fn test_bullshark_leader_deterministic() {
         // Test that leader selection is deterministic
         // All nodes should compute the same leader for the same round
+        let a = consensus_tests::round_robin_bullshark();
+        let b = consensus_tests::round_robin_bullshark();
+        let state = ConsensusState::new(vec![]);
+        for round in 1..=20 {
+            assert_eq!(
+                a.steady_leader_name(round, &state),
+                b.steady_leader_name(round, &state)
+            );
+        }
+        for wave in 1..=20 {
+            assert_eq!(
+                a.fallback_leader_name(wave, &state),
+                b.fallback_leader_name(wave, &state)
+            );
+        }
     }
 
     #[test]
     fn test_bullshark_leader_rotation() {
         // Test that leaders rotate fairly across all validators
+        let size = consensus_tests::mock_committee().size();
+        let bullshark = consensus_tests::round_robin_bullshark();
+        let state = ConsensusState::new(vec![]);
+        let leaders: HashSet<_> = (1..=size as Round)
+            .map(|round| bullshark.steady_leader_name(round, &state))
+            .collect();
+        assert_eq!(leaders.len(), size);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+#[path = "tests/consensus_tests.rs"]
+pub mod consensus_tests;
\ No newline at end of file
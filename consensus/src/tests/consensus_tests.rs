@@ -1,12 +1,12 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use config::{Authority, PrimaryAddresses};
-use crypto::{generate_keypair, SecretKey};
+use config::{Authority, CoinKeys, PrimaryAddresses};
+use crypto::{deal_threshold_keys, generate_keypair, SecretKey, ThresholdSecretKey};
 use primary::Header;
 use rand::rngs::StdRng;
 use rand::SeedableRng as _;
 use std::collections::{BTreeSet, VecDeque};
-use store::Store; // Thêm import cho Store
+use store::{Store, Table, CERTIFICATES}; // Thêm import cho Store
 use tokio::sync::mpsc::channel;
 
 // Fixture
@@ -80,6 +80,77 @@ fn make_certificates(
     (certificates, next_parents)
 }
 
+// Stores and indexes certificates by round, as the primary does before delivering them.
+async fn store_certificates<'a>(
+    store: &mut Store,
+    certificates: impl IntoIterator<Item = &'a Certificate>,
+) {
+    let table: Table<Digest, Certificate> = Table::new(store, CERTIFICATES);
+    let index = CertificateIndex::new(store);
+    let mut batch = WriteBatch::default();
+    for certificate in certificates {
+        batch.insert(&table, &certificate.digest(), certificate);
+        index.insert(&mut batch, certificate);
+    }
+    store.write_all(batch).await.unwrap();
+}
+
+// Spawns a Tusk consensus (with a round-robin schedule) on the store, sinking the primary channel.
+fn spawn_tusk(store: Store) -> (Sender<Certificate>, Receiver<CommittedSubDag>) {
+    let (tx_waiter, rx_waiter) = channel(1);
+    let (tx_primary, mut rx_primary) = channel(1);
+    let (tx_output, rx_output) = channel(1);
+    Consensus::spawn(
+        mock_committee(),
+        /* gc_depth */ 50,
+        store,
+        rx_waiter,
+        tx_primary,
+        tx_output,
+        ConsensusProtocol::Tusk(Tusk::new(
+            mock_committee(),
+            /* gc_depth */ 50,
+            None,
+            Box::new(RoundRobin::new(&mock_committee())),
+        )),
+    );
+    tokio::spawn(async move { while rx_primary.recv().await.is_some() {} });
+    (tx_waiter, rx_output)
+}
+
+// Elects the same authority in every round, whatever the coin, for the tests that need to know the
+// leaders in advance.
+struct FixedLeader(PublicKey);
+
+impl LeaderSchedule for FixedLeader {
+    fn leader(&self, _state: &ConsensusState, _round: Round, _seed: u64) -> PublicKey {
+        self.0
+    }
+
+    fn update(
+        &self,
+        _state: &mut ConsensusState,
+        _leader: &Certificate,
+        _sub_dag: &[Certificate],
+    ) -> bool {
+        false
+    }
+}
+
+// Receives committed sub-DAGs until `n` certificates are committed, and returns those certificates.
+async fn committed_certificates(
+    rx_output: &mut Receiver<CommittedSubDag>,
+    n: usize,
+) -> VecDeque<Certificate> {
+    let mut certificates = VecDeque::new();
+    while certificates.len() < n {
+        let sub_dag = rx_output.recv().await.unwrap();
+        assert_eq!(sub_dag.leader, *sub_dag.certificates.last().unwrap());
+        certificates.extend(sub_dag.certificates);
+    }
+    certificates
+}
+
 // Run for 4 dag rounds in ideal conditions (all nodes reference all other nodes). We should commit
 // the leader of round 2.
 #[tokio::test]
@@ -102,9 +173,7 @@ async fn commit_one() {
     let (tx_output, mut rx_output) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_commit_one";
-    let _ = std::fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     Consensus::spawn(
         mock_committee(),
@@ -113,6 +182,12 @@ async fn commit_one() {
         rx_waiter,
         tx_primary,
         tx_output,
+        ConsensusProtocol::Tusk(Tusk::new(
+            mock_committee(),
+            /* gc_depth */ 50,
+            None,
+            Box::new(RoundRobin::new(&mock_committee())),
+        )),
     );
     tokio::spawn(async move { while rx_primary.recv().await.is_some() {} });
 
@@ -124,12 +199,14 @@ async fn commit_one() {
 
     // Ensure the first 4 ordered certificates are from round 1 (they are the parents of the committed
     // leader); then the leader's certificate should be committed.
-    for _ in 1..=4 {
-        let certificate = rx_output.recv().await.unwrap();
+    let sub_dag = rx_output.recv().await.unwrap();
+    assert_eq!(sub_dag.commit_index, 1);
+    assert_eq!(sub_dag.leader_round, 2);
+    assert_eq!(sub_dag.len(), 5);
+    for certificate in &sub_dag.certificates[..4] {
         assert_eq!(certificate.round(), 1);
     }
-    let certificate = rx_output.recv().await.unwrap();
-    assert_eq!(certificate.round(), 2);
+    assert_eq!(sub_dag.certificates[4], sub_dag.leader);
 }
 
 // Run for 8 dag rounds with one dead node node (that is not a leader). We should commit the leaders of
@@ -154,9 +231,7 @@ async fn dead_node() {
     let (tx_output, mut rx_output) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_dead_node";
-    let _ = std::fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     Consensus::spawn(
         mock_committee(),
@@ -165,6 +240,12 @@ async fn dead_node() {
         rx_waiter,
         tx_primary,
         tx_output,
+        ConsensusProtocol::Tusk(Tusk::new(
+            mock_committee(),
+            /* gc_depth */ 50,
+            None,
+            Box::new(FixedLeader(keys[0])),
+        )),
     );
     tokio::spawn(async move { while rx_primary.recv().await.is_some() {} });
 
@@ -176,12 +257,13 @@ async fn dead_node() {
     });
 
     // We should commit 3 leaders (rounds 2, 4, and 6).
+    let mut committed = committed_certificates(&mut rx_output, 16).await;
     for i in 1..=15 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         let expected = ((i - 1) / keys.len() as u64) + 1;
         assert_eq!(certificate.round(), expected);
     }
-    let certificate = rx_output.recv().await.unwrap();
+    let certificate = committed.pop_front().unwrap();
     assert_eq!(certificate.round(), 6);
 }
 
@@ -249,9 +331,7 @@ async fn not_enough_support() {
     let (tx_output, mut rx_output) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_not_enough_support";
-    let _ = std::fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     Consensus::spawn(
         mock_committee(),
@@ -260,6 +340,12 @@ async fn not_enough_support() {
         rx_waiter,
         tx_primary,
         tx_output,
+        ConsensusProtocol::Tusk(Tusk::new(
+            mock_committee(),
+            /* gc_depth */ 50,
+            None,
+            Box::new(FixedLeader(keys[0])),
+        )),
     );
     tokio::spawn(async move { while rx_primary.recv().await.is_some() {} });
 
@@ -270,19 +356,20 @@ async fn not_enough_support() {
     }
 
     // We should commit 2 leaders (rounds 2 and 4).
+    let mut committed = committed_certificates(&mut rx_output, 11).await;
     for _ in 1..=3 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 1);
     }
     for _ in 1..=4 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 2);
     }
     for _ in 1..=3 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 3);
     }
-    let certificate = rx_output.recv().await.unwrap();
+    let certificate = committed.pop_front().unwrap();
     assert_eq!(certificate.round(), 4);
 }
 
@@ -319,9 +406,7 @@ async fn missing_leader() {
     let (tx_output, mut rx_output) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_missing_leader";
-    let _ = std::fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     Consensus::spawn(
         mock_committee(),
@@ -330,6 +415,12 @@ async fn missing_leader() {
         rx_waiter,
         tx_primary,
         tx_output,
+        ConsensusProtocol::Tusk(Tusk::new(
+            mock_committee(),
+            /* gc_depth */ 50,
+            None,
+            Box::new(FixedLeader(keys[0])),
+        )),
     );
     tokio::spawn(async move { while rx_primary.recv().await.is_some() {} });
 
@@ -340,18 +431,594 @@ async fn missing_leader() {
     }
 
     // Ensure the commit sequence is as expected.
+    let mut committed = committed_certificates(&mut rx_output, 11).await;
     for _ in 1..=3 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 1);
     }
     for _ in 1..=3 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
+        assert_eq!(certificate.round(), 2);
+    }
+    for _ in 1..=4 {
+        let certificate = committed.pop_front().unwrap();
+        assert_eq!(certificate.round(), 3);
+    }
+    let certificate = committed.pop_front().unwrap();
+    assert_eq!(certificate.round(), 4);
+}
+
+// Commit the leader of round 2 (as in `commit_one`), then restart the consensus on the same store
+// and commit the leader of round 4. The restarted consensus should rebuild its DAG from the
+// certificates in the store and only output the certificates that were not committed yet.
+#[tokio::test]
+async fn restart_from_store() {
+    let keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    let genesis = Certificate::genesis(&mock_committee())
+        .iter()
+        .map(|x| x.digest())
+        .collect::<BTreeSet<_>>();
+    let (certificates, parents) = make_certificates(1, 4, &genesis, &keys);
+    let (more_certificates, next_parents) = make_certificates(5, 6, &parents, &keys);
+    let (_, trigger) = mock_certificate(keys[0], 7, next_parents);
+
+    // Create a new test store, holding all certificates (as the primary would).
+    let mut store = Store::new_in_memory();
+    store_certificates(&mut store, certificates.iter().chain(more_certificates.iter())).await;
+
+    // Commit the leader of round 2.
+    let (tx_waiter, mut rx_output) = spawn_tusk(store.clone());
+    for certificate in certificates {
+        tx_waiter.send(certificate).await.unwrap();
+    }
+    tx_waiter.send(more_certificates[0].clone()).await.unwrap();
+    let sub_dag = rx_output.recv().await.unwrap();
+    assert_eq!(sub_dag.commit_index, 1);
+    assert_eq!(sub_dag.len(), 5);
+
+    // The state is persisted once the sub-DAG is output.
+    let mut state = store.column(CONSENSUS);
+    let bytes = state.notify_read(b"consensus_state".to_vec()).await.unwrap();
+    let record: CommitRecord = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(record.commit_index, 1);
+    assert_eq!(record.last_committed_round, 2);
+    let bytes = state.read(LAST_COMMITTED_ROUND_KEY.to_vec()).await.unwrap().unwrap();
+    assert_eq!(bincode::deserialize::<Round>(&bytes).unwrap(), 2);
+
+    // Restart and commit the leader of round 4.
+    let (tx_waiter, mut rx_output) = spawn_tusk(store);
+    for certificate in more_certificates.into_iter().skip(1) {
+        tx_waiter.send(certificate).await.unwrap();
+    }
+    tx_waiter.send(trigger).await.unwrap();
+
+    // The commit index carries on from the previous run.
+    let sub_dag = rx_output.recv().await.unwrap();
+    assert_eq!(sub_dag.commit_index, 2);
+    assert_eq!(sub_dag.leader_round, 4);
+    let mut committed: VecDeque<_> = sub_dag.certificates.into_iter().collect();
+    assert_eq!(committed.len(), 8);
+    for _ in 1..=3 {
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 2);
     }
     for _ in 1..=4 {
-        let certificate = rx_output.recv().await.unwrap();
+        let certificate = committed.pop_front().unwrap();
         assert_eq!(certificate.round(), 3);
     }
-    let certificate = rx_output.recv().await.unwrap();
+    let certificate = committed.pop_front().unwrap();
     assert_eq!(certificate.round(), 4);
-}
\ No newline at end of file
+}
+
+// Restart the consensus while its DAG holds a certificate that no later certificate references yet
+// (the round-4 certificate of one authority, whose round-5 certificate skips it). The restarted
+// consensus should still have it, and commit it once a later certificate references it.
+#[tokio::test]
+async fn restart_with_unreferenced_certificate() {
+    let keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    let genesis = Certificate::genesis(&mock_committee())
+        .iter()
+        .map(|x| x.digest())
+        .collect::<BTreeSet<_>>();
+    let (mut certificates, parents) = make_certificates(1, 4, &genesis, &keys);
+
+    // The round-5 certificates skip the round-4 certificate of `keys[2]`, except the one of
+    // `keys[1]` (delivered after the restart).
+    let orphan = certificates
+        .iter()
+        .find(|x| x.round() == 4 && x.origin() == keys[2])
+        .unwrap()
+        .digest();
+    let skipping: BTreeSet<_> = parents.iter().filter(|x| *x != &orphan).cloned().collect();
+    let round_5: Vec<_> = keys
+        .iter()
+        .map(|name| match name == &keys[1] {
+            true => mock_certificate(*name, 5, parents.clone()),
+            false => mock_certificate(*name, 5, skipping.clone()),
+        })
+        .collect();
+    let round_5_digests = round_5.iter().map(|(x, _)| x.clone()).collect();
+    let (later, next_parents) = make_certificates(6, 8, &round_5_digests, &keys);
+    let (_, trigger) = mock_certificate(keys[0], 9, next_parents);
+
+    // Create a new test store, holding all certificates (as the primary would).
+    let mut store = Store::new_in_memory();
+    let round_5: Vec<_> = round_5.into_iter().map(|(_, x)| x).collect();
+    let all = certificates.iter().chain(round_5.iter()).chain(later.iter());
+    store_certificates(&mut store, all).await;
+
+    // Deliver rounds 1 to 4 and the round-5 certificates of `keys[0]` and `keys[2]`: this
+    // commits the leader of round 2, and the orphan is only in the DAG.
+    let (tx_waiter, mut rx_output) = spawn_tusk(store.clone());
+    while let Some(certificate) = certificates.pop_front() {
+        tx_waiter.send(certificate).await.unwrap();
+    }
+    tx_waiter.send(round_5[0].clone()).await.unwrap();
+    tx_waiter.send(round_5[2].clone()).await.unwrap();
+    let sub_dag = rx_output.recv().await.unwrap();
+    assert_eq!(sub_dag.leader_round, 2);
+    assert!(sub_dag.certificates.iter().all(|x| x.digest() != orphan));
+
+    // Restart, deliver the rest of the DAG, and commit up to the leader of round 6.
+    let (tx_waiter, mut rx_output) = spawn_tusk(store);
+    tokio::spawn(async move {
+        let rest = [round_5[1].clone(), round_5[3].clone()];
+        for certificate in rest.into_iter().chain(later).chain([trigger]) {
+            tx_waiter.send(certificate).await.unwrap();
+        }
+    });
+    let mut committed = Vec::new();
+    loop {
+        let sub_dag = rx_output.recv().await.unwrap();
+        committed.extend(sub_dag.certificates.iter().map(|x| x.digest()));
+        if sub_dag.leader_round == 6 {
+            break;
+        }
+    }
+
+    // The orphan got committed (exactly once) along with the sub-DAG referencing it.
+    assert_eq!(committed.iter().filter(|x| *x == &orphan).count(), 1);
+}
+
+pub fn round_robin_bullshark() -> Bullshark {
+    Bullshark::new(
+        mock_committee(),
+        /* gc_depth */ 50,
+        None,
+        Box::new(RoundRobin::new(&mock_committee())),
+    )
+}
+
+// Feed certificates to Bullshark in order and return everything it commits.
+fn run_bullshark(
+    bullshark: &Bullshark,
+    state: &mut ConsensusState,
+    certificates: VecDeque<Certificate>,
+) -> Vec<Certificate> {
+    let mut metrics = ConsensusMetrics::default();
+    let mut committed = Vec::new();
+    for certificate in certificates {
+        let (sequence, _) = bullshark
+            .process_certificate(state, certificate, &mut metrics)
+            .unwrap();
+        committed.extend(sequence.into_iter().flat_map(|x| x.certificates));
+    }
+    committed
+}
+
+// Run for 4 dag rounds in ideal conditions. Bullshark should commit both steady-state leaders of
+// the first wave (rounds 1 and 3).
+#[test]
+fn bullshark_commit_steady_leaders() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+    let (certificates, _) = make_certificates(1, 4, &genesis_digests, &keys);
+
+    let bullshark = round_robin_bullshark();
+    let mut state = ConsensusState::new(genesis);
+    let committed = run_bullshark(&bullshark, &mut state, certificates);
+
+    // The first leader is committed alone, then the causal history of the second leader.
+    let rounds: Vec<_> = committed.iter().map(|x| x.round()).collect();
+    assert_eq!(rounds, vec![1, 1, 1, 1, 2, 2, 2, 2, 3]);
+    assert_eq!(committed[0].origin(), keys[1]);
+    assert_eq!(committed[8].origin(), keys[3]);
+    assert_eq!(state.last_committed_round, 3);
+}
+
+// The steady-state leader of round 1 is only referenced by f+1 certificates of round 2, so it is
+// not committed directly. It is ordered when the leader of round 3 (which has f+1 steady-state
+// votes for it in its causal history) is committed.
+#[test]
+fn bullshark_not_enough_votes() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+
+    let mut certificates = VecDeque::new();
+    let (out, _) = make_certificates(1, 1, &genesis_digests, &keys);
+    let leader_digest = out
+        .iter()
+        .find(|x| x.origin() == keys[1])
+        .map(|x| x.digest())
+        .unwrap();
+    let others: BTreeSet<_> = out
+        .iter()
+        .filter(|x| x.origin() != keys[1])
+        .map(|x| x.digest())
+        .collect();
+    certificates.extend(out.iter().cloned());
+
+    // Round 2: only two nodes vote for the leader of round 1.
+    let all: BTreeSet<_> = out.iter().map(|x| x.digest()).collect();
+    let mut parents = BTreeSet::new();
+    for (i, name) in keys.iter().enumerate() {
+        let round_parents = if i < 2 { all.clone() } else { others.clone() };
+        let (digest, certificate) = mock_certificate(*name, 2, round_parents);
+        certificates.push_back(certificate);
+        parents.insert(digest);
+    }
+    assert!(!others.contains(&leader_digest));
+
+    // Rounds 3 and 4: fully connected graph.
+    let (out, _) = make_certificates(3, 4, &parents, &keys);
+    certificates.extend(out);
+
+    let bullshark = round_robin_bullshark();
+    let mut state = ConsensusState::new(genesis);
+    let committed = run_bullshark(&bullshark, &mut state, certificates);
+
+    let rounds: Vec<_> = committed.iter().map(|x| x.round()).collect();
+    assert_eq!(rounds, vec![1, 1, 1, 1, 2, 2, 2, 2, 3]);
+    assert_eq!(committed[0].origin(), keys[1]);
+    assert_eq!(committed[8].origin(), keys[3]);
+}
+
+// The second steady-state leader of wave 1 gets no votes, so every node votes fallback in wave 2.
+// The fallback leader of wave 2 is then committed at the end of the wave, and everyone goes back
+// to steady-state votes in wave 3.
+#[test]
+fn bullshark_fallback_leader() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+
+    // Rounds 1 to 3: fully connected graph.
+    let (mut certificates, parents) = make_certificates(1, 3, &genesis_digests, &keys);
+
+    // Round 4: nobody references the leader of round 3.
+    let leader_3 = certificates
+        .iter()
+        .find(|x| x.round() == 3 && x.origin() == keys[3])
+        .map(|x| x.digest())
+        .unwrap();
+    let parents: BTreeSet<_> = parents.into_iter().filter(|x| x != &leader_3).collect();
+
+    // Rounds 4 to 9: fully connected graph.
+    let (out, _) = make_certificates(4, 9, &parents, &keys);
+    certificates.extend(out);
+
+    let bullshark = round_robin_bullshark();
+    let mut state = ConsensusState::new(genesis);
+    let committed = run_bullshark(&bullshark, &mut state, certificates);
+
+    let votes = |wave| {
+        state.vote_types[&wave]
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(votes(2), vec![VoteType::Fallback; 4]);
+    assert_eq!(votes(3), vec![VoteType::Steady; 4]);
+
+    // The first leader of wave 1, then the causal history of the fallback leader of wave 2 (which
+    // does not include the leader of round 3).
+    let rounds: Vec<_> = committed.iter().map(|x| x.round()).collect();
+    assert_eq!(rounds, vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 4, 5]);
+    assert_eq!(
+        committed[15].origin(),
+        bullshark.fallback_leader_name(2, &state).unwrap()
+    );
+    assert!(committed.iter().all(|x| x.digest() != leader_3));
+}
+
+// Fixture
+fn coin_keys(names: &[PublicKey]) -> (CoinKeys, Vec<ThresholdSecretKey>) {
+    let mut rng = StdRng::from_seed([0; 32]);
+    let (public_key, shares) = deal_threshold_keys(&mut rng, names.len(), 2);
+    let keys = CoinKeys {
+        public_key,
+        shares: names
+            .iter()
+            .cloned()
+            .zip(shares.iter().map(|(x, _)| *x))
+            .collect(),
+        threshold: 2,
+    };
+    (keys, shares.into_iter().map(|(_, x)| x).collect())
+}
+
+// The coin of a round combines the shares of the headers of that round: it can only be flipped
+// once f+1 valid shares are in the DAG, and every set of f+1 shares gives the same value.
+#[test]
+fn flip_common_coin() {
+    let mut names: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    names.sort();
+    let (keys, secrets) = coin_keys(&names);
+    let round = 4;
+    let certificates: Vec<_> = names
+        .iter()
+        .zip(secrets.iter())
+        .map(|(name, secret)| Certificate {
+            header: Header {
+                author: *name,
+                round,
+                coin: Some(secret.sign(&Header::coin_message(round))),
+                ..Header::default()
+            },
+            ..Certificate::default()
+        })
+        .collect();
+    let dag_with = |certificates: &[Certificate]| -> Dag {
+        let certificates = certificates
+            .iter()
+            .map(|x| (x.origin(), (x.digest(), x.clone())))
+            .collect();
+        [(round, certificates)].iter().cloned().collect()
+    };
+
+    // A single share is not enough.
+    let coin = Coin::new(Some(keys.clone()));
+    assert!(coin.flip(round, &dag_with(&certificates[..1])).is_none());
+
+    // Any two shares give the same coin.
+    let first = coin.flip(round, &dag_with(&certificates[..2])).unwrap();
+    let coin = Coin::new(Some(keys.clone()));
+    let second = coin.flip(round, &dag_with(&certificates[2..])).unwrap();
+    assert_eq!(first, second);
+
+    // Invalid shares are ignored.
+    let mut bad = certificates[1].clone();
+    bad.header.coin = certificates[0].header.coin.clone();
+    let coin = Coin::new(Some(keys));
+    assert!(coin
+        .flip(round, &dag_with(&[certificates[0].clone(), bad]))
+        .is_none());
+}
+
+// Every authority attaches its coin share to its headers. Nodes receiving the certificates in
+// different orders should elect the same leaders, drawn from the threshold coin rather than from the
+// round alone.
+#[test]
+fn elect_leaders_with_common_coin() {
+    let mut names: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    names.sort();
+    let (keys, secrets) = coin_keys(&names);
+
+    // Make fully connected certificates for rounds 1 to 11 carrying coin shares.
+    let mut rounds = Vec::new();
+    let mut parents: BTreeSet<_> = Certificate::genesis(&mock_committee())
+        .iter()
+        .map(|x| x.digest())
+        .collect();
+    for round in 1..=11 {
+        let certificates: Vec<_> = names
+            .iter()
+            .zip(secrets.iter())
+            .map(|(name, secret)| Certificate {
+                header: Header {
+                    author: *name,
+                    round,
+                    parents: parents.clone(),
+                    coin: Some(secret.sign(&Header::coin_message(round))),
+                    ..Header::default()
+                },
+                ..Certificate::default()
+            })
+            .collect();
+        parents = certificates.iter().map(|x| x.digest()).collect();
+        rounds.push(certificates);
+    }
+
+    // Each node receives the certificates of a round in a different order.
+    let elections: Vec<Vec<_>> = (0..names.len())
+        .map(|node| {
+            let tusk = Tusk::new(
+                mock_committee(),
+                /* gc_depth */ 50,
+                Some(keys.clone()),
+                Box::new(RoundRobin::new(&mock_committee())),
+            );
+            let mut state = ConsensusState::new(Certificate::genesis(&mock_committee()));
+            let mut metrics = ConsensusMetrics::default();
+            let mut leaders = Vec::new();
+            for certificates in &rounds {
+                let mut certificates = certificates.clone();
+                certificates.rotate_left(node);
+                for certificate in certificates {
+                    let (sequence, _) = tusk
+                        .process_certificate(&mut state, certificate, &mut metrics)
+                        .unwrap();
+                    leaders.extend(sequence.iter().map(|x| (x.leader_round, x.leader.origin())));
+                }
+            }
+            leaders
+        })
+        .collect();
+
+    // All nodes elect the same leaders, for rounds 2 to 8.
+    let leaders = &elections[0];
+    let leader_rounds: Vec<_> = leaders.iter().map(|(round, _)| *round).collect();
+    assert_eq!(leader_rounds, vec![2, 4, 6, 8]);
+    assert!(elections.iter().all(|x| x == leaders));
+
+    // The leaders follow the threshold coin, not the coin derived from the round alone (which is
+    // all the nodes use without coin keys).
+    let schedule = RoundRobin::new(&mock_committee());
+    let state = ConsensusState::new(Certificate::genesis(&mock_committee()));
+    let dag: Dag = rounds
+        .iter()
+        .map(|x| {
+            let round = x[0].round();
+            let certificates = x.iter().map(|x| (x.origin(), (x.digest(), x.clone())));
+            (round, certificates.collect())
+        })
+        .collect();
+    let coin = Coin::new(Some(keys));
+    for (round, leader) in leaders {
+        let seed = coin.flip(round + 2, &dag).unwrap();
+        assert_eq!(*leader, schedule.leader(&state, *round, seed));
+    }
+    let predictable: Vec<_> = leaders
+        .iter()
+        .map(|(round, _)| schedule.leader(&state, *round, Coin::mix(round + 2)))
+        .collect();
+    let elected: Vec<_> = leaders.iter().map(|(_, leader)| *leader).collect();
+    assert_ne!(elected, predictable);
+}
+
+// Commit a sub-DAG in which nobody references the first authority. With a period of one commit,
+// the schedule swaps it out for the best scorer right away.
+#[test]
+fn reputation_schedule_swaps_out_low_scorers() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+    let (certificates, _) = make_certificates(1, 2, &genesis_digests, &keys[1..]);
+
+    let schedule = ReputationSchedule::new(mock_committee(), /* period */ 1);
+    let mut state = ConsensusState::new(genesis);
+    for certificate in &certificates {
+        state
+            .dag
+            .entry(certificate.round())
+            .or_default()
+            .insert(certificate.origin(), (certificate.digest(), certificate.clone()));
+    }
+    assert_eq!(schedule.leader(&state, 4, 4), keys[0]);
+
+    let sub_dag: Vec<_> = certificates.into_iter().collect();
+    let leader = sub_dag.last().unwrap().clone();
+    assert!(schedule.update(&mut state, &leader, &sub_dag));
+    assert_eq!(state.reputation.bad, vec![keys[0]]);
+    assert_eq!(state.reputation.good, vec![keys[3]]);
+    assert_eq!(schedule.leader(&state, 4, 4), keys[3]);
+    assert_eq!(schedule.leader(&state, 5, 5), keys[1]);
+
+    // The same scores do not change the schedule.
+    assert!(!schedule.update(&mut state, &leader, &sub_dag));
+}
+
+// Run Bullshark for 12 rounds while the first steady-state leader of every wave is crashed. The
+// round-robin schedule keeps electing it, the reputation schedule swaps it out after the first
+// commit.
+#[test]
+fn bullshark_reputation_schedule() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let live: Vec<_> = keys.iter().filter(|x| *x != &keys[1]).cloned().collect();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+    let (certificates, _) = make_certificates(1, 12, &genesis_digests, &live);
+
+    let run = |schedule: Box<dyn LeaderSchedule>| {
+        let bullshark = Bullshark::new(mock_committee(), 50, None, schedule);
+        let mut state = ConsensusState::new(genesis.clone());
+        let mut metrics = ConsensusMetrics::default();
+        let mut committed = Vec::new();
+        for certificate in certificates.clone() {
+            let (sequence, _) = bullshark
+                .process_certificate(&mut state, certificate, &mut metrics)
+                .unwrap();
+            committed.extend(sequence.into_iter().flat_map(|x| x.certificates));
+        }
+        (committed, metrics)
+    };
+
+    let (round_robin, metrics) = run(Box::new(RoundRobin::new(&mock_committee())));
+    assert_eq!(metrics.failed_leader_elections, 3);
+    assert_eq!(round_robin.last().unwrap().round(), 11);
+
+    let (reputation, metrics) = run(Box::new(ReputationSchedule::new(mock_committee(), 1)));
+    assert_eq!(metrics.failed_leader_elections, 1);
+    assert_eq!(reputation.last().unwrap().round(), 11);
+    assert!(reputation.iter().all(|x| x.origin() != keys[1]));
+}
+
+// A Byzantine authority (a leader every other wave) shows its certificates to some nodes only:
+// they are referenced by every honest certificate of the next round, by a single one, or by none.
+// A node receiving all of them and a node receiving only the referenced ones (as late as the DAG
+// allows, and with the honest certificates in another order) commit the same sequence, which
+// keeps growing and never includes the certificates nobody references.
+#[test]
+fn bullshark_byzantine_certificates() {
+    let mut keys: Vec<_> = keys().into_iter().map(|(x, _)| x).collect();
+    keys.sort();
+    let byzantine = keys[1];
+    let honest: Vec<_> = keys.iter().filter(|x| *x != &byzantine).cloned().collect();
+    let genesis = Certificate::genesis(&mock_committee());
+    let genesis_digests = genesis.iter().map(|x| x.digest()).collect::<BTreeSet<_>>();
+
+    let mut everything = VecDeque::new();
+    let mut referenced = VecDeque::new();
+    let mut orphans = BTreeSet::new();
+    let mut parents = genesis_digests;
+    let mut withheld: Option<Certificate> = None;
+    for round in 1..=20 {
+        let (_, certificate) = mock_certificate(byzantine, round, parents.clone());
+        everything.push_back(certificate.clone());
+
+        let mut next_parents = BTreeSet::new();
+        let mut certificates = Vec::new();
+        for (i, name) in honest.iter().enumerate() {
+            let mut round_parents = parents.clone();
+            if let Some(x) = &withheld {
+                let referencing = match x.round() % 3 {
+                    0 => true,
+                    1 => i == 0,
+                    _ => false,
+                };
+                if referencing {
+                    round_parents.insert(x.digest());
+                }
+            }
+            let (digest, certificate) = mock_certificate(*name, round, round_parents);
+            certificates.push(certificate);
+            next_parents.insert(digest);
+        }
+        everything.extend(certificates.iter().cloned());
+
+        // The second node gets the Byzantine certificate right before the first certificate
+        // referencing it (if any).
+        for certificate in certificates.into_iter().rev() {
+            if let Some(x) = &withheld {
+                if certificate.header.parents.contains(&x.digest()) {
+                    referenced.push_back(withheld.take().unwrap());
+                }
+            }
+            referenced.push_back(certificate);
+        }
+        if let Some(x) = withheld.take() {
+            orphans.insert(x.digest());
+        }
+        withheld = Some(certificate);
+        parents = next_parents;
+    }
+
+    let bullshark = round_robin_bullshark();
+    let mut state = ConsensusState::new(genesis.clone());
+    let first = run_bullshark(&bullshark, &mut state, everything);
+    let mut state = ConsensusState::new(genesis);
+    let second = run_bullshark(&bullshark, &mut state, referenced);
+
+    let digests = |x: &[Certificate]| x.iter().map(|x| x.digest()).collect::<Vec<_>>();
+    assert_eq!(digests(&first), digests(&second));
+    assert!(first.last().unwrap().round() >= 15);
+    assert!(first.iter().any(|x| x.origin() == byzantine));
+    assert!(first.iter().all(|x| !orphans.contains(&x.digest())));
+}
@@ -15,6 +15,12 @@ use tokio::sync::oneshot;
 #[path = "tests/crypto_tests.rs"]
 pub mod crypto_tests;
 
+mod threshold;
+
+pub use crate::threshold::{
+    deal_threshold_keys, SignatureShare, ThresholdPublicKey, ThresholdSecretKey, ThresholdSignature,
+};
+
 pub type CryptoError = ed25519::Error;
 
 /// Represents a hash digest (32 bytes).
@@ -174,6 +180,16 @@ where
     (public, secret)
 }
 
+pub fn deal_production_threshold_keys(
+    shares: usize,
+    threshold: usize,
+) -> (
+    ThresholdPublicKey,
+    Vec<(ThresholdPublicKey, ThresholdSecretKey)>,
+) {
+    deal_threshold_keys(&mut OsRng, shares, threshold)
+}
+
 /// Represents an ed25519 signature.
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct Signature {
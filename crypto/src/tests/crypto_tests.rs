@@ -130,3 +130,69 @@ async fn signature_service() {
     // Verify the signature we received.
     assert!(signature.verify(&digest, &public_key).is_ok());
 }
+
+pub fn threshold_keys() -> (
+    ThresholdPublicKey,
+    Vec<(ThresholdPublicKey, ThresholdSecretKey)>,
+) {
+    let mut rng = StdRng::from_seed([0; 32]);
+    deal_threshold_keys(&mut rng, 4, 2)
+}
+
+#[test]
+fn import_export_threshold_keys() {
+    let (public_key, mut shares) = threshold_keys();
+    let export = public_key.encode_base64();
+    let import = ThresholdPublicKey::decode_base64(&export);
+    assert_eq!(import.unwrap(), public_key);
+
+    let (public_share, secret_share) = shares.pop().unwrap();
+    let export = secret_share.encode_base64();
+    let import = ThresholdSecretKey::decode_base64(&export).unwrap();
+    let message: &[u8] = b"Hello, world!";
+    assert!(import.sign(message).verify(message, &public_share).is_ok());
+}
+
+#[test]
+fn verify_signature_shares() {
+    let (_, shares) = threshold_keys();
+    let message: &[u8] = b"Hello, world!";
+    let (public_share, secret_share) = &shares[0];
+    let share = secret_share.sign(message);
+    assert!(share.verify(message, public_share).is_ok());
+
+    // The share does not verify against another message or another share's key.
+    let bad_message: &[u8] = b"Bad message!";
+    assert!(share.verify(bad_message, public_share).is_err());
+    assert!(share.verify(message, &shares[1].0).is_err());
+}
+
+#[test]
+fn combine_threshold_signature() {
+    let (public_key, shares) = threshold_keys();
+    let message: &[u8] = b"Hello, world!";
+    let signed: Vec<_> = shares
+        .iter()
+        .enumerate()
+        .map(|(i, (_, secret))| (i, secret.sign(message)))
+        .collect();
+
+    // Any two shares combine into the same valid signature.
+    let first = ThresholdSignature::combine(&signed[0..2]).unwrap();
+    let second = ThresholdSignature::combine(&signed[2..4]).unwrap();
+    assert!(first.verify(message, &public_key).is_ok());
+    assert_eq!(first, second);
+
+    // A single share is not enough.
+    let single = ThresholdSignature::combine(&signed[0..1]).unwrap();
+    assert!(single.verify(message, &public_key).is_err());
+}
+
+#[test]
+fn combine_duplicate_shares() {
+    let (_, shares) = threshold_keys();
+    let message: &[u8] = b"Hello, world!";
+    let share = shares[0].1.sign(message);
+    let signed = vec![(0, share.clone()), (0, share)];
+    assert!(ThresholdSignature::combine(&signed).is_err());
+}
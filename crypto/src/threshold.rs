@@ -0,0 +1,276 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::{CryptoError, Digest, Hash};
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use rand::{CryptoRng, RngCore};
+use serde::{de, ser, Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt;
+
+/// Domain separation tag of the messages signed with the threshold scheme.
+const DST: &[u8] = b"NARWHAL-THRESHOLD-BLS12381G1_XMD:SHA-512_SSWU_RO_";
+
+fn hash_to_g1(message: &[u8]) -> G1Affine {
+    <G1Projective as HashToCurve<ExpandMsgXmd<Sha512>>>::hash_to_curve(message, DST).into()
+}
+
+/// Represents a public key of the BLS threshold scheme (a point of G2). It is either the public
+/// key of the whole scheme or the public key of a single share.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct ThresholdPublicKey(G2Affine);
+
+impl ThresholdPublicKey {
+    pub fn encode_base64(&self) -> String {
+        base64::encode(&self.0.to_compressed()[..])
+    }
+
+    pub fn decode_base64(s: &str) -> Result<Self, base64::DecodeError> {
+        let bytes = base64::decode(s)?;
+        let array: [u8; 96] = bytes[..]
+            .try_into()
+            .map_err(|_| base64::DecodeError::InvalidLength)?;
+        Option::from(G2Affine::from_compressed(&array))
+            .map(Self)
+            .ok_or(base64::DecodeError::InvalidLength)
+    }
+}
+
+impl fmt::Debug for ThresholdPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.encode_base64())
+    }
+}
+
+impl Serialize for ThresholdPublicKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.encode_base64())
+    }
+}
+
+impl<'de> Deserialize<'de> for ThresholdPublicKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let value = Self::decode_base64(&s).map_err(|e| de::Error::custom(e.to_string()))?;
+        Ok(value)
+    }
+}
+
+/// Represents the secret key of a share of the BLS threshold scheme.
+pub struct ThresholdSecretKey(Scalar);
+
+impl ThresholdSecretKey {
+    pub fn encode_base64(&self) -> String {
+        base64::encode(&self.0.to_bytes()[..])
+    }
+
+    pub fn decode_base64(s: &str) -> Result<Self, base64::DecodeError> {
+        let bytes = base64::decode(s)?;
+        let array: [u8; 32] = bytes[..]
+            .try_into()
+            .map_err(|_| base64::DecodeError::InvalidLength)?;
+        Option::from(Scalar::from_bytes(&array))
+            .map(Self)
+            .ok_or(base64::DecodeError::InvalidLength)
+    }
+
+    /// Signs a message with this share of the secret key.
+    pub fn sign(&self, message: &[u8]) -> SignatureShare {
+        SignatureShare((hash_to_g1(message) * self.0).into())
+    }
+}
+
+impl Serialize for ThresholdSecretKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.encode_base64())
+    }
+}
+
+impl<'de> Deserialize<'de> for ThresholdSecretKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let value = Self::decode_base64(&s).map_err(|e| de::Error::custom(e.to_string()))?;
+        Ok(value)
+    }
+}
+
+impl Drop for ThresholdSecretKey {
+    fn drop(&mut self) {
+        self.0 = Scalar::zero();
+    }
+}
+
+fn serialize_g1<S>(point: &G1Affine, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+{
+    serializer.serialize_bytes(&point.to_compressed())
+}
+
+fn deserialize_g1<'de, D>(deserializer: D) -> Result<G1Affine, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+    let array: [u8; 48] = bytes[..]
+        .try_into()
+        .map_err(|_| de::Error::custom("Invalid point length"))?;
+    Option::from(G1Affine::from_compressed(&array))
+        .ok_or_else(|| de::Error::custom("Invalid point"))
+}
+
+/// Represents a share of a BLS threshold signature (a point of G1).
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignatureShare(
+    #[serde(serialize_with = "serialize_g1", deserialize_with = "deserialize_g1")] G1Affine,
+);
+
+impl SignatureShare {
+    pub fn to_bytes(&self) -> [u8; 48] {
+        self.0.to_compressed()
+    }
+
+    /// Verifies the share against the public key of the share that produced it.
+    pub fn verify(
+        &self,
+        message: &[u8],
+        public_key: &ThresholdPublicKey,
+    ) -> Result<(), CryptoError> {
+        verify_pairing(&self.0, message, &public_key.0)
+    }
+}
+
+impl fmt::Debug for SignatureShare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", base64::encode(&self.to_bytes()[..]))
+    }
+}
+
+/// Represents a BLS threshold signature, combined from enough signature shares. All subsets of
+/// shares combine into the same signature.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdSignature(
+    #[serde(serialize_with = "serialize_g1", deserialize_with = "deserialize_g1")] G1Affine,
+);
+
+impl ThresholdSignature {
+    /// Combines signature shares, each given with the index of the share that produced it (as
+    /// dealt by `deal_threshold_keys`). The caller must provide at least `threshold` valid shares
+    /// for the result to be a valid signature.
+    pub fn combine(shares: &[(usize, SignatureShare)]) -> Result<Self, CryptoError> {
+        let indices: HashSet<_> = shares.iter().map(|(i, _)| *i).collect();
+        if shares.is_empty() || indices.len() != shares.len() {
+            return Err(CryptoError::new());
+        }
+
+        // Interpolate the shares at zero (the shares are evaluated at `index + 1`).
+        let xs: Vec<_> = shares
+            .iter()
+            .map(|(i, _)| Scalar::from(*i as u64 + 1))
+            .collect();
+        let mut signature = G1Projective::identity();
+        for (i, (_, share)) in shares.iter().enumerate() {
+            let mut numerator = Scalar::one();
+            let mut denominator = Scalar::one();
+            for (j, x) in xs.iter().enumerate() {
+                if i != j {
+                    numerator *= x;
+                    denominator *= x - xs[i];
+                }
+            }
+            let inverse: Option<Scalar> = denominator.invert().into();
+            let coefficient = numerator * inverse.ok_or_else(CryptoError::new)?;
+            signature += share.0 * coefficient;
+        }
+        Ok(Self(signature.into()))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 48] {
+        self.0.to_compressed()
+    }
+
+    /// Verifies the signature against the public key of the scheme.
+    pub fn verify(
+        &self,
+        message: &[u8],
+        public_key: &ThresholdPublicKey,
+    ) -> Result<(), CryptoError> {
+        verify_pairing(&self.0, message, &public_key.0)
+    }
+}
+
+impl Hash for ThresholdSignature {
+    fn digest(&self) -> Digest {
+        let hash = Sha512::digest(&self.to_bytes());
+        Digest(hash[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for ThresholdSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", base64::encode(&self.to_bytes()[..]))
+    }
+}
+
+fn verify_pairing(signature: &G1Affine, message: &[u8], key: &G2Affine) -> Result<(), CryptoError> {
+    if pairing(signature, &G2Affine::generator()) == pairing(&hash_to_g1(message), key) {
+        Ok(())
+    } else {
+        Err(CryptoError::new())
+    }
+}
+
+fn random_scalar<R>(csprng: &mut R) -> Scalar
+where
+    R: CryptoRng + RngCore,
+{
+    let mut bytes = [0u8; 64];
+    csprng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_wide(&bytes)
+}
+
+/// Deals the keys of a BLS threshold scheme with `shares` shares, any `threshold` of which are
+/// needed to sign. Returns the public key of the scheme and the public and secret keys of each
+/// share (the share at position `i` has index `i`).
+pub fn deal_threshold_keys<R>(
+    csprng: &mut R,
+    shares: usize,
+    threshold: usize,
+) -> (
+    ThresholdPublicKey,
+    Vec<(ThresholdPublicKey, ThresholdSecretKey)>,
+)
+where
+    R: CryptoRng + RngCore,
+{
+    assert!(threshold > 0 && threshold <= shares, "Invalid threshold");
+    let coefficients: Vec<_> = (0..threshold).map(|_| random_scalar(csprng)).collect();
+    let public = |secret: &Scalar| ThresholdPublicKey((G2Projective::generator() * secret).into());
+
+    let keys = (0..shares)
+        .map(|i| {
+            // Evaluate the polynomial at `i + 1` (Horner's method).
+            let x = Scalar::from(i as u64 + 1);
+            let secret = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |acc, coefficient| acc * x + coefficient);
+            (public(&secret), ThresholdSecretKey(secret))
+        })
+        .collect();
+    (public(&coefficients[0]), keys)
+}
@@ -80,8 +80,8 @@ type CommittedBlock struct {
 	sizeCache     protoimpl.SizeCache
 	unknownFields protoimpl.UnknownFields
 
-	Epoch        uint64         `protobuf:"varint,1,opt,name=epoch,proto3" json:"epoch,omitempty"`   // Ta có thể dùng trường 'round' của Certificate
-	Height       uint64         `protobuf:"varint,2,opt,name=height,proto3" json:"height,omitempty"` // Tương tự, có thể dùng 'round'
+	Epoch        uint64         `protobuf:"varint,1,opt,name=epoch,proto3" json:"epoch,omitempty"`   // The round of the leader of the committed sub-DAG.
+	Height       uint64         `protobuf:"varint,2,opt,name=height,proto3" json:"height,omitempty"` // The commit index of the sub-DAG (starts at 1, increases by one per block).
 	Transactions []*Transaction `protobuf:"bytes,3,rep,name=transactions,proto3" json:"transactions,omitempty"`
 }
 
@@ -1,22 +1,22 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::error::NetworkError;
+use crate::transport::{Listener, Reader};
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures::stream::SplitSink;
+use futures::sink::Sink;
 use futures::stream::StreamExt as _;
 use log::{debug, info, warn};
 use std::error::Error;
 use std::net::SocketAddr;
-use tokio::net::{TcpListener, TcpStream};
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use std::pin::Pin;
 
 #[cfg(test)]
 #[path = "tests/receiver_tests.rs"]
 pub mod receiver_tests;
 
-// Convenient alias for the writer end of the TCP channel.
+// Convenient alias for the writer end of a connection (over TCP or the simulated network).
 // Cung cấp một cách để gửi phản hồi lại cho người gửi. Đây là một kênh hai chiều!
-pub type Writer = SplitSink<Framed<TcpStream, LengthDelimitedCodec>, Bytes>;
+pub type Writer = Pin<Box<dyn Sink<Bytes, Error = std::io::Error> + Send>>;
 
 //MessageHandler Trait: "Bản hợp đồng cho Bộ xử lý"
 #[async_trait]
@@ -49,13 +49,13 @@ impl<Handler: MessageHandler> Receiver<Handler> {
 
     /// Main loop responsible to accept incoming connections and spawn a new runner to handle it.
     async fn run(&self) {
-        let listener = TcpListener::bind(&self.address)
+        let mut listener = Listener::bind(self.address)
             .await
             .expect("Failed to bind TCP port");
 
         debug!("Listening on {}", self.address);
         loop {
-            let (socket, peer) = match listener.accept().await {
+            let (writer, reader, peer) = match listener.accept().await {
                 Ok(value) => value,
                 Err(e) => {
                     warn!("{}", NetworkError::FailedToListen(e));
@@ -63,16 +63,19 @@ impl<Handler: MessageHandler> Receiver<Handler> {
                 }
             };
             info!("Incoming connection established with {}", peer);
-            Self::spawn_runner(socket, peer, self.handler.clone()).await;
+            Self::spawn_runner(writer, reader, peer, self.handler.clone()).await;
         }
     }
 
-    /// Spawn a new runner to handle a specific TCP connection. It receives messages and process them
+    /// Spawn a new runner to handle a specific connection. It receives messages and process them
     /// using the provided handler.
-    async fn spawn_runner(socket: TcpStream, peer: SocketAddr, handler: Handler) {
+    async fn spawn_runner(
+        mut writer: Writer,
+        mut reader: Reader,
+        peer: SocketAddr,
+        handler: Handler,
+    ) {
         tokio::spawn(async move {
-            let transport = Framed::new(socket, LengthDelimitedCodec::new());
-            let (mut writer, mut reader) = transport.split();
             while let Some(frame) = reader.next().await {
                 match frame.map_err(|e| NetworkError::FailedToReceiveMessage(peer, e)) {
                     Ok(message) => {
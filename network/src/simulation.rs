@@ -0,0 +1,285 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::receiver::Writer;
+use crate::transport::Reader;
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::sink::Sink;
+use futures::stream::StreamExt as _;
+use rand::rngs::SmallRng;
+use rand::{Rng as _, SeedableRng as _};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::time::{sleep_until, Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/simulation_tests.rs"]
+pub mod simulation_tests;
+
+/// An incoming connection, as handed to the listener of its destination.
+pub(crate) type Incoming = (Writer, Reader, SocketAddr);
+
+thread_local! {
+    /// The simulated network installed on this thread (if any) and the node running on it.
+    static CURRENT: RefCell<Option<(SimulatedNetwork, usize)>> = const { RefCell::new(None) };
+}
+
+/// The network conditions of a simulation.
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    /// The seed of every random choice of the network (latencies and drops).
+    pub seed: u64,
+    /// The minimum one-way latency of a message (in ms).
+    pub min_latency: u64,
+    /// The maximum one-way latency of a message (in ms).
+    pub max_latency: u64,
+    /// The probability that a message is dropped. A drop resets the connection carrying it (as
+    /// TCP would after a failure), so `ReliableSender` re-transmits all the messages it did not
+    /// get an ACK for, while `SimpleSender` loses them.
+    pub drop_probability: f64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            min_latency: 0,
+            max_latency: 10,
+            drop_probability: 0.0,
+        }
+    }
+}
+
+struct State {
+    config: SimulationConfig,
+    /// The listening addresses, along with the node owning them.
+    listeners: HashMap<SocketAddr, (usize, UnboundedSender<Incoming>)>,
+    /// The group of each node while the network is partitioned.
+    groups: Option<HashMap<usize, usize>>,
+    /// One random generator per (directed) pair of nodes.
+    links: HashMap<(usize, usize), SmallRng>,
+    /// Counts connections, to give each one a distinct peer address.
+    connections: u16,
+}
+
+impl State {
+    fn connected(&self, from: usize, to: usize) -> bool {
+        match &self.groups {
+            Some(groups) if from != to => matches!(
+                (groups.get(&from), groups.get(&to)),
+                (Some(x), Some(y)) if x == y
+            ),
+            _ => true,
+        }
+    }
+}
+
+/// An in-memory network connecting the nodes of a simulation. Nodes run on their own threads;
+/// once a thread calls `enter`, every `Receiver`, `ReliableSender` and `SimpleSender` it runs
+/// goes through this network instead of TCP. Messages between two nodes are delivered in order
+/// after a random latency, and may be dropped or blocked by a partition.
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    state: Arc<Mutex<State>>,
+}
+
+impl SimulatedNetwork {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                config,
+                listeners: HashMap::new(),
+                groups: None,
+                links: HashMap::new(),
+                connections: 0,
+            })),
+        }
+    }
+
+    /// Runs the current thread as `node`: its listeners belong to that node and its connections
+    /// originate from it.
+    pub fn enter(&self, node: usize) {
+        CURRENT.with(|current| *current.borrow_mut() = Some((self.clone(), node)));
+    }
+
+    /// Partitions the network: nodes of different groups can no longer reach each other, and
+    /// nodes absent from every group are cut off from all others. Established connections crossing
+    /// the partition reset when they next carry a message.
+    pub fn partition(&self, groups: Vec<Vec<usize>>) {
+        let groups = groups
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, nodes)| nodes.into_iter().map(move |node| (node, i)))
+            .collect();
+        self.state.lock().unwrap().groups = Some(groups);
+    }
+
+    /// Heals any partition.
+    pub fn heal(&self) {
+        self.state.lock().unwrap().groups = None;
+    }
+
+    /// Returns the simulated network installed on the current thread (if any) and its node.
+    pub(crate) fn current() -> Option<(Self, usize)> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Listens on `address` on behalf of `node`. An address can be reused once its previous
+    /// listener is gone (e.g. to restart a node).
+    pub(crate) fn bind(
+        &self,
+        node: usize,
+        address: SocketAddr,
+    ) -> io::Result<UnboundedReceiver<Incoming>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, tx)) = state.listeners.get(&address) {
+            if !tx.is_closed() {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+        }
+        let (tx, rx) = unbounded();
+        state.listeners.insert(address, (node, tx));
+        Ok(rx)
+    }
+
+    /// Opens a connection from `node` to the listener at `address`.
+    pub(crate) fn connect(&self, node: usize, address: SocketAddr) -> io::Result<(Writer, Reader)> {
+        let mut state = self.state.lock().unwrap();
+        // Like with TCP, listening on an unspecified address accepts connections to any address.
+        let unspecified = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), address.port());
+        let listener = state
+            .listeners
+            .get(&address)
+            .or_else(|| state.listeners.get(&unspecified));
+        let (peer, listener) = match listener {
+            Some((peer, listener)) if state.connected(node, *peer) => (*peer, listener.clone()),
+            _ => return Err(io::ErrorKind::ConnectionRefused.into()),
+        };
+        state.connections = state.connections.wrapping_add(1);
+        let source = SocketAddr::new(Ipv4Addr::from(node as u32).into(), state.connections);
+        drop(state);
+
+        let (tx_request, rx_request) = unbounded();
+        let (tx_reply, rx_reply) = unbounded();
+        let client = Link {
+            network: self.clone(),
+            from: node,
+            to: peer,
+            outgoing: tx_request.clone(),
+        };
+        let server = Link {
+            network: self.clone(),
+            from: peer,
+            to: node,
+            outgoing: tx_reply.clone(),
+        };
+        listener
+            .unbounded_send((Box::pin(server), reader(rx_request, tx_reply), source))
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+        Ok((Box::pin(client), reader(rx_reply, tx_request)))
+    }
+
+    /// Decides the fate of a message from `from` to `to`: returns its latency, or `None` if the
+    /// message is lost.
+    fn transmit(&self, from: usize, to: usize) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        if from == to {
+            return Some(Duration::from_millis(0));
+        }
+        if !state.connected(from, to) {
+            return None;
+        }
+        let config = state.config.clone();
+        let rng = state.links.entry((from, to)).or_insert_with(|| {
+            let link = ((from as u64) << 32) | to as u64;
+            SmallRng::seed_from_u64(config.seed ^ link.rotate_left(17))
+        });
+        if rng.gen_bool(config.drop_probability) {
+            return None;
+        }
+        let latency = rng.gen_range(config.min_latency, config.max_latency + 1);
+        Some(Duration::from_millis(latency))
+    }
+}
+
+/// What travels on one direction of a simulated connection.
+enum Packet {
+    /// A message, along with its latency.
+    Message(Bytes, Duration),
+    /// Resets the connection: nothing sent after it is delivered, in either direction.
+    Reset,
+}
+
+/// Delivers the messages of one direction of a connection after their latency, in order.
+/// `reverse` is the other direction of the connection, closed upon reset.
+fn reader(receiver: UnboundedReceiver<Packet>, reverse: UnboundedSender<Packet>) -> Reader {
+    let state = (receiver, reverse, None);
+    let stream = futures::stream::unfold(state, |(mut receiver, reverse, last)| async move {
+        let (data, latency) = match receiver.next().await? {
+            Packet::Message(data, latency) => (data, latency),
+            Packet::Reset => {
+                reverse.close_channel();
+                return None;
+            }
+        };
+        let deadline = match last {
+            Some(last) if last > Instant::now() + latency => last,
+            _ => Instant::now() + latency,
+        };
+        sleep_until(deadline).await;
+        let message = BytesMut::from(&data[..]);
+        Some((Ok(message), (receiver, reverse, Some(deadline))))
+    });
+    Box::pin(stream)
+}
+
+/// The writing end of one side of a simulated connection.
+struct Link {
+    network: SimulatedNetwork,
+    from: usize,
+    to: usize,
+    /// Carries our messages to the other side.
+    outgoing: UnboundedSender<Packet>,
+}
+
+impl Sink<Bytes> for Link {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.outgoing.is_closed() {
+            true => Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, data: Bytes) -> io::Result<()> {
+        // Like with TCP, the sender only learns about a lost message once the connection resets.
+        let packet = match self.network.transmit(self.from, self.to) {
+            Some(latency) => Packet::Message(data, latency),
+            None => Packet::Reset,
+        };
+        self.outgoing
+            .unbounded_send(packet)
+            .map_err(|_| io::ErrorKind::ConnectionReset.into())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.outgoing.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        // Dropping our end closes the connection, so the other side sees it end.
+        self.outgoing.close_channel();
+    }
+}
@@ -1,9 +1,11 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
 use futures::sink::SinkExt as _;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::Sender;
 use tokio::time::{sleep, Duration};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 #[derive(Clone)]
 struct TestHandler {
@@ -0,0 +1,113 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::{MessageHandler, Receiver, ReliableSender};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use futures::sink::SinkExt as _;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::future::Future;
+use std::thread;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::oneshot;
+
+#[derive(Clone)]
+struct TestHandler {
+    deliver: Sender<u64>,
+}
+
+#[async_trait]
+impl MessageHandler for TestHandler {
+    async fn dispatch(&self, writer: &mut Writer, message: Bytes) -> Result<(), Box<dyn Error>> {
+        let _ = writer.send(Bytes::from("Ack")).await;
+        let message = bincode::deserialize(&message).unwrap();
+        self.deliver.send(message).await.unwrap();
+        Ok(())
+    }
+}
+
+// Runs `future` on its own thread as the specified node of the network.
+fn node<F>(network: &SimulatedNetwork, node: usize, future: F) -> thread::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let network = network.clone();
+    thread::spawn(move || {
+        network.enter(node);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    })
+}
+
+// Node 0 reliably sends `count` messages to node 1, which returns them as it receives them.
+fn exchange(network: &SimulatedNetwork, count: u64) -> Vec<u64> {
+    let address = "127.0.0.1:7000".parse::<SocketAddr>().unwrap();
+    let (tx_done, rx_done) = oneshot::channel();
+    let receiver = node(network, 1, async move {
+        let (tx, mut rx) = channel(100);
+        Receiver::spawn(address, TestHandler { deliver: tx });
+        let mut received = Vec::new();
+        while received.iter().collect::<BTreeSet<_>>().len() < count as usize {
+            received.push(rx.recv().await.unwrap());
+        }
+
+        // Keep running until the sender gets all its ACKs.
+        let _ = rx_done.await;
+        received
+    });
+    let sender = node(network, 0, async move {
+        let mut sender = ReliableSender::new();
+        let mut handlers = Vec::new();
+        for i in 0..count {
+            let bytes = Bytes::from(bincode::serialize(&i).unwrap());
+            handlers.push(sender.send(address, bytes).await);
+        }
+        assert!(try_join_all(handlers).await.is_ok());
+        let _ = tx_done.send(());
+    });
+    sender.join().unwrap();
+    receiver.join().unwrap()
+}
+
+#[test]
+fn deliver_in_order() {
+    let network = SimulatedNetwork::new(SimulationConfig {
+        seed: 1,
+        max_latency: 20,
+        ..SimulationConfig::default()
+    });
+    assert_eq!(exchange(&network, 20), (0..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn retransmit_dropped_messages() {
+    let network = SimulatedNetwork::new(SimulationConfig {
+        seed: 2,
+        drop_probability: 0.2,
+        ..SimulationConfig::default()
+    });
+
+    // Dropped ACKs make the sender re-transmit messages that were delivered.
+    let received: BTreeSet<_> = exchange(&network, 20).into_iter().collect();
+    assert_eq!(received, (0..20).collect());
+}
+
+#[test]
+fn partition() {
+    let network = SimulatedNetwork::new(SimulationConfig::default());
+    network.partition(vec![vec![0], vec![1]]);
+    let handle = {
+        let network = network.clone();
+        thread::spawn(move || exchange(&network, 1))
+    };
+
+    // Nothing goes through until the partition heals.
+    thread::sleep(std::time::Duration::from_millis(300));
+    assert!(!handle.is_finished());
+    network.heal();
+    assert_eq!(handle.join().unwrap(), vec![0]);
+}
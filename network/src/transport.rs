@@ -0,0 +1,56 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::receiver::Writer;
+use crate::simulation::{Incoming, SimulatedNetwork};
+use bytes::BytesMut;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::{Stream, StreamExt as _};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+// Convenient alias for the reader end of a connection.
+pub type Reader = Pin<Box<dyn Stream<Item = Result<BytesMut, io::Error>> + Send>>;
+
+/// Connects to `address`, through the simulated network if one is installed on this thread and
+/// over TCP otherwise.
+pub async fn connect(address: SocketAddr) -> io::Result<(Writer, Reader)> {
+    match SimulatedNetwork::current() {
+        Some((network, node)) => network.connect(node, address),
+        None => {
+            let stream = TcpStream::connect(address).await?;
+            let (writer, reader) = Framed::new(stream, LengthDelimitedCodec::new()).split();
+            Ok((Box::pin(writer), Box::pin(reader)))
+        }
+    }
+}
+
+/// Accepts incoming connections, either over TCP or from the simulated network.
+pub enum Listener {
+    Tcp(TcpListener),
+    Simulated(UnboundedReceiver<Incoming>),
+}
+
+impl Listener {
+    pub async fn bind(address: SocketAddr) -> io::Result<Self> {
+        match SimulatedNetwork::current() {
+            Some((network, node)) => network.bind(node, address).map(Self::Simulated),
+            None => TcpListener::bind(address).await.map(Self::Tcp),
+        }
+    }
+
+    pub async fn accept(&mut self) -> io::Result<(Writer, Reader, SocketAddr)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (socket, peer) = listener.accept().await?;
+                let (writer, reader) = Framed::new(socket, LengthDelimitedCodec::new()).split();
+                Ok((Box::pin(writer), Box::pin(reader), peer))
+            }
+            Self::Simulated(incoming) => incoming
+                .next()
+                .await
+                .ok_or_else(|| io::ErrorKind::NotConnected.into()),
+        }
+    }
+}
@@ -1,17 +1,21 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use anyhow::{Context, Result};
 use bytes::BufMut as _;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use clap::{crate_name, crate_version, App, AppSettings};
 use env_logger::Env;
 use futures::future::join_all;
 use futures::sink::SinkExt as _;
+use futures::stream::StreamExt as _;
 use log::{info, warn};
 use rand::Rng;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::net::TcpStream;
 use tokio::time::{interval, sleep, Duration, Instant};
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use worker::{ClientMessage, ClientReply};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -103,10 +107,23 @@ impl Client {
         let mut tx = BytesMut::with_capacity(self.size);
         let mut counter = 0;
         let mut r = rand::thread_rng().gen();
-        let mut transport = Framed::new(stream, LengthDelimitedCodec::new());
+        let (mut transport, mut replies) = Framed::new(stream, LengthDelimitedCodec::new()).split();
         let interval = interval(Duration::from_millis(BURST_DURATION));
         tokio::pin!(interval);
 
+        // The worker only replies when it drops our transactions.
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter_dropped = dropped.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(bytes)) = replies.next().await {
+                if let Ok(ClientReply::Overloaded | ClientReply::TransactionTooLarge { .. }) =
+                    bincode::deserialize(&bytes)
+                {
+                    counter_dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
         // NOTE: This log entry is used to compute performance.
         info!("Start sending transactions");
 
@@ -128,7 +145,8 @@ impl Client {
                 };
 
                 tx.resize(self.size, 0u8);
-                let bytes = tx.split().freeze();
+                let message = ClientMessage::Transaction(tx.split().to_vec());
+                let bytes = Bytes::from(bincode::serialize(&message)?);
                 if let Err(e) = transport.send(bytes).await {
                     warn!("Failed to send transaction: {}", e);
                     break 'main;
@@ -138,6 +156,10 @@ impl Client {
                 // NOTE: This log entry is used to compute performance.
                 warn!("Transaction rate too high for this client");
             }
+            let n = dropped.swap(0, Ordering::Relaxed);
+            if n > 0 {
+                warn!("The worker dropped {} transactions", n);
+            }
             counter += 1;
         }
         Ok(())
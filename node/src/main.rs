@@ -4,13 +4,15 @@ use clap::{crate_name, crate_version, App, AppSettings, ArgMatches, SubCommand};
 use config::Export as _;
 use config::Import as _;
 use config::{Committee, KeyPair, Parameters, WorkerId};
-use consensus::Consensus;
+use consensus::{CommittedSubDag, Consensus};
 use env_logger::Env;
-use primary::{Certificate, Primary};
+use primary::{EquivocationProof, Primary};
+#[cfg(feature = "rocksdb")]
+use store::RocksDbBackend;
 use store::Store;
 use tokio::sync::mpsc::{channel, Receiver};
-use worker::{Worker, WorkerMessage};
-use consensus::{ConsensusProtocol, Tusk, Bullshark};
+use worker::{BatchFetcher, Deduplicator, Worker};
+use consensus::{ConsensusProtocol, ReputationSchedule, Tusk, Bullshark};
 
 // Thêm các use statements cần thiết
 use bytes::{BufMut, BytesMut};
@@ -23,6 +25,9 @@ pub mod comm {
     include!(concat!(env!("OUT_DIR"), "/comm.rs"));
 }
 
+#[cfg(test)]
+mod simulation;
+
 /// The default channel capacity.
 pub const CHANNEL_CAPACITY: usize = 1_000;
 
@@ -34,9 +39,16 @@ async fn main() -> Result<()> {
         .args_from_usage("-v... 'Sets the level of verbosity'")
         .subcommand(
             SubCommand::with_name("generate_keys")
-                .about("Print a fresh key pair to file")
+                .about("Print a fresh key pair (without common coin share) to file")
                 .args_from_usage("--filename=<FILE> 'The file where to print the new key pair'"),
         )
+        .subcommand(
+            SubCommand::with_name("deal_keys")
+                .about("Print fresh key pairs sharing a common coin to files (one per node)")
+                .args_from_usage(
+                    "--filenames=<FILE>... 'The files where to print the new key pairs'",
+                ),
+        )
         .subcommand(
             SubCommand::with_name("run")
                 .about("Run a node")
@@ -71,6 +83,14 @@ async fn main() -> Result<()> {
         ("generate_keys", Some(sub_matches)) => KeyPair::new()
             .export(sub_matches.value_of("filename").unwrap())
             .context("Failed to generate key pair")?,
+        ("deal_keys", Some(sub_matches)) => {
+            let filenames: Vec<_> = sub_matches.values_of("filenames").unwrap().collect();
+            for (keypair, filename) in KeyPair::deal(filenames.len()).iter().zip(filenames) {
+                keypair
+                    .export(filename)
+                    .context("Failed to generate key pair")?;
+            }
+        }
         ("run", Some(sub_matches)) => run(sub_matches).await?,
         _ => unreachable!(),
     }
@@ -94,8 +114,11 @@ async fn run(matches: &ArgMatches<'_>) -> Result<()> {
         }
         None => Parameters::default(),
     };
+    parameters
+        .validate()
+        .context("Failed to load the node's parameters")?;
 
-    let store = Store::new(store_path).context("Failed to create a store")?;
+    let store = open_store(store_path, &parameters)?;
     let (tx_output, rx_output) = channel(CHANNEL_CAPACITY);
 
     match matches.subcommand() {
@@ -113,6 +136,21 @@ async fn run(matches: &ArgMatches<'_>) -> Result<()> {
             
             let (tx_new_certificates, rx_new_certificates) = channel(CHANNEL_CAPACITY);
             let (tx_feedback, rx_feedback) = channel(CHANNEL_CAPACITY);
+            let (tx_equivocations, rx_equivocations) = channel(CHANNEL_CAPACITY);
+            // Without a common coin, anybody can predict the leaders.
+            let coin_keys = keypair
+                .coin
+                .as_ref()
+                .map(|x| x.keys.clone())
+                .context("The node's keys hold no common coin share (create them with deal_keys)")?;
+            let batch_fetcher = BatchFetcher::new(
+                keypair.name,
+                committee.clone(),
+                parameters.sync_retry_delay,
+            );
+            let deduplicator = Deduplicator::new(store.clone(), parameters.dedup_window)
+                .await
+                .context("Failed to load the deduplication state")?;
             
             Primary::spawn(
                 keypair,
@@ -121,7 +159,9 @@ async fn run(matches: &ArgMatches<'_>) -> Result<()> {
                 store.clone(),
                 tx_new_certificates,
                 rx_feedback,
+                tx_equivocations,
             );
+            tokio::spawn(report_equivocations(rx_equivocations));
             
             let committee_clone: Committee = committee.clone();
 
@@ -132,15 +172,20 @@ async fn run(matches: &ArgMatches<'_>) -> Result<()> {
                 rx_new_certificates,
                 tx_feedback,
                 tx_output,
-ConsensusProtocol::Bullshark(Bullshark {
-                    committee: committee_clone,
-                    gc_depth: parameters.gc_depth,
-                }),
+ConsensusProtocol::Bullshark(Bullshark::new(
+                    committee_clone.clone(),
+                    parameters.gc_depth,
+                    Some(coin_keys),
+                    Box::new(ReputationSchedule::new(
+                        committee_clone,
+                        ReputationSchedule::DEFAULT_PERIOD,
+                    )),
+                )),
             );
 
             
 
-            analyze(rx_output, node_id, store).await;
+            analyze(rx_output, node_id, batch_fetcher, deduplicator).await;
         }
         ("worker", Some(sub_matches)) => {
             // SỬA LỖI: Xử lý lỗi ParseIntError một cách an toàn, không dùng unwrap().
@@ -160,8 +205,39 @@ ConsensusProtocol::Bullshark(Bullshark {
     unreachable!();
 }
 
-/// Receives an ordered list of certificates and apply any application-specific logic.
-async fn analyze(mut rx_output: Receiver<Certificate>, node_id: usize, mut store: Store) {
+/// Opens the store of the node, persisted with RocksDB at `path`.
+#[cfg(feature = "rocksdb")]
+fn open_store(path: &str, parameters: &Parameters) -> Result<Store> {
+    let backend = RocksDbBackend::new(path, parameters).context("Failed to create a store")?;
+    Ok(Store::with_backend(backend))
+}
+
+/// Without RocksDB, the node keeps its store in memory: nothing survives a restart.
+#[cfg(not(feature = "rocksdb"))]
+fn open_store(_path: &str, _parameters: &Parameters) -> Result<Store> {
+    log::warn!("Built without the rocksdb feature: the store does not survive a restart");
+    Ok(Store::new_in_memory())
+}
+
+/// Receives the proofs that authorities equivocated, so operators can slash or eject them.
+async fn report_equivocations(mut rx_equivocations: Receiver<EquivocationProof>) {
+    while let Some(proof) = rx_equivocations.recv().await {
+        log::warn!(
+            "Authority {} equivocated at round {}: {:?}",
+            proof.offender(),
+            proof.round(),
+            proof
+        );
+    }
+}
+
+/// Receives the committed sub-DAGs (in order) and apply any application-specific logic.
+async fn analyze(
+    mut rx_output: Receiver<CommittedSubDag>,
+    node_id: usize,
+    mut batch_fetcher: BatchFetcher,
+    mut deduplicator: Deduplicator,
+) {
     fn put_uvarint_to_bytes_mut(buf: &mut BytesMut, mut value: u64) {
         loop {
             if value < 0x80 {
@@ -207,64 +283,43 @@ async fn analyze(mut rx_output: Receiver<Certificate>, node_id: usize, mut store
         node_id
     );
 
-    while let Some(certificate) = rx_output.recv().await {
+    while let Some(sub_dag) = rx_output.recv().await {
         log::info!(
-            "[ANALYZE] Node ID {} RECEIVED certificate for round {} from consensus.",
+            "[ANALYZE] Node ID {} RECEIVED sub-DAG {} (leader round {}, {} certificates) from consensus.",
             node_id,
-            certificate.header.round
+            sub_dag.commit_index,
+            sub_dag.leader_round,
+            sub_dag.certificates.len()
         );
 
         let mut all_transactions = Vec::new();
 
-        for (digest, worker_id) in certificate.header.payload {
-            match store.read(digest.to_vec()).await {
-                Ok(Some(serialized_batch_message)) => {
-                    match bincode::deserialize(&serialized_batch_message) {
-                        Ok(WorkerMessage::Batch(batch)) => {
-                            log::debug!(
-                                "[ANALYZE] Unpacked batch {} with {} transactions for worker {}.",
-                                digest,
-                                batch.len(),
-                                worker_id
-                            );
-                            for tx_data in batch {
-                                all_transactions.push(comm::Transaction {
-                                    digest: tx_data,
-                                    worker_id: worker_id as u32,
-                                });
-                            }
-                        }
-                        Ok(_) => {
-                            log::warn!(
-                                "[ANALYZE] Digest {} did not correspond to a Batch message.",
-                                digest
-                            );
-                        }
-                        Err(e) => {
-                            log::error!(
-                                "[ANALYZE] Failed to deserialize message for digest {}: {}",
-                                digest,
-                                e
-                            );
-                        }
-                    }
-                }
-                Ok(None) => {
-                    log::warn!("[ANALYZE] Batch for digest {} not found in store.", digest);
-                }
-                Err(e) => {
-                    log::error!(
-                        "[ANALYZE] Failed to read batch for digest {}: {}",
-                        digest,
-                        e
-                    );
-                }
+        let payload: Vec<_> = sub_dag
+            .certificates
+            .into_iter()
+            .flat_map(|certificate| certificate.header.payload)
+            .collect();
+        // Every node executes all the committed batches: this waits until they are available.
+        let batches = batch_fetcher.fetch(&payload).await;
+        for ((digest, worker_id), batch) in payload.into_iter().zip(batches) {
+            let batch = deduplicator.filter(sub_dag.leader_round, batch);
+            log::debug!(
+                "[ANALYZE] Unpacked batch {} with {} transactions for worker {}.",
+                digest,
+                batch.len(),
+                worker_id
+            );
+            for tx_data in batch {
+                all_transactions.push(comm::Transaction {
+                    digest: tx_data,
+                    worker_id,
+                });
             }
         }
 
         let committed_block = comm::CommittedBlock {
-            epoch: certificate.header.round,
-            height: certificate.header.round,
+            epoch: sub_dag.leader_round,
+            height: sub_dag.commit_index,
             transactions: all_transactions,
         };
 
@@ -273,9 +328,9 @@ async fn analyze(mut rx_output: Receiver<Certificate>, node_id: usize, mut store
         };
 
         log::debug!(
-            "[ANALYZE] Node ID {} serializing data for round {}",
+            "[ANALYZE] Node ID {} serializing data for height {}",
             node_id,
-            certificate.header.round
+            sub_dag.commit_index
         );
         let mut proto_buf = BytesMut::new();
         epoch_data
@@ -286,10 +341,10 @@ async fn analyze(mut rx_output: Receiver<Certificate>, node_id: usize, mut store
         put_uvarint_to_bytes_mut(&mut len_buf, proto_buf.len() as u64);
 
         if epoch_data.blocks.iter().all(|b| b.transactions.is_empty()) {
-             log::info!("[ANALYZE] Node ID {} SENDING EMPTY BLOCK for round {}.", node_id, certificate.header.round);
+             log::info!("[ANALYZE] Node ID {} SENDING EMPTY BLOCK for height {}.", node_id, sub_dag.commit_index);
         }
 
-        log::info!("[ANALYZE] Node ID {} WRITING {} bytes (len) and {} bytes (data) to socket for round {}.", node_id, len_buf.len(), proto_buf.len(), certificate.header.round);
+        log::info!("[ANALYZE] Node ID {} WRITING {} bytes (len) and {} bytes (data) to socket for height {}.", node_id, len_buf.len(), proto_buf.len(), sub_dag.commit_index);
 
         if let Err(e) = stream.write_all(&len_buf).await {
             log::error!(
@@ -310,10 +365,16 @@ async fn analyze(mut rx_output: Receiver<Certificate>, node_id: usize, mut store
         }
 
         log::info!(
-            "[ANALYZE] SUCCESS: Node ID {} sent block for round {} successfully.",
+            "[ANALYZE] SUCCESS: Node ID {} sent block for height {} successfully.",
             node_id,
-            certificate.header.round
+            sub_dag.commit_index
         );
+
+        // Only remember the transactions once the executor got them: after a crash, we output
+        // them again rather than never.
+        if let Err(e) = deduplicator.persist(sub_dag.leader_round).await {
+            log::error!("Failed to persist the deduplication state: {}", e);
+        }
     }
 
     log::warn!(
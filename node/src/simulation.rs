@@ -0,0 +1,196 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::CHANNEL_CAPACITY;
+use config::{Authority, Committee, KeyPair, Parameters, PrimaryAddresses, WorkerAddresses};
+use consensus::{Bullshark, Consensus, ConsensusProtocol, ReputationSchedule};
+use crypto::{Digest, Hash as _};
+use network::{SimulatedNetwork, SimulationConfig};
+use primary::Primary;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use store::Store;
+use tokio::sync::mpsc::channel;
+use tokio::sync::oneshot;
+use worker::Worker;
+
+#[cfg(test)]
+#[path = "tests/simulation_tests.rs"]
+pub mod simulation_tests;
+
+/// The digests of the certificates of a committed sub-DAG (in commit order, leader last).
+pub type SubDag = Vec<Digest>;
+
+/// The parameters of the simulated nodes: short delays, since the nodes run no client load.
+pub fn parameters() -> Parameters {
+    Parameters {
+        max_header_delay: 20,
+        sync_retry_delay: 100,
+        max_batch_delay: 20,
+        ..Parameters::default()
+    }
+}
+
+/// A committee whose nodes (each a primary, one worker and the consensus) run on their own
+/// threads, connected by a `SimulatedNetwork`.
+pub struct Simulation {
+    /// The network connecting the nodes; tests use it to inject partitions.
+    pub network: SimulatedNetwork,
+    /// Stops each node.
+    stops: Vec<oneshot::Sender<()>>,
+    /// The threads running the nodes.
+    handles: Vec<JoinHandle<()>>,
+    /// Receives the first sub-DAGs committed by each node.
+    rx_commits: mpsc::Receiver<(usize, Vec<SubDag>)>,
+}
+
+impl Simulation {
+    /// Spawns a committee with the specified key pairs (node `i` has the `i`-th one), each node
+    /// reporting the first `commits` sub-DAGs it commits.
+    pub fn spawn(keypairs: Vec<KeyPair>, config: SimulationConfig, commits: usize) -> Self {
+        let network = SimulatedNetwork::new(config);
+        let committee = committee(&keypairs);
+        let parameters = parameters();
+
+        let (tx_commits, rx_commits) = mpsc::channel();
+        let (stops, handles) = keypairs
+            .into_iter()
+            .enumerate()
+            .map(|(id, keypair)| {
+                let (tx_stop, rx_stop) = oneshot::channel();
+                let network = network.clone();
+                let committee = committee.clone();
+                let parameters = parameters.clone();
+                let tx_commits = tx_commits.clone();
+                let handle = thread::spawn(move || {
+                    network.enter(id);
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap()
+                        .block_on(node(
+                            id, keypair, committee, parameters, commits, tx_commits, rx_stop,
+                        ));
+                });
+                (tx_stop, handle)
+            })
+            .unzip();
+
+        Self {
+            network,
+            stops,
+            handles,
+            rx_commits,
+        }
+    }
+
+    /// Waits until every node committed its first sub-DAGs and returns them (indexed by node),
+    /// then stops the committee. Panics if the nodes take longer than `timeout` (in real time).
+    pub fn commits(self, timeout: Duration) -> Vec<Vec<SubDag>> {
+        let mut sequences = vec![Vec::new(); self.handles.len()];
+        for _ in 0..self.handles.len() {
+            let (id, sequence) = self
+                .rx_commits
+                .recv_timeout(timeout)
+                .expect("The committee failed to commit in time");
+            sequences[id] = sequence;
+        }
+        for stop in self.stops {
+            let _ = stop.send(());
+        }
+        for handle in self.handles {
+            handle.join().expect("A node panicked");
+        }
+        sequences
+    }
+}
+
+// Makes a committee (with one worker per authority) out of the specified key pairs. The network
+// is simulated, so the ports only need to be distinct (nodes listen on all interfaces).
+fn committee(keypairs: &[KeyPair]) -> Committee {
+    let address = |i: usize, port: usize| format!("127.0.0.1:{}", port + i).parse().unwrap();
+    Committee {
+        authorities: keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, keypair)| {
+                let primary = PrimaryAddresses {
+                    primary_to_primary: address(i, 100),
+                    worker_to_primary: address(i, 200),
+                };
+                let worker = WorkerAddresses {
+                    primary_to_worker: address(i, 300),
+                    transactions: address(i, 400),
+                    worker_to_worker: address(i, 500),
+                };
+                let authority = Authority {
+                    stake: 1,
+                    primary,
+                    workers: vec![(0, worker)].into_iter().collect(),
+                };
+                (keypair.name, authority)
+            })
+            .collect(),
+    }
+}
+
+// Runs a node (with in-memory stores) until it is stopped, reporting its first committed sub-DAGs.
+async fn node(
+    id: usize,
+    keypair: KeyPair,
+    committee: Committee,
+    parameters: Parameters,
+    commits: usize,
+    tx_commits: mpsc::Sender<(usize, Vec<SubDag>)>,
+    mut rx_stop: oneshot::Receiver<()>,
+) {
+    let primary_store = Store::new_in_memory();
+    let worker_store = Store::new_in_memory();
+
+    let (tx_new_certificates, rx_new_certificates) = channel(CHANNEL_CAPACITY);
+    let (tx_feedback, rx_feedback) = channel(CHANNEL_CAPACITY);
+    let (tx_output, mut rx_output) = channel(CHANNEL_CAPACITY);
+    let (tx_equivocations, _rx_equivocations) = channel(CHANNEL_CAPACITY);
+    let name = keypair.name;
+    let coin_keys = keypair.coin.as_ref().map(|x| x.keys.clone());
+
+    Primary::spawn(
+        keypair,
+        committee.clone(),
+        parameters.clone(),
+        primary_store.clone(),
+        tx_new_certificates,
+        rx_feedback,
+        tx_equivocations,
+    );
+    Worker::spawn(name, 0, committee.clone(), parameters.clone(), worker_store);
+    Consensus::spawn(
+        committee.clone(),
+        parameters.gc_depth,
+        primary_store,
+        rx_new_certificates,
+        tx_feedback,
+        tx_output,
+        ConsensusProtocol::Bullshark(Bullshark::new(
+            committee.clone(),
+            parameters.gc_depth,
+            coin_keys,
+            // A short period, to also exercise changes of the leader schedule.
+            Box::new(ReputationSchedule::new(committee, 5)),
+        )),
+    );
+
+    let mut sequence = Vec::new();
+    loop {
+        tokio::select! {
+            Some(sub_dag) = rx_output.recv() => {
+                if sequence.len() < commits {
+                    sequence.push(sub_dag.certificates.iter().map(|x| x.digest()).collect());
+                    if sequence.len() == commits {
+                        let _ = tx_commits.send((id, sequence.clone()));
+                    }
+                }
+            },
+            _ = &mut rx_stop => break,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use std::env;
+
+// The number of seeds each test runs; set `SIMULATION_SEEDS` to run (many) more.
+fn seeds() -> u64 {
+    env::var("SIMULATION_SEEDS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(2)
+}
+
+// Key pairs without a common coin: checking coin shares is slow in debug builds.
+fn keys(size: usize) -> Vec<KeyPair> {
+    (0..size).map(|_| KeyPair::new()).collect()
+}
+
+fn config(seed: u64) -> SimulationConfig {
+    SimulationConfig {
+        seed,
+        min_latency: 1,
+        max_latency: 20,
+        drop_probability: 0.01,
+    }
+}
+
+// Ensures all nodes committed the same sequence of sub-DAGs.
+fn assert_safety(sequences: &[Vec<SubDag>], seed: u64) {
+    for (id, sequence) in sequences.iter().enumerate() {
+        assert_eq!(
+            sequence, &sequences[0],
+            "Nodes 0 and {} committed different sequences (seed {})",
+            id, seed
+        );
+    }
+}
+
+#[test]
+fn identical_commit_sequences() {
+    for seed in 0..seeds() {
+        let simulation = Simulation::spawn(keys(4), config(seed), 10);
+        let sequences = simulation.commits(Duration::from_secs(60));
+        assert_safety(&sequences, seed);
+    }
+}
+
+#[test]
+fn larger_committee() {
+    for seed in 0..seeds() {
+        let simulation = Simulation::spawn(keys(7), config(seed), 10);
+        let sequences = simulation.commits(Duration::from_secs(60));
+        assert_safety(&sequences, seed);
+    }
+}
+
+#[test]
+fn common_coin() {
+    let simulation = Simulation::spawn(KeyPair::deal(4), config(0), 5);
+    let sequences = simulation.commits(Duration::from_secs(120));
+    assert_safety(&sequences, 0);
+}
+
+#[test]
+fn heal_partition() {
+    for seed in 0..seeds() {
+        // Cut off one node; the others keep committing without it.
+        let simulation = Simulation::spawn(keys(4), config(seed), 20);
+        simulation.network.partition(vec![vec![0, 1, 2], vec![3]]);
+        thread::sleep(Duration::from_millis(500));
+
+        // Once the partition heals, the node catches up and commits the same sequence.
+        simulation.network.heal();
+        let sequences = simulation.commits(Duration::from_secs(60));
+        assert_safety(&sequences, seed);
+    }
+}
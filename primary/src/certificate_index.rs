@@ -0,0 +1,118 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::messages::Certificate;
+use crate::primary::Round;
+use crypto::{Digest, Hash as _, PublicKey};
+use std::convert::TryInto as _;
+use std::ops::RangeInclusive;
+use store::{Store, StoreError, Table, WriteBatch, CERTIFICATES, INDICES};
+
+#[cfg(test)]
+#[path = "tests/certificate_index_tests.rs"]
+pub mod certificate_index_tests;
+
+/// The prefix of the index entries in the `INDICES` column family.
+const PREFIX: &[u8] = b"certificates/";
+
+/// The secondary index (round, author) -> digest of the certificates we store, maintained by the
+/// `Core`. Its entries sort by round (big-endian) and then by author, so that we can scan the DAG
+/// by round.
+#[derive(Clone)]
+pub struct CertificateIndex {
+    /// The index itself.
+    indices: Store,
+    /// The certificates, by digest.
+    certificates: Table<Digest, Certificate>,
+}
+
+impl CertificateIndex {
+    pub fn new(store: &Store) -> Self {
+        Self {
+            indices: store.column(INDICES),
+            certificates: Table::new(store, CERTIFICATES),
+        }
+    }
+
+    fn prefix(round: Round) -> Vec<u8> {
+        [PREFIX, &round.to_be_bytes()].concat()
+    }
+
+    /// Adds the index entry of a certificate to the batch storing it.
+    pub fn insert(&self, batch: &mut WriteBatch, certificate: &Certificate) {
+        let prefix = Self::prefix(certificate.round());
+        let key = [&prefix, certificate.origin().as_ref()].concat();
+        batch.put(&self.indices, key, certificate.digest().to_vec());
+    }
+
+    /// Returns the digests of the certificates of the specified rounds, along with their authors,
+    /// sorted by round and author.
+    pub async fn digests(
+        &mut self,
+        rounds: RangeInclusive<Round>,
+    ) -> Result<Vec<(Round, PublicKey, Digest)>, StoreError> {
+        let from = Self::prefix(*rounds.start());
+        let entries = match rounds.end().checked_add(1) {
+            Some(end) => self.indices.read_range(from, Self::prefix(end)).await?,
+            None => self.indices.read_prefix(PREFIX.to_vec()).await?,
+        };
+        let digests = entries
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let key = key.get(PREFIX.len()..)?;
+                let round = Round::from_be_bytes(key.get(..8)?.try_into().ok()?);
+                let author = PublicKey(key.get(8..)?.try_into().ok()?);
+                let digest = Digest(value[..].try_into().ok()?);
+                Some((round, author, digest))
+            })
+            .collect();
+        Ok(digests)
+    }
+
+    /// Returns the certificates of the specified rounds, sorted by round and author.
+    pub async fn rounds(
+        &mut self,
+        rounds: RangeInclusive<Round>,
+    ) -> Result<Vec<Certificate>, StoreError> {
+        let digests = self.digests(rounds).await?;
+        self.read(digests.into_iter().map(|(_, _, digest)| digest))
+            .await
+    }
+
+    /// Returns the certificates of a round, sorted by author.
+    pub async fn round(&mut self, round: Round) -> Result<Vec<Certificate>, StoreError> {
+        self.rounds(round..=round).await
+    }
+
+    /// Returns the certificates of an author between two rounds (included), sorted by round.
+    pub async fn author(
+        &mut self,
+        author: &PublicKey,
+        rounds: RangeInclusive<Round>,
+    ) -> Result<Vec<Certificate>, StoreError> {
+        let digests = self.digests(rounds).await?;
+        let digests = digests
+            .into_iter()
+            .filter(|(_, x, _)| x == author)
+            .map(|(_, _, digest)| digest);
+        self.read(digests).await
+    }
+
+    /// Reads the certificates from the store, skipping the ones pruned in the meantime.
+    async fn read(
+        &mut self,
+        digests: impl Iterator<Item = Digest>,
+    ) -> Result<Vec<Certificate>, StoreError> {
+        let mut certificates = Vec::new();
+        for digest in digests {
+            if let Some(certificate) = self.certificates.read(&digest).await? {
+                certificates.push(certificate);
+            }
+        }
+        Ok(certificates)
+    }
+
+    /// Deletes the index entries of the rounds lower than `round` (see `Pruner`).
+    pub async fn prune(&mut self, round: Round) {
+        let (from, to) = (Self::prefix(0), Self::prefix(round));
+        self.indices.delete_range(from, to).await;
+    }
+}
@@ -1,35 +1,67 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::error::{DagError, DagResult};
 use crate::messages::Certificate;
+use crate::primary::Round;
+use crypto::{Digest, Hash as _, PublicKey};
 use futures::future::try_join_all;
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
-use log::error;
-use store::Store;
-use tokio::sync::mpsc::{Receiver, Sender};
+use log::{debug, error};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{Store, Table, CERTIFICATES};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 /// Waits to receive all the ancestors of a certificate before looping it back to the `Core`
 /// for further processing.
 pub struct CertificateWaiter {
-    /// The persistent storage.
-    store: Store,
+    /// The certificates we processed.
+    certificates: Table<Digest, Certificate>,
+    /// The current consensus round (used for cleanup).
+    consensus_round: Arc<AtomicU64>,
+    /// The depth of the garbage collector.
+    gc_depth: Round,
+    /// The highest round of the certificates we received (written by the `Core`).
+    certified_round: Arc<AtomicU64>,
+    /// How far ahead of `certified_round` we accept to wait for the ancestors of certificates.
+    max_round_lookahead: Round,
     /// Receives sync commands from the `Synchronizer`.
     rx_synchronizer: Receiver<Certificate>,
     /// Loops back to the core certificates for which we got all parents.
     tx_core: Sender<Certificate>,
+    /// The certificates waiting for their ancestors, along with their round, origin and the
+    /// handler to cancel the wait.
+    pending: HashMap<Digest, (Round, PublicKey, Sender<()>)>,
+    /// The number of certificates of each origin in `pending`.
+    pending_per_origin: HashMap<PublicKey, usize>,
+    /// The maximum number of certificates of a single origin that may wait for their ancestors.
+    /// Authorities have one certificate per round, so this is the number of rounds we track.
+    max_pending_per_origin: usize,
 }
 
 impl CertificateWaiter {
     pub fn spawn(
         store: Store,
+        consensus_round: Arc<AtomicU64>,
+        gc_depth: Round,
+        certified_round: Arc<AtomicU64>,
+        max_round_lookahead: Round,
         rx_synchronizer: Receiver<Certificate>,
         tx_core: Sender<Certificate>,
     ) {
         tokio::spawn(async move {
             Self {
-                store,
+                certificates: Table::new(&store, CERTIFICATES),
+                consensus_round,
+                gc_depth,
+                certified_round,
+                max_round_lookahead,
                 rx_synchronizer,
                 tx_core,
+                pending: HashMap::new(),
+                pending_per_origin: HashMap::new(),
+                max_pending_per_origin: (gc_depth + max_round_lookahead) as usize,
             }
             .run()
             .await
@@ -39,18 +71,59 @@ impl CertificateWaiter {
     /// Helper function. It waits for particular data to become available in the storage
     /// and then delivers the specified header.
     async fn waiter(
-        mut missing: Vec<(Vec<u8>, Store)>,
+        mut missing: Vec<(Digest, Table<Digest, Certificate>)>,
         deliver: Certificate,
-    ) -> DagResult<Certificate> {
-        let waiting: Vec<_> = missing
-            .iter_mut()
-            .map(|(x, y)| y.notify_read(x.to_vec()))
-            .collect();
+        mut handler: Receiver<()>,
+    ) -> DagResult<Option<Certificate>> {
+        let waiting: Vec<_> = missing.iter_mut().map(|(x, y)| y.notify_read(x)).collect();
+        tokio::select! {
+            result = try_join_all(waiting) => {
+                result.map(|_| Some(deliver)).map_err(DagError::from)
+            }
+            _ = handler.recv() => Ok(None),
+        }
+    }
 
-        try_join_all(waiting)
-            .await
-            .map(|_| deliver)
-            .map_err(DagError::from)
+    /// Checks whether we should wait for the ancestors of a certificate. We wait only once per
+    /// certificate, only up to `max_round_lookahead` rounds ahead, and only for a bounded number
+    /// of certificates per origin.
+    fn admit(&self, certificate: &Certificate, digest: &Digest) -> bool {
+        if self.pending.contains_key(digest) {
+            return false;
+        }
+        let certified_round = self.certified_round.load(Ordering::Relaxed);
+        if certificate.round() > certified_round + self.max_round_lookahead {
+            debug!(
+                "Not waiting for the ancestors of {:?}: too far ahead",
+                certificate
+            );
+            return false;
+        }
+        let pending = self
+            .pending_per_origin
+            .get(&certificate.origin())
+            .unwrap_or(&0);
+        if *pending >= self.max_pending_per_origin {
+            debug!(
+                "Not waiting for the ancestors of {:?}: too many pending certificates from {}",
+                certificate,
+                certificate.origin()
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Removes a certificate from the waiter pool and returns its cancel handler.
+    fn remove_pending(&mut self, digest: &Digest) -> Option<Sender<()>> {
+        let (_, origin, handler) = self.pending.remove(digest)?;
+        if let Some(count) = self.pending_per_origin.get_mut(&origin) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending_per_origin.remove(&origin);
+            }
+        }
+        Some(handler)
     }
 
     async fn run(&mut self) {
@@ -59,6 +132,11 @@ impl CertificateWaiter {
         loop {
             tokio::select! {
                 Some(certificate) = self.rx_synchronizer.recv() => {
+                    let digest = certificate.digest();
+                    if !self.admit(&certificate, &digest) {
+                        continue;
+                    }
+
                     // Add the certificate to the waiter pool. The waiter will return it to us
                     // when all its parents are in the store.
                     let wait_for = certificate
@@ -66,21 +144,46 @@ impl CertificateWaiter {
                         .parents
                         .iter()
                         .cloned()
-                        .map(|x| (x.to_vec(), self.store.clone()))
+                        .map(|x| (x, self.certificates.clone()))
                         .collect();
-                    let fut = Self::waiter(wait_for, certificate);
+                    let (tx_cancel, rx_cancel) = channel(1);
+                    let origin = certificate.origin();
+                    self.pending.insert(digest, (certificate.round(), origin, tx_cancel));
+                    *self.pending_per_origin.entry(origin).or_insert(0) += 1;
+                    let fut = Self::waiter(wait_for, certificate, rx_cancel);
                     waiting.push(fut);
                 }
                 Some(result) = waiting.next() => match result {
-                    Ok(certificate) => {
+                    Ok(Some(certificate)) => {
+                        let _ = self.remove_pending(&certificate.digest());
                         self.tx_core.send(certificate).await.expect("Failed to send certificate");
                     },
+                    Ok(None) => {
+                        // This request has been canceled.
+                    },
                     Err(e) => {
                         error!("{}", e);
                         panic!("Storage failure: killing node.");
                     }
                 },
             }
+
+            // Cleanup internal state.
+            let round = self.consensus_round.load(Ordering::Relaxed);
+            if round > self.gc_depth {
+                let gc_round = round - self.gc_depth;
+                let expired: Vec<_> = self
+                    .pending
+                    .iter()
+                    .filter(|(_, (r, _, _))| r <= &gc_round)
+                    .map(|(digest, _)| digest.clone())
+                    .collect();
+                for digest in expired {
+                    if let Some(handler) = self.remove_pending(&digest) {
+                        let _ = handler.send(()).await;
+                    }
+                }
+            }
         }
     }
 }
@@ -1,20 +1,23 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::aggregators::{CertificatesAggregator, VotesAggregator};
+use crate::certificate_index::CertificateIndex;
 use crate::error::{DagError, DagResult};
-use crate::messages::{Certificate, Header, Vote};
-use crate::primary::{PrimaryMessage, Round};
+use crate::messages::{Certificate, EquivocationProof, Header, Vote};
+use crate::primary::{PrimaryMessage, Round, LAST_COMMITTED_ROUND_KEY};
+use crate::proposer::Proposer;
 use crate::synchronizer::Synchronizer;
 use async_recursion::async_recursion;
 use bytes::Bytes;
 use config::Committee;
 use crypto::Hash as _;
 use crypto::{Digest, PublicKey, SignatureService};
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use network::{CancelHandler, ReliableSender};
 use std::collections::{HashMap, HashSet};
+use std::convert::TryInto as _;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use store::Store;
+use store::{RoundIndex, Store, Table, WriteBatch, CERTIFICATES, CONSENSUS, HEADERS};
 use tokio::sync::mpsc::{Receiver, Sender};
 
 #[cfg(test)]
@@ -28,6 +31,16 @@ pub struct Core {
     committee: Committee,
     /// The persistent storage.
     store: Store,
+    /// The headers we processed.
+    headers: Table<Digest, Header>,
+    /// The certificates we processed.
+    certificates: Table<Digest, Certificate>,
+    /// Indexes the headers and the certificates we store by round, so that the `Pruner` can
+    /// delete the old ones.
+    header_rounds: RoundIndex,
+    certificate_rounds: RoundIndex,
+    /// Indexes the certificates we store by round and author.
+    certificate_index: CertificateIndex,
     /// Handles synchronization with other nodes and our workers.
     synchronizer: Synchronizer,
     /// Service to sign headers.
@@ -36,6 +49,11 @@ pub struct Core {
     consensus_round: Arc<AtomicU64>,
     /// The depth of the garbage collector.
     gc_depth: Round,
+    /// The highest round of the certificates we received (shared with the waiters). Only the
+    /// `Core` writes into this variable.
+    certified_round: Arc<AtomicU64>,
+    /// How far ahead of `certified_round` we accept headers and certificates.
+    max_round_lookahead: Round,
 
     /// Receiver for dag messages (headers, votes, certificates).
     rx_primaries: Receiver<PrimaryMessage>,
@@ -47,6 +65,8 @@ pub struct Core {
     rx_proposer: Receiver<Header>,
     /// Output all certificates to the consensus layer.
     tx_consensus: Sender<Certificate>,
+    /// Output the proofs of equivocation to the application.
+    tx_equivocations: Sender<EquivocationProof>,
     /// Send valid a quorum of certificates' ids to the `Proposer` (along with their round).
     tx_proposer: Sender<(Vec<Digest>, Round)>,
 
@@ -54,8 +74,18 @@ pub struct Core {
     gc_round: Round,
     /// The authors of the last voted headers.
     last_voted: HashMap<Round, HashSet<PublicKey>>,
+    /// The highest round of the headers we voted for, per author (as persisted in the store).
+    voted_rounds: HashMap<PublicKey, Round>,
+    /// The value of `voted_rounds` when we started. We do not know which of these headers we voted
+    /// for before restarting, so we never vote again up to these rounds.
+    recovered_votes: HashMap<PublicKey, Round>,
     /// The set of headers we are currently processing.
     processing: HashMap<Round, HashSet<Digest>>,
+    /// The first header we received from each author, per round (to detect equivocations).
+    first_headers: HashMap<Round, HashMap<PublicKey, Header>>,
+    /// The first vote each authority sent us for our header of each round (to detect
+    /// equivocations).
+    first_votes: HashMap<Round, HashMap<PublicKey, Vote>>,
     /// The last header we proposed (for which we are waiting votes).
     current_header: Header,
     /// Aggregates votes into a certificate.
@@ -78,31 +108,46 @@ impl Core {
         signature_service: SignatureService,
         consensus_round: Arc<AtomicU64>,
         gc_depth: Round,
+        certified_round: Arc<AtomicU64>,
+        max_round_lookahead: Round,
         rx_primaries: Receiver<PrimaryMessage>,
         rx_header_waiter: Receiver<Header>,
         rx_certificate_waiter: Receiver<Certificate>,
         rx_proposer: Receiver<Header>,
         tx_consensus: Sender<Certificate>,
+        tx_equivocations: Sender<EquivocationProof>,
         tx_proposer: Sender<(Vec<Digest>, Round)>,
     ) {
         tokio::spawn(async move {
             Self {
                 name,
                 committee,
+                headers: Table::new(&store, HEADERS),
+                certificates: Table::new(&store, CERTIFICATES),
+                header_rounds: RoundIndex::new(&store, HEADERS),
+                certificate_rounds: RoundIndex::new(&store, CERTIFICATES),
+                certificate_index: CertificateIndex::new(&store),
                 store,
                 synchronizer,
                 signature_service,
                 consensus_round,
                 gc_depth,
+                certified_round,
+                max_round_lookahead,
                 rx_primaries,
                 rx_header_waiter,
                 rx_certificate_waiter,
                 rx_proposer,
                 tx_consensus,
+                tx_equivocations,
                 tx_proposer,
                 gc_round: 0,
                 last_voted: HashMap::with_capacity(2 * gc_depth as usize),
+                voted_rounds: HashMap::new(),
+                recovered_votes: HashMap::new(),
                 processing: HashMap::with_capacity(2 * gc_depth as usize),
+                first_headers: HashMap::with_capacity(2 * gc_depth as usize),
+                first_votes: HashMap::with_capacity(2 * gc_depth as usize),
                 current_header: Header::default(),
                 votes_aggregator: VotesAggregator::new(),
                 certificates_aggregators: HashMap::with_capacity(2 * gc_depth as usize),
@@ -114,6 +159,80 @@ impl Core {
         });
     }
 
+    /// The store key of the highest round of the headers we voted for from `author`.
+    fn vote_key(author: &PublicKey) -> Vec<u8> {
+        [b"last_voted".as_ref(), author.0.as_ref()].concat()
+    }
+
+    /// The store key of the proof that `offender` equivocated at `round`.
+    fn equivocation_key(offender: &PublicKey, round: Round) -> Vec<u8> {
+        [
+            b"equivocation".as_ref(),
+            offender.0.as_ref(),
+            &round.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// Restores the rounds we voted for, along with the headers and certificates we stored since
+    /// our last header, and broadcasts again that header (if we crashed). The votes we received
+    /// for it are lost: the authorities that already voted for it do not vote again, so it may
+    /// never be certified. The `Proposer` then moves on once we have a quorum of certificates of
+    /// its round (possibly from the store).
+    async fn recover(&mut self) -> DagResult<Option<Header>> {
+        for name in self.committee.authorities.keys() {
+            if let Some(bytes) = self.store.read(Self::vote_key(name)).await? {
+                let round = bincode::deserialize(&bytes).expect("Failed to deserialize round");
+                self.voted_rounds.insert(*name, round);
+            }
+        }
+        self.recovered_votes = self.voted_rounds.clone();
+
+        let header: Option<Header> = self
+            .store
+            .read(Proposer::LAST_HEADER_KEY.to_vec())
+            .await?
+            .map(|bytes| bincode::deserialize(&bytes).expect("Failed to deserialize our header"));
+        let round = header.as_ref().map_or(0, |x| x.round);
+
+        // We do not need to process again the headers we stored.
+        for (round, id) in self.header_rounds.keys(round).await? {
+            let id = Digest(id.try_into().expect("Invalid header digest in the round index"));
+            self.processing.entry(round).or_default().insert(id);
+        }
+
+        // Aggregate the certificates we stored, and send the parents of the latest rounds to the
+        // `Proposer` (it ignores the rounds before our last header).
+        for certificate in self.certificate_index.rounds(round..=Round::MAX).await? {
+            let round = certificate.round();
+            if let Some(parents) = self
+                .certificates_aggregators
+                .entry(round)
+                .or_insert_with(|| Box::new(CertificatesAggregator::new()))
+                .append(certificate, &self.committee)?
+            {
+                self.tx_proposer
+                    .send((parents, round))
+                    .await
+                    .expect("Failed to send certificate");
+            }
+        }
+        Ok(header)
+    }
+
+    /// Persists our vote for `header` before sending it. We must not send the vote if this fails:
+    /// after a crash, we could vote again in the same round.
+    async fn persist_vote(&mut self, header: &Header) -> DagResult<()> {
+        let round = self.voted_rounds.get(&header.author).copied().unwrap_or(0);
+        if header.round > round {
+            let bytes = bincode::serialize(&header.round).expect("Failed to serialize round");
+            let pairs = vec![(Self::vote_key(&header.author), bytes)];
+            self.store.write_batch(pairs).await?;
+            self.voted_rounds.insert(header.author, header.round);
+        }
+        Ok(())
+    }
+
     async fn process_own_header(&mut self, header: Header) -> DagResult<()> {
         // Reset the votes aggregator.
         self.current_header = header.clone();
@@ -141,6 +260,19 @@ impl Core {
     #[async_recursion]
     async fn process_header(&mut self, header: &Header) -> DagResult<()> {
         debug!("Processing {:?}", header);
+        // Check whether the author already sent us another header for this round.
+        let first = self
+            .first_headers
+            .entry(header.round)
+            .or_default()
+            .entry(header.author)
+            .or_insert_with(|| header.clone());
+        if first.id != header.id {
+            let proof =
+                EquivocationProof::Headers(Box::new(first.clone()), Box::new(header.clone()));
+            self.process_equivocation(proof).await?;
+        }
+
         // Indicate that we are processing this header.
         self.processing
             .entry(header.round)
@@ -178,16 +310,27 @@ impl Core {
         }
 
         // Store the header.
-        let bytes = bincode::serialize(header).expect("Failed to serialize header");
-        self.store.write(header.id.to_vec(), bytes).await;
-
-        // Check if we can vote for this header.
-        if self
-            .last_voted
-            .entry(header.round)
-            .or_insert_with(HashSet::new)
-            .insert(header.author)
+        let mut batch = WriteBatch::default();
+        batch.insert(&self.headers, &header.id, header);
+        self.header_rounds
+            .insert(&mut batch, header.round, header.id.as_ref());
+        self.store.write_all(batch).await?;
+
+        // Check if we can vote for this header. After a crash, we do not vote again for the rounds
+        // we may have voted for already.
+        let recovered = self
+            .recovered_votes
+            .get(&header.author)
+            .is_some_and(|r| header.round <= *r);
+        if !recovered
+            && self
+                .last_voted
+                .entry(header.round)
+                .or_insert_with(HashSet::new)
+                .insert(header.author)
         {
+            self.persist_vote(header).await?;
+
             // Make a vote and send it to the header's creator.
             let vote = Vote::new(header, &self.name, &mut self.signature_service).await;
             debug!("Created {:?}", vote);
@@ -213,9 +356,61 @@ impl Core {
         Ok(())
     }
 
+    /// Checks whether the author of a (verified) vote for one of our headers already sent us a
+    /// vote for another header of the same round.
+    async fn check_vote(&mut self, vote: &Vote) -> DagResult<()> {
+        let first = self
+            .first_votes
+            .entry(vote.round)
+            .or_default()
+            .entry(vote.author)
+            .or_insert_with(|| vote.clone());
+        if first.id != vote.id {
+            let proof = EquivocationProof::Votes(Box::new(first.clone()), Box::new(vote.clone()));
+            self.process_equivocation(proof).await?;
+        }
+        Ok(())
+    }
+
+    /// Records an equivocation we did not know about: persists its proof, gossips it to the other
+    /// primaries, and hands it to the application.
+    async fn process_equivocation(&mut self, proof: EquivocationProof) -> DagResult<()> {
+        let key = Self::equivocation_key(&proof.offender(), proof.round());
+        if self.store.read(key.clone()).await?.is_some() {
+            return Ok(());
+        }
+        warn!("Authority {} equivocated: {:?}", proof.offender(), proof);
+
+        // Store the proof.
+        let bytes = bincode::serialize(&proof).expect("Failed to serialize equivocation proof");
+        self.store.write(key, bytes).await;
+
+        // Broadcast the proof.
+        let addresses = self
+            .committee
+            .others_primaries(&self.name)
+            .iter()
+            .map(|(_, x)| x.primary_to_primary)
+            .collect();
+        let bytes = bincode::serialize(&PrimaryMessage::Equivocation(proof.clone()))
+            .expect("Failed to serialize equivocation proof");
+        let handlers = self.network.broadcast(addresses, Bytes::from(bytes)).await;
+        self.cancel_handlers
+            .entry(proof.round())
+            .or_default()
+            .extend(handlers);
+
+        // Hand it to the application.
+        if let Err(e) = self.tx_equivocations.send(proof).await {
+            warn!("Failed to deliver equivocation proof: {}", e);
+        }
+        Ok(())
+    }
+
     #[async_recursion]
     async fn process_vote(&mut self, vote: Vote) -> DagResult<()> {
         debug!("Processing {:?}", vote);
+        self.check_vote(&vote).await?;
 
         // Add it to the votes' aggregator and try to make a new certificate.
         if let Some(certificate) =
@@ -274,9 +469,18 @@ impl Core {
             return Ok(());
         }
 
-        // Store the certificate.
-        let bytes = bincode::serialize(&certificate).expect("Failed to serialize certificate");
-        self.store.write(certificate.digest().to_vec(), bytes).await;
+        // Store the certificate along with its header (we may not have stored the header yet if its
+        // processing got suspended), so that one is never in the store without the other.
+        let (round, digest) = (certificate.round(), certificate.digest());
+        let mut batch = WriteBatch::default();
+        batch.insert(&self.headers, &certificate.header.id, &certificate.header);
+        batch.insert(&self.certificates, &digest, &certificate);
+        self.header_rounds
+            .insert(&mut batch, round, certificate.header.id.as_ref());
+        self.certificate_rounds
+            .insert(&mut batch, round, digest.as_ref());
+        self.certificate_index.insert(&mut batch, &certificate);
+        self.store.write_all(batch).await?;
 
         // Check if we have enough certificates to enter a new dag round and propose a header.
         if let Some(parents) = self
@@ -292,21 +496,21 @@ impl Core {
                 .expect("Failed to send certificate");
         }
 
-        // Quick check: Đọc consensus state để tránh gửi certificate đã commit
-        let consensus_state_key = b"consensus_state".to_vec();
-      if let Ok(Some(bytes)) = self.store.read(consensus_state_key.clone()).await {
-          #[derive(serde::Deserialize)]
-          struct ConsensusState {
-              last_committed_round: Round,
-          }
-          if let Ok(state) = bincode::deserialize::<ConsensusState>(&bytes) {
-              if certificate.round() <= state.last_committed_round {
-                  debug!("Certificate {} already committed (round {} <= {}), skipping consensus", 
-                         certificate.digest(), certificate.round(), state.last_committed_round);
-                  return Ok(());
-              }
-          }
-      }
+        // Don't send the certificates of the rounds the consensus already committed.
+        let mut consensus = self.store.column(CONSENSUS);
+        if let Ok(Some(bytes)) = consensus.read(LAST_COMMITTED_ROUND_KEY.to_vec()).await {
+            if let Ok(last_committed_round) = bincode::deserialize::<Round>(&bytes) {
+                if certificate.round() <= last_committed_round {
+                    debug!(
+                        "Certificate {} already committed (round {} <= {}), skipping consensus",
+                        certificate.digest(),
+                        certificate.round(),
+                        last_committed_round
+                    );
+                    return Ok(());
+                }
+            }
+        }
 
         log::info!("Sending certificate {:?} to consensus", certificate.digest());
 
@@ -327,11 +531,17 @@ impl Core {
             DagError::TooOld(header.id.clone(), header.round)
         );
 
+        // Anyone can make headers for any round, but only a quorum can certify a round. Ensure bad
+        // nodes do not make us sync (and remember) headers of rounds nobody reached.
+        let certified_round = self.certified_round.load(Ordering::Relaxed);
+        ensure!(
+            header.round <= certified_round + self.max_round_lookahead,
+            DagError::TooNew(header.id.clone(), header.round)
+        );
+
         // Verify the header's signature.
         header.verify(&self.committee)?;
 
-        // TODO [issue #3]: Prevent bad nodes from sending junk headers with high round numbers.
-
         Ok(())
     }
 
@@ -353,6 +563,25 @@ impl Core {
         vote.verify(&self.committee).map_err(DagError::from)
     }
 
+    fn sanitize_unexpected_vote(&mut self, vote: &Vote) -> DagResult<()> {
+        // Only votes for one of our (recent) headers are evidence.
+        ensure!(
+            vote.origin == self.name && self.gc_round <= vote.round,
+            DagError::UnexpectedVote(vote.id.clone())
+        );
+        vote.verify(&self.committee)
+    }
+
+    fn sanitize_equivocation(&mut self, proof: &EquivocationProof) -> DagResult<()> {
+        ensure!(
+            self.gc_round <= proof.round(),
+            DagError::TooOld(proof.digest(), proof.round())
+        );
+
+        // Verify the proof (and the conflicting messages).
+        proof.verify(&self.committee)
+    }
+
     fn sanitize_certificate(&mut self, certificate: &Certificate) -> DagResult<()> {
         ensure!(
             self.gc_round <= certificate.round(),
@@ -360,11 +589,38 @@ impl Core {
         );
 
         // Verify the certificate (and the embedded header).
-        certificate.verify(&self.committee).map_err(DagError::from)
+        certificate.verify(&self.committee)?;
+
+        // A valid certificate proves a quorum reached its round, so we learn the round even if we
+        // do not process a certificate that far ahead of the previous ones (the next certificates
+        // of that round will be within reach).
+        let certified_round = self
+            .certified_round
+            .fetch_max(certificate.round(), Ordering::Relaxed);
+        ensure!(
+            certificate.round() <= certified_round + self.max_round_lookahead,
+            DagError::TooNew(certificate.digest(), certificate.round())
+        );
+        Ok(())
     }
 
     // Main loop listening to incoming messages.
     pub async fn run(&mut self) {
+        // Recover our state if we crashed, and help the others certify our last header.
+        match self.recover().await {
+            Ok(Some(header)) => {
+                info!("Recovered our header of round {}", header.round);
+                if let Err(e) = self.process_own_header(header).await {
+                    warn!("{}", e);
+                }
+            }
+            Ok(None) => (),
+            Err(e) => {
+                error!("{}", e);
+                panic!("Storage failure: killing node.");
+            }
+        }
+
         loop {
             let result = tokio::select! {
                 // We receive here messages from other primaries.
@@ -380,6 +636,13 @@ impl Core {
                         PrimaryMessage::Vote(vote) => {
                             match self.sanitize_vote(&vote) {
                                 Ok(()) => self.process_vote(vote).await,
+                                // The vote may conflict with one of its author for our header.
+                                Err(e @ DagError::UnexpectedVote(..)) => {
+                                    match self.sanitize_unexpected_vote(&vote) {
+                                        Ok(()) => self.check_vote(&vote).await.and(Err(e)),
+                                        _ => Err(e),
+                                    }
+                                },
                                 error => error
                             }
                         },
@@ -389,6 +652,12 @@ impl Core {
                                 error => error
                             }
                         },
+                        PrimaryMessage::Equivocation(proof) => {
+                            match self.sanitize_equivocation(&proof) {
+                                Ok(()) => self.process_equivocation(proof).await,
+                                error => error
+                            }
+                        },
                         _ => panic!("Unexpected core message")
                     }
                 },
@@ -403,7 +672,7 @@ impl Core {
                 Some(certificate) = self.rx_certificate_waiter.recv() => {
                     // Kiểm tra store để tránh duplicate processing từ certificate waiter loopback
                     let digest = certificate.digest();
-                    match self.store.read(digest.to_vec()).await {
+                    match self.certificates.read(&digest).await {
                         Ok(Some(_)) => {
                             debug!("Certificate {} from waiter already processed, skipping.", digest);
                             Ok(())
@@ -422,6 +691,7 @@ impl Core {
                     panic!("Storage failure: killing node.");
                 }
                 Err(e @ DagError::TooOld(..)) => debug!("{}", e),
+                Err(e @ DagError::TooNew(..)) => debug!("{}", e),
                 Err(e) => warn!("{}", e),
             }
 
@@ -431,6 +701,8 @@ impl Core {
                 let gc_round = round - self.gc_depth;
                 self.last_voted.retain(|k, _| k >= &gc_round);
                 self.processing.retain(|k, _| k >= &gc_round);
+                self.first_headers.retain(|k, _| k >= &gc_round);
+                self.first_votes.retain(|k, _| k >= &gc_round);
                 self.certificates_aggregators.retain(|k, _| k >= &gc_round);
                 self.cancel_handlers.retain(|k, _| k >= &gc_round);
                 self.gc_round = gc_round;
@@ -54,6 +54,12 @@ pub enum DagError {
     #[error("Parents of header {0} are not a quorum")]
     HeaderRequiresQuorum(Digest),
 
+    #[error("Invalid equivocation proof against {0}")]
+    InvalidEquivocationProof(PublicKey),
+
     #[error("Message {0} (round {1}) too old")]
     TooOld(Digest, Round),
+
+    #[error("Message {0} (round {1}) too far ahead")]
+    TooNew(Digest, Round),
 }
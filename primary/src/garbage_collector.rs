@@ -1,24 +1,31 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::messages::Certificate;
-use crate::primary::PrimaryWorkerMessage;
+use crate::primary::{PrimaryWorkerMessage, Round};
 use bytes::Bytes;
-use config::Committee;
-use crypto::PublicKey;
+use config::{Committee, WorkerId};
+use crypto::{Digest, PublicKey};
 use network::SimpleSender;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::{Receiver, Sender};
 
 /// Receives the highest round reached by consensus and update it for all tasks.
 pub struct GarbageCollector {
+    /// The public key of this primary.
+    name: PublicKey,
     /// The current consensus round (used for cleanup).
     consensus_round: Arc<AtomicU64>,
     /// Receives the ordered certificates from consensus.
     rx_consensus: Receiver<Certificate>,
+    /// Hands our own committed certificates to the `Proposer`.
+    tx_proposer: Sender<Certificate>,
+    /// Tells the `Pruner` the last committed round.
+    tx_pruner: Sender<Round>,
     /// The network addresses of our workers.
-    addresses: Vec<SocketAddr>,
-    /// A network sender to notify our workers of cleanup events.
+    addresses: HashMap<WorkerId, SocketAddr>,
+    /// A network sender to notify our workers of commit and cleanup events.
     network: SimpleSender,
 }
 
@@ -28,18 +35,26 @@ impl GarbageCollector {
         committee: &Committee,
         consensus_round: Arc<AtomicU64>,
         rx_consensus: Receiver<Certificate>,
+        tx_proposer: Sender<Certificate>,
+        tx_pruner: Sender<Round>,
     ) {
         let addresses = committee
-            .our_workers(name)
-            .expect("Our public key or worker id is not in the committee")
+            .authorities
+            .get(name)
+            .expect("Our public key is not in the committee")
+            .workers
             .iter()
-            .map(|x| x.primary_to_worker)
+            .map(|(id, x)| (*id, x.primary_to_worker))
             .collect();
 
+        let name = *name;
         tokio::spawn(async move {
             Self {
+                name,
                 consensus_round,
                 rx_consensus,
+                tx_proposer,
+                tx_pruner,
                 addresses,
                 network: SimpleSender::new(),
             }
@@ -51,20 +66,54 @@ impl GarbageCollector {
     async fn run(&mut self) {
         let mut last_committed_round = 0;
         while let Some(certificate) = self.rx_consensus.recv().await {
-            // TODO [issue #9]: Re-include batch digests that have not been sequenced into our next block.
+            // Let the proposer re-include the batch digests that have not been sequenced.
+            if certificate.origin() == self.name {
+                self.tx_proposer
+                    .send(certificate.clone())
+                    .await
+                    .expect("Failed to send certificate to the proposer");
+            }
 
+            // Tell our workers which of their batches are committed (so they can notify clients).
             let round = certificate.round();
+            let mut committed: HashMap<WorkerId, Vec<Digest>> = HashMap::new();
+            for (digest, worker_id) in &certificate.header.payload {
+                committed
+                    .entry(*worker_id)
+                    .or_default()
+                    .push(digest.clone());
+            }
+            for (worker_id, digests) in committed {
+                let address = match self.addresses.get(&worker_id) {
+                    Some(x) => *x,
+                    None => continue,
+                };
+                let message = PrimaryWorkerMessage::Committed(digests, round);
+                let bytes =
+                    bincode::serialize(&message).expect("Failed to serialize our own message");
+                self.network.send(address, Bytes::from(bytes)).await;
+            }
+
             if round > last_committed_round {
                 last_committed_round = round;
 
                 // Trigger cleanup on the primary.
                 self.consensus_round.store(round, Ordering::Relaxed);
 
+                // Trigger pruning of the store.
+                self.tx_pruner
+                    .send(round)
+                    .await
+                    .expect("Failed to send round to the pruner");
+
                 // Trigger cleanup on the workers..
                 let bytes = bincode::serialize(&PrimaryWorkerMessage::Cleanup(round))
                     .expect("Failed to serialize our own message");
                 self.network
-                    .broadcast(self.addresses.clone(), Bytes::from(bytes))
+                    .broadcast(
+                        self.addresses.values().cloned().collect(),
+                        Bytes::from(bytes),
+                    )
                     .await;
             }
         }
@@ -1,11 +1,12 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::error::{DagError, DagResult};
-use crate::messages::Header;
+use crate::messages::{Certificate, Header};
+use crate::payload_receiver::PayloadReceiver;
 use crate::primary::{PrimaryMessage, PrimaryWorkerMessage, Round};
 use bytes::Bytes;
 use config::{Committee, WorkerId};
 use crypto::{Digest, PublicKey};
-use futures::future::try_join_all;
+use futures::future::{try_join, try_join_all};
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
 use log::{debug, error};
@@ -14,7 +15,7 @@ use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use store::Store;
+use store::{Store, Table, CERTIFICATES, INDICES};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::time::{sleep, Duration, Instant};
 
@@ -35,12 +36,18 @@ pub struct HeaderWaiter {
     name: PublicKey,
     /// The committee information.
     committee: Committee,
-    /// The persistent storage.
-    store: Store,
+    /// The certificates we processed.
+    certificates: Table<Digest, Certificate>,
+    /// The store indices (to check which batches our workers hold).
+    indices: Store,
     /// The current consensus round (used for cleanup).
     consensus_round: Arc<AtomicU64>,
     /// The depth of the garbage collector.
     gc_depth: Round,
+    /// The highest round of the certificates we received (written by the `Core`).
+    certified_round: Arc<AtomicU64>,
+    /// How far ahead of `certified_round` we accept to sync headers.
+    max_round_lookahead: Round,
     /// The delay to wait before re-trying sync requests.
     sync_retry_delay: u64,
     /// Determine with how many nodes to sync when re-trying to send sync-request.
@@ -61,7 +68,12 @@ pub struct HeaderWaiter {
     batch_requests: HashMap<Digest, Round>,
     /// List of digests (either certificates, headers or tx batch) that are waiting
     /// to be processed. Their processing will resume when we get all their dependencies.
-    pending: HashMap<Digest, (Round, Sender<()>)>,
+    pending: HashMap<Digest, (Round, PublicKey, Sender<()>)>,
+    /// The number of headers of each author in `pending`.
+    pending_per_author: HashMap<PublicKey, usize>,
+    /// The maximum number of headers of a single author that may wait for their dependencies.
+    /// Honest authorities make one header per round, so this is the number of rounds we track.
+    max_pending_per_author: usize,
 }
 
 impl HeaderWaiter {
@@ -72,6 +84,8 @@ impl HeaderWaiter {
         store: Store,
         consensus_round: Arc<AtomicU64>,
         gc_depth: Round,
+        certified_round: Arc<AtomicU64>,
+        max_round_lookahead: Round,
         sync_retry_delay: u64,
         sync_retry_nodes: usize,
         rx_synchronizer: Receiver<WaiterMessage>,
@@ -81,9 +95,12 @@ impl HeaderWaiter {
             Self {
                 name,
                 committee,
-                store,
+                certificates: Table::new(&store, CERTIFICATES),
+                indices: store.column(INDICES),
                 consensus_round,
                 gc_depth,
+                certified_round,
+                max_round_lookahead,
                 sync_retry_delay,
                 sync_retry_nodes,
                 rx_synchronizer,
@@ -92,31 +109,82 @@ impl HeaderWaiter {
                 parent_requests: HashMap::new(),
                 batch_requests: HashMap::new(),
                 pending: HashMap::new(),
+                pending_per_author: HashMap::new(),
+                max_pending_per_author: (gc_depth + max_round_lookahead) as usize,
             }
             .run()
             .await;
         });
     }
 
-    /// Helper function. It waits for particular data to become available in the storage
-    /// and then delivers the specified header.
+    /// Helper function. It waits for the missing parents and payload markers to become available
+    /// in the storage and then delivers the specified header.
     async fn waiter(
-        mut missing: Vec<(Vec<u8>, Store)>,
+        mut parents: Vec<(Digest, Table<Digest, Certificate>)>,
+        mut batches: Vec<(Vec<u8>, Store)>,
         deliver: Header,
         mut handler: Receiver<()>,
     ) -> DagResult<Option<Header>> {
-        let waiting: Vec<_> = missing
+        let waiting_parents: Vec<_> = parents.iter_mut().map(|(x, y)| y.notify_read(x)).collect();
+        let waiting_batches: Vec<_> = batches
             .iter_mut()
             .map(|(x, y)| y.notify_read(x.to_vec()))
             .collect();
         tokio::select! {
-            result = try_join_all(waiting) => {
+            result = try_join(try_join_all(waiting_parents), try_join_all(waiting_batches)) => {
                 result.map(|_| Some(deliver)).map_err(DagError::from)
             }
             _ = handler.recv() => Ok(None),
         }
     }
 
+    /// Checks whether we should sync the dependencies of a header. We sync only once per header,
+    /// only up to `max_round_lookahead` rounds ahead, and only a bounded number of headers per
+    /// author (bad nodes may send many headers for each round).
+    fn admit(&self, header: &Header) -> bool {
+        if self.pending.contains_key(&header.id) {
+            return false;
+        }
+        let certified_round = self.certified_round.load(Ordering::Relaxed);
+        if header.round > certified_round + self.max_round_lookahead {
+            debug!(
+                "Not synching {}: round {} is too far ahead",
+                header, header.round
+            );
+            return false;
+        }
+        let pending = self.pending_per_author.get(&header.author).unwrap_or(&0);
+        if *pending >= self.max_pending_per_author {
+            debug!(
+                "Not synching {}: too many pending headers from its author",
+                header
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Adds a header to the waiter pool and returns its cancel handler.
+    fn add_pending(&mut self, header: &Header) -> Receiver<()> {
+        let (tx_cancel, rx_cancel) = channel(1);
+        self.pending
+            .insert(header.id.clone(), (header.round, header.author, tx_cancel));
+        *self.pending_per_author.entry(header.author).or_insert(0) += 1;
+        rx_cancel
+    }
+
+    /// Removes a header from the waiter pool and returns its cancel handler.
+    fn remove_pending(&mut self, digest: &Digest) -> Option<Sender<()>> {
+        let (_, author, handler) = self.pending.remove(digest)?;
+        if let Some(count) = self.pending_per_author.get_mut(&author) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending_per_author.remove(&author);
+            }
+        }
+        Some(handler)
+    }
+
     /// Main loop listening to the `Synchronizer` messages.
     async fn run(&mut self) {
         let mut waiting = FuturesUnordered::new();
@@ -130,25 +198,26 @@ impl HeaderWaiter {
                     match message {
                         WaiterMessage::SyncBatches(missing, header) => {
                             debug!("Synching the payload of {}", header);
-                            let header_id = header.id.clone();
                             let round = header.round;
                             let author = header.author;
 
-                            // Ensure we sync only once per header.
-                            if self.pending.contains_key(&header_id) {
+                            // Ensure we sync only once per header, and not for too many headers.
+                            if !self.admit(&header) {
                                 continue;
                             }
 
                             // Add the header to the waiter pool. The waiter will return it to when all
                             // its parents are in the store.
                             let wait_for = missing
-                                .keys() // Chỉ cần lấy digest từ HashMap
-                                .map(|digest| (digest.to_vec(), self.store.clone()))
+                                .iter()
+                                .map(|(digest, worker_id)| {
+                                    let key = PayloadReceiver::key(digest, *worker_id);
+                                    (key, self.indices.clone())
+                                })
                                 .collect();
 
-                            let (tx_cancel, rx_cancel) = channel(1);
-                            self.pending.insert(header_id, (round, tx_cancel));
-                            let fut = Self::waiter(wait_for, header, rx_cancel);
+                            let rx_cancel = self.add_pending(&header);
+                            let fut = Self::waiter(Vec::new(), wait_for, header, rx_cancel);
                             waiting.push(fut);
 
                             // Ensure we didn't already send a sync request for these parents.
@@ -173,12 +242,11 @@ impl HeaderWaiter {
 
                         WaiterMessage::SyncParents(missing, header) => {
                             debug!("Synching the parents of {}", header);
-                            let header_id = header.id.clone();
                             let round = header.round;
                             let author = header.author;
 
-                            // Ensure we sync only once per header.
-                            if self.pending.contains_key(&header_id) {
+                            // Ensure we sync only once per header, and not for too many headers.
+                            if !self.admit(&header) {
                                 continue;
                             }
 
@@ -187,11 +255,10 @@ impl HeaderWaiter {
                             let wait_for = missing
                                 .iter()
                                 .cloned()
-                                .map(|x| (x.to_vec(), self.store.clone()))
+                                .map(|x| (x, self.certificates.clone()))
                                 .collect();
-                            let (tx_cancel, rx_cancel) = channel(1);
-                            self.pending.insert(header_id, (round, tx_cancel));
-                            let fut = Self::waiter(wait_for, header, rx_cancel);
+                            let rx_cancel = self.add_pending(&header);
+                            let fut = Self::waiter(wait_for, Vec::new(), header, rx_cancel);
                             waiting.push(fut);
 
                             // Ensure we didn't already sent a sync request for these parents.
@@ -223,7 +290,7 @@ impl HeaderWaiter {
 
                 Some(result) = waiting.next() => match result {
                     Ok(Some(header)) => {
-                        let _ = self.pending.remove(&header.id);
+                        let _ = self.remove_pending(&header.id);
                         for x in header.payload.keys() {
                             let _ = self.batch_requests.remove(x);
                         }
@@ -277,12 +344,17 @@ impl HeaderWaiter {
             if round > self.gc_depth {
                 let mut gc_round = round - self.gc_depth;
 
-                for (r, handler) in self.pending.values() {
-                    if r <= &gc_round {
+                let expired: Vec<_> = self
+                    .pending
+                    .iter()
+                    .filter(|(_, (r, _, _))| r <= &gc_round)
+                    .map(|(digest, _)| digest.clone())
+                    .collect();
+                for digest in expired {
+                    if let Some(handler) = self.remove_pending(&digest) {
                         let _ = handler.send(()).await;
                     }
                 }
-                self.pending.retain(|_, (r, _)| r > &mut gc_round);
                 self.batch_requests.retain(|_, r| r > &mut gc_round);
                 self.parent_requests.retain(|_, (r, _)| r > &mut gc_round);
             }
@@ -1,19 +1,20 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use crate::messages::Certificate;
 use crate::primary::PrimaryMessage;
 use bytes::Bytes;
 use config::Committee;
 use crypto::{Digest, PublicKey};
 use log::{error, warn};
 use network::SimpleSender;
-use store::Store;
+use store::{Store, Table, CERTIFICATES};
 use tokio::sync::mpsc::Receiver;
 
 /// A task dedicated to help other authorities by replying to their certificates requests.
 pub struct Helper {
     /// The committee information.
     committee: Committee,
-    /// The persistent storage.
-    store: Store,
+    /// The certificates we processed.
+    certificates: Table<Digest, Certificate>,
     /// Input channel to receive certificates requests.
     rx_primaries: Receiver<(Vec<Digest>, PublicKey)>,
     /// A network sender to reply to the sync requests.
@@ -29,7 +30,7 @@ impl Helper {
         tokio::spawn(async move {
             Self {
                 committee,
-                store,
+                certificates: Table::new(&store, CERTIFICATES),
                 rx_primaries,
                 network: SimpleSender::new(),
             }
@@ -53,11 +54,9 @@ impl Helper {
 
             // Reply to the request (the best we can).
             for digest in digests {
-                match self.store.read(digest.to_vec()).await {
-                    Ok(Some(data)) => {
+                match self.certificates.read(&digest).await {
+                    Ok(Some(certificate)) => {
                         // TODO: Remove this deserialization-serialization in the critical path.
-                        let certificate = bincode::deserialize(&data)
-                            .expect("Failed to deserialize our own certificate");
                         let bytes = bincode::serialize(&PrimaryMessage::Certificate(certificate))
                             .expect("Failed to serialize our own certificate");
                         self.network.send(address, Bytes::from(bytes)).await;
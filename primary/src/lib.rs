@@ -2,20 +2,31 @@
 #[macro_use]
 mod error;
 mod aggregators;
+mod certificate_index;
 mod certificate_waiter;
 mod core;
 mod garbage_collector;
 mod header_waiter;
 mod helper;
 mod messages;
+mod payload_cache;
 mod payload_receiver;
 mod primary;
 mod proposer;
+mod pruner;
 mod synchronizer;
 
 #[cfg(test)]
 #[path = "tests/common.rs"]
 mod common;
 
-pub use crate::messages::{Certificate, Header};
-pub use crate::primary::{Primary, PrimaryWorkerMessage, Round, WorkerPrimaryMessage};
+#[cfg(test)]
+#[path = "tests/adversary.rs"]
+mod adversary;
+
+pub use crate::certificate_index::CertificateIndex;
+pub use crate::messages::{Certificate, EquivocationProof, Header};
+pub use crate::payload_cache::{PayloadCache, PayloadCacheMetrics};
+pub use crate::primary::{
+    Primary, PrimaryWorkerMessage, Round, WorkerPrimaryMessage, LAST_COMMITTED_ROUND_KEY,
+};
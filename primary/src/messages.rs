@@ -2,7 +2,7 @@
 use crate::error::{DagError, DagResult};
 use crate::primary::Round;
 use config::{Committee, WorkerId};
-use crypto::{Digest, Hash, PublicKey, Signature, SignatureService};
+use crypto::{Digest, Hash, PublicKey, Signature, SignatureService, SignatureShare};
 use ed25519_dalek::Digest as _;
 use ed25519_dalek::Sha512;
 use serde::{Deserialize, Serialize};
@@ -16,6 +16,9 @@ pub struct Header {
     pub round: Round,
     pub payload: BTreeMap<Digest, WorkerId>,
     pub parents: BTreeSet<Digest>,
+    /// The author's share of the common coin of this round (checked by the consensus when it
+    /// flips the coin).
+    pub coin: Option<SignatureShare>,
     pub id: Digest,
     pub signature: Signature,
 }
@@ -26,6 +29,7 @@ impl Header {
         round: Round,
         payload: BTreeMap<Digest, WorkerId>,
         parents: BTreeSet<Digest>,
+        coin: Option<SignatureShare>,
         signature_service: &mut SignatureService,
     ) -> Self {
         let header = Self {
@@ -33,6 +37,7 @@ impl Header {
             round,
             payload,
             parents,
+            coin,
             id: Digest::default(),
             signature: Signature::default(),
         };
@@ -65,6 +70,11 @@ impl Header {
             .verify(&self.id, &self.author)
             .map_err(DagError::from)
     }
+
+    /// Returns the message signed by the coin shares of the headers of a round.
+    pub fn coin_message(round: Round) -> [u8; 8] {
+        round.to_le_bytes()
+    }
 }
 
 impl Hash for Header {
@@ -79,6 +89,9 @@ impl Hash for Header {
         for x in &self.parents {
             hasher.update(x);
         }
+        if let Some(coin) = &self.coin {
+            hasher.update(coin.to_bytes());
+        }
         Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
     }
 }
@@ -254,3 +267,89 @@ impl PartialEq for Certificate {
         ret
     }
 }
+
+/// Evidence that an authority signed two conflicting messages for the same round. It only holds
+/// messages signed by the offender, so anyone can check it.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum EquivocationProof {
+    /// Two different headers of the same author and round.
+    Headers(Box<Header>, Box<Header>),
+    /// Two votes of the same author for different headers of the same origin and round.
+    Votes(Box<Vote>, Box<Vote>),
+}
+
+impl EquivocationProof {
+    /// The authority that equivocated.
+    pub fn offender(&self) -> PublicKey {
+        match self {
+            Self::Headers(x, _) => x.author,
+            Self::Votes(x, _) => x.author,
+        }
+    }
+
+    /// The round of the conflicting messages.
+    pub fn round(&self) -> Round {
+        match self {
+            Self::Headers(x, _) => x.round,
+            Self::Votes(x, _) => x.round,
+        }
+    }
+
+    pub fn verify(&self, committee: &Committee) -> DagResult<()> {
+        // Ensure the two messages conflict.
+        let conflicting = match self {
+            Self::Headers(x, y) => x.author == y.author && x.round == y.round && x.id != y.id,
+            Self::Votes(x, y) => {
+                x.author == y.author && x.origin == y.origin && x.round == y.round && x.id != y.id
+            }
+        };
+        ensure!(
+            conflicting,
+            DagError::InvalidEquivocationProof(self.offender())
+        );
+
+        // Check both messages (and their signatures).
+        match self {
+            Self::Headers(x, y) => x.verify(committee).and_then(|()| y.verify(committee)),
+            Self::Votes(x, y) => x.verify(committee).and_then(|()| y.verify(committee)),
+        }
+    }
+}
+
+impl Hash for EquivocationProof {
+    fn digest(&self) -> Digest {
+        let (x, y) = match self {
+            Self::Headers(x, y) => (x.digest(), y.digest()),
+            Self::Votes(x, y) => (x.digest(), y.digest()),
+        };
+        let mut hasher = Sha512::new();
+        hasher.update(&x);
+        hasher.update(&y);
+        Digest(hasher.finalize()[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for EquivocationProof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::Headers(x, y) => write!(
+                f,
+                "{}: E{}({}, {}, {})",
+                self.digest(),
+                x.round,
+                x.author,
+                x.id,
+                y.id
+            ),
+            Self::Votes(x, y) => write!(
+                f,
+                "{}: E{}({}, {}, {})",
+                self.digest(),
+                x.round,
+                x.author,
+                x.id,
+                y.id
+            ),
+        }
+    }
+}
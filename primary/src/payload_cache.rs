@@ -0,0 +1,163 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::primary::Round;
+use config::WorkerId;
+use crypto::Digest;
+use log::debug;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+#[path = "tests/payload_cache_tests.rs"]
+pub mod payload_cache_tests;
+
+/// The memory taken by one entry of the cache (and of its eviction index).
+const ENTRY_SIZE: usize = 2 * size_of::<Digest>() + size_of::<WorkerId>() + size_of::<Round>();
+
+/// The hit rate of the cache.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadCacheMetrics {
+    /// The number of lookups that found the batch in the cache.
+    pub hits: u64,
+    /// The number of lookups that had to fall back to the store.
+    pub misses: u64,
+    /// The number of entries evicted (either garbage collected or to stay within budget).
+    pub evictions: u64,
+    /// The number of entries currently in the cache.
+    pub entries: usize,
+}
+
+impl PayloadCacheMetrics {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+struct Inner {
+    /// The batches' digests, along with the worker holding them and the consensus round at
+    /// which we heard of them.
+    entries: HashMap<Digest, (WorkerId, Round)>,
+    /// The digests of `entries` indexed by the consensus round at which we heard of them.
+    by_round: BTreeMap<Round, VecDeque<Digest>>,
+    /// The last round we garbage collected.
+    gc_round: Round,
+    metrics: PayloadCacheMetrics,
+}
+
+/// Keeps in memory the digests of the batches our workers hold, so that we do not read the store
+/// to check the payload of every header. The cache holds at most `budget` bytes and forgets the
+/// batches we heard of `gc_depth` rounds before the current consensus round; the store remains
+/// the source of truth for everything evicted.
+#[derive(Clone)]
+pub struct PayloadCache {
+    inner: Arc<Mutex<Inner>>,
+    /// The maximum memory taken by the cache. Denominated in bytes.
+    budget: usize,
+    /// The current consensus round (used for cleanup).
+    consensus_round: Arc<AtomicU64>,
+    /// The depth of the garbage collector.
+    gc_depth: Round,
+}
+
+impl PayloadCache {
+    pub fn new(budget: usize, consensus_round: Arc<AtomicU64>, gc_depth: Round) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: HashMap::new(),
+                by_round: BTreeMap::new(),
+                gc_round: 0,
+                metrics: PayloadCacheMetrics::default(),
+            })),
+            budget,
+            consensus_round,
+            gc_depth,
+        }
+    }
+
+    /// Records that our worker `worker_id` holds the batch `digest`.
+    pub fn insert(&self, digest: Digest, worker_id: WorkerId) {
+        let round = self.consensus_round.load(Ordering::Relaxed);
+        let mut inner = self.inner.lock().unwrap();
+        self.cleanup(&mut inner, round);
+
+        if let Some((_, r)) = inner.entries.insert(digest.clone(), (worker_id, round)) {
+            // We already knew this batch: only move it to its new round.
+            if let Some(digests) = inner.by_round.get_mut(&r) {
+                digests.retain(|x| x != &digest);
+            }
+        }
+        inner.by_round.entry(round).or_default().push_back(digest);
+
+        // Evict the oldest entries until we are within budget.
+        while inner.entries.len() * ENTRY_SIZE > self.budget {
+            let mut oldest = match inner.by_round.first_entry() {
+                Some(x) => x,
+                None => break,
+            };
+            let digest = oldest.get_mut().pop_front();
+            if oldest.get().is_empty() {
+                oldest.remove();
+            }
+            if let Some(digest) = digest {
+                inner.entries.remove(&digest);
+                inner.metrics.evictions += 1;
+            }
+        }
+        inner.metrics.entries = inner.entries.len();
+    }
+
+    /// Checks whether our worker `worker_id` holds the batch `digest`. A miss only means that the
+    /// caller should check the store.
+    pub fn contains(&self, digest: &Digest, worker_id: &WorkerId) -> bool {
+        let round = self.consensus_round.load(Ordering::Relaxed);
+        let mut inner = self.inner.lock().unwrap();
+        self.cleanup(&mut inner, round);
+
+        let hit = matches!(inner.entries.get(digest), Some((x, _)) if x == worker_id);
+        match hit {
+            true => inner.metrics.hits += 1,
+            false => inner.metrics.misses += 1,
+        }
+        hit
+    }
+
+    /// Returns the current metrics of the cache.
+    pub fn metrics(&self) -> PayloadCacheMetrics {
+        self.inner.lock().unwrap().metrics.clone()
+    }
+
+    /// Forgets the batches we heard of before the garbage collection round.
+    fn cleanup(&self, inner: &mut Inner, round: Round) {
+        if round <= self.gc_depth || round - self.gc_depth <= inner.gc_round {
+            return;
+        }
+        inner.gc_round = round - self.gc_depth;
+        let expired: Vec<_> = inner
+            .by_round
+            .range(..inner.gc_round)
+            .map(|(r, _)| *r)
+            .collect();
+        for r in expired {
+            Self::evict(inner, r);
+        }
+        inner.metrics.entries = inner.entries.len();
+        debug!(
+            "Payload cache: {} entries, hit rate {:.2} ({:?})",
+            inner.entries.len(),
+            inner.metrics.hit_rate(),
+            inner.metrics
+        );
+    }
+
+    /// Removes all the entries of the specified round.
+    fn evict(inner: &mut Inner, round: Round) {
+        for digest in inner.by_round.remove(&round).unwrap_or_default() {
+            inner.entries.remove(&digest);
+            inner.metrics.evictions += 1;
+        }
+    }
+}
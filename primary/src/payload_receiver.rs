@@ -1,34 +1,64 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
-use crate::primary::PayloadCache; // <--- THÊM USE
+use crate::payload_cache::PayloadCache;
 use config::WorkerId;
 use crypto::Digest;
-use store::Store;
+use log::error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{RoundIndex, Store, WriteBatch, INDICES};
 use tokio::sync::mpsc::Receiver;
 
+/// Receives batches' digests of other authorities. These are only needed to verify headers.
 pub struct PayloadReceiver {
-    store: Store,
+    /// The store indices (see `PayloadReceiver::key`).
+    indices: Store,
+    /// The round index of our markers, through which the `Pruner` deletes them.
+    index: RoundIndex,
+    /// The last committed round, under which we index our markers.
+    consensus_round: Arc<AtomicU64>,
     cache: PayloadCache, // <--- THÊM TRƯỜNG CACHE
-    rx_workers: Receiver<(Digest, WorkerId, Vec<u8>)>,
+    rx_workers: Receiver<(Digest, WorkerId)>,
 }
 
 impl PayloadReceiver {
     pub fn spawn(
         store: Store,
+        consensus_round: Arc<AtomicU64>,
         cache: PayloadCache, // <--- NHẬN CACHE
-        rx_workers: Receiver<(Digest, WorkerId, Vec<u8>)>,
+        rx_workers: Receiver<(Digest, WorkerId)>,
     ) {
         tokio::spawn(async move {
-            Self { store, cache, rx_workers }.run().await;
+            Self {
+                indices: store.column(INDICES),
+                index: RoundIndex::new(&store, INDICES),
+                consensus_round,
+                cache,
+                rx_workers,
+            }
+            .run()
+            .await;
         });
     }
 
+    /// The store key indicating that our worker `worker_id` holds the batch `digest`.
+    pub fn key(digest: &Digest, worker_id: WorkerId) -> Vec<u8> {
+        [digest.as_ref(), &worker_id.to_le_bytes()].concat()
+    }
+
     async fn run(&mut self) {
-        while let Some((digest, _worker_id, batch)) = self.rx_workers.recv().await {
+        while let Some((digest, worker_id)) = self.rx_workers.recv().await {
             // Ghi vào cache (nhanh)
-            self.cache.insert(digest.clone(), batch.clone());
+            self.cache.insert(digest.clone(), worker_id);
 
-            // Ghi vào store để lưu trữ lâu dài (chậm)
-            self.store.write(digest.to_vec(), batch).await;
+            // Ghi vào store để lưu trữ lâu dài (chậm). Bản thân batch vẫn nằm ở worker.
+            let key = Self::key(&digest, worker_id);
+            let round = self.consensus_round.load(Ordering::Relaxed);
+            let mut batch = WriteBatch::default();
+            batch.put(&self.indices, key.clone(), Vec::default());
+            self.index.insert(&mut batch, round, &key);
+            if let Err(e) = self.indices.write_all(batch).await {
+                error!("Failed to store the marker of batch {}: {}", digest, e);
+            }
         }
     }
-}
\ No newline at end of file
+}
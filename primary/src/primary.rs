@@ -5,9 +5,11 @@ use crate::error::DagError;
 use crate::garbage_collector::GarbageCollector;
 use crate::header_waiter::HeaderWaiter;
 use crate::helper::Helper;
-use crate::messages::{Certificate, Header, Vote};
+use crate::messages::{Certificate, EquivocationProof, Header, Vote};
+use crate::payload_cache::PayloadCache;
 use crate::payload_receiver::PayloadReceiver;
 use crate::proposer::Proposer;
+use crate::pruner::Pruner;
 use crate::synchronizer::Synchronizer;
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -19,11 +21,13 @@ use network::{MessageHandler, Receiver as NetworkReceiver, Writer};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::sync::atomic::AtomicU64;
-use dashmap::DashMap;
 use std::sync::Arc;
 use store::Store;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
-pub type PayloadCache = Arc<DashMap<Digest, Vec<u8>>>;
+
+#[cfg(test)]
+#[path = "tests/primary_tests.rs"]
+pub mod primary_tests;
 
 /// The default channel capacity for each channel of the primary.
 pub const CHANNEL_CAPACITY: usize = 1_000;
@@ -31,6 +35,11 @@ pub const CHANNEL_CAPACITY: usize = 1_000;
 /// The round number.
 pub type Round = u64;
 
+/// The key of the last round committed by the consensus in the `CONSENSUS` column family. The
+/// consensus writes it along with its state, and the `Core` reads it to skip the certificates of
+/// the rounds already committed.
+pub const LAST_COMMITTED_ROUND_KEY: &[u8] = b"last_committed_round";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum PrimaryMessage {
     // Đề xuất header mới
@@ -41,6 +50,8 @@ pub enum PrimaryMessage {
     Certificate(Certificate),
     // Yêu cầu đồng bộ
     CertificatesRequest(Vec<Digest>, /* requestor */ PublicKey),
+    // Bằng chứng một authority đã ký hai message mâu thuẫn
+    Equivocation(EquivocationProof),
 }
 
 /// The messages sent by the primary to its workers.
@@ -50,15 +61,21 @@ pub enum PrimaryWorkerMessage {
     Synchronize(Vec<Digest>, /* node mục tiêu */ PublicKey),
     /// Primary thông báo một round đã được xử lý xong để Worker dọn dẹp.
     Cleanup(Round),
+    /// Xin Worker một batch của nó. Worker trả lời bằng `Option<Vec<u8>>`: batch đã serialize
+    /// (nếu nó có batch này).
+    RequestBatch(Digest),
+    /// Primary báo cho Worker các batch (của worker này) vừa được consensus chốt, cùng với round
+    /// của certificate chứa chúng.
+    Committed(Vec<Digest>, Round),
 }
 
 /// The messages sent by the workers to their primary.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum WorkerPrimaryMessage {
     /// Worker thông báo nó đã tạo xong một batch mới.
-    OurBatch(Digest, WorkerId, Vec<u8>),
+    OurBatch(Digest, WorkerId),
     /// Worker thông báo nó đã nhận được digest của một batch từ một authority khác.
-    OthersBatch(Digest, WorkerId, Vec<u8>),
+    OthersBatch(Digest, WorkerId),
 }
 
 pub struct Primary;
@@ -71,19 +88,22 @@ impl Primary {
         store: Store,                        // Kết nối đến database để lưu trữ dữ liệu.
         tx_consensus: Sender<Certificate>,   // Kênh để GỬI certificate đã được chốt cho lớp Consensus.
         rx_consensus: Receiver<Certificate>, // Kênh để NHẬN feedback từ lớp Consensus (dùng cho garbage collection).
-    ) {
+        tx_equivocations: Sender<EquivocationProof>, // Kênh để GỬI bằng chứng equivocation cho ứng dụng.
+    ) -> PayloadCache {
         // Nhóm 1: Giao tiếp với Worker (Xử lý Batch Giao dịch)
         // Kênh cho các batch của node khác (Worker -> PayloadReceiver).
-        let (tx_others_digests, rx_others_digests) =
-            channel::<(Digest, WorkerId, Vec<u8>)>(CHANNEL_CAPACITY);
+        let (tx_others_digests, rx_others_digests) = channel(CHANNEL_CAPACITY);
         // Kênh cho các batch của chính mình (Worker -> Proposer).
-        let (tx_our_digests, rx_our_digests) =
-            channel::<(Digest, WorkerId, Vec<u8>)>(CHANNEL_CAPACITY);
+        let (tx_our_digests, rx_our_digests) = channel(CHANNEL_CAPACITY);
         // Nhóm 2: Vòng lặp Tạo Header Nội bộ (Core <-> Proposer)
         // Kênh gửi tín hiệu "đủ cha mẹ rồi, tạo header mới đi" (Core -> Proposer).
         let (tx_parents, rx_parents) = channel(CHANNEL_CAPACITY);
         // Kênh gửi header mới tạo (Proposer -> Core).
         let (tx_headers, rx_headers) = channel(CHANNEL_CAPACITY);
+        // Kênh gửi các certificate đã commit của chính mình (GarbageCollector -> Proposer).
+        let (tx_committed, rx_committed) = channel(CHANNEL_CAPACITY);
+        // Kênh gửi round đã commit cuối cùng (GarbageCollector -> Pruner).
+        let (tx_pruner, rx_pruner) = channel(CHANNEL_CAPACITY);
         // Nhóm 3: Xử lý Đồng bộ hóa (Khi bị thiếu dữ liệu)
         // Kênh gửi yêu cầu "đi tìm header/certificate bị thiếu" (Synchronizer -> Waiters).
         let (tx_sync_headers, rx_sync_headers) = channel(CHANNEL_CAPACITY);
@@ -95,7 +115,6 @@ impl Primary {
         // Kênh chính nhận message từ các primary khác (NetworkReceiver -> Core).
         let (tx_primary_messages, rx_primary_messages) = channel(CHANNEL_CAPACITY);
         let (tx_cert_requests, rx_cert_requests) = channel(CHANNEL_CAPACITY);
-        let payload_cache = Arc::new(DashMap::new());
 
         // Write the parameters to the logs.
         parameters.log();
@@ -103,11 +122,25 @@ impl Primary {
         // Parse the public and secret key of this authority.
         let name = keypair.name;
         let secret = keypair.secret;
+        let coin = keypair.coin.map(|x| x.secret);
 
         // Atomic variable use to synchronizer all tasks with the latest consensus round. This is only
         // used for cleanup. The only tasks that write into this variable is `GarbageCollector`.
         let consensus_round = Arc::new(AtomicU64::new(0));
 
+        // Atomic variable holding the highest round of the certificates we received. It bounds how
+        // far ahead we accept (and sync) headers and certificates. The only task that writes into
+        // this variable is the `Core`.
+        let certified_round = Arc::new(AtomicU64::new(0));
+
+        // The digests of the batches our workers hold, shared by the `PayloadReceiver` (writing
+        // into it) and the `Synchronizer` (reading it). It forgets what is garbage collected.
+        let payload_cache = PayloadCache::new(
+            parameters.payload_cache_size,
+            consensus_round.clone(),
+            parameters.gc_depth,
+        );
+
         // Spawn the network receiver listening to messages from the other primaries.
         let mut address = committee
             .primary(&name)
@@ -176,23 +209,38 @@ impl Primary {
             signature_service.clone(),
             consensus_round.clone(),
             parameters.gc_depth,
+            certified_round.clone(),
+            parameters.max_round_lookahead,
             /* Đầu vào: Nhận tin từ các Primary khác  rx_primaries */ rx_primary_messages,
             /* Đầu vào: Nhận header để xử lý lại rx_header_waiter*/ rx_headers_loopback,
             /* Đầu vào: Nhận certificate để xử lý lại rx_certificate_waiter */ rx_certificates_loopback,
             /* Đầu vào: Nhận header mới do mình tạo  rx_proposer*/ rx_headers,
             /* Đầu ra: Gửi certificate đã chốt cho Consensus */ tx_consensus,
+            /* Đầu ra: Gửi bằng chứng equivocation cho ứng dụng */ tx_equivocations,
             /* Đầu ra: Gửi tín hiệu tạo header mới  tx_proposer */ tx_parents,
         );
         // Khởi chạy 'bộ phận dọn dẹp'.
         // Lắng nghe round đã được đồng thuận cuối cùng từ Consensus (qua rx_consensus) và
         // ra lệnh cho các thành phần khác xóa dữ liệu cũ bằng cách cập nhật biến `consensus_round`.
-        GarbageCollector::spawn(&name, &committee, consensus_round.clone(), rx_consensus);
+        GarbageCollector::spawn(
+            &name,
+            &committee,
+            consensus_round.clone(),
+            rx_consensus,
+            /* tx_proposer */ tx_committed,
+            tx_pruner,
+        );
+
+        // The `Pruner` deletes from the store the headers, certificates and payload markers that are
+        // too old for anyone to need them.
+        Pruner::spawn(store.clone(), parameters.retention_depth, rx_pruner);
 
         // Khởi chạy 'bộ phận kho tạm' cho payload từ node khác.
         // Chỉ đơn giản là nhận batch digest (qua rx_others_digests) và lưu vào Store để xác thực sau này.
         // Receives batch digests from other workers. They are only used to validate headers.
         PayloadReceiver::spawn(
-            store.clone(), 
+            store.clone(),
+            consensus_round.clone(),
             payload_cache.clone(), // <--- TRUYỀN CACHE VÀO ĐÂY
             /* rx_workers */ rx_others_digests
         );
@@ -208,8 +256,10 @@ impl Primary {
             name,
             committee.clone(),
             store.clone(),
-            consensus_round,
+            consensus_round.clone(),
             parameters.gc_depth,
+            certified_round.clone(),
+            parameters.max_round_lookahead,
             parameters.sync_retry_delay,
             parameters.sync_retry_nodes,
             /*Đầu vào: Nhận yêu cầu sync rx_synchronizer */ rx_sync_headers,
@@ -222,6 +272,10 @@ impl Primary {
         // `Core` for further processing.
         CertificateWaiter::spawn(
             store.clone(),
+            consensus_round.clone(),
+            parameters.gc_depth,
+            certified_round,
+            parameters.max_round_lookahead,
             /* rx_synchronizer */ rx_sync_certificates,
             /* tx_core */ tx_certificates_loopback,
         );
@@ -237,11 +291,15 @@ impl Primary {
             name,
             &committee,
             signature_service,
+            coin,
             store.clone(),
             parameters.header_size,
             parameters.max_header_delay,
+            consensus_round,
+            parameters.gc_depth,
             /* rx_core */ rx_parents,
             /* rx_workers */ rx_our_digests,
+            /* rx_committed */ rx_committed,
             /* tx_core */ tx_headers,
         );
 
@@ -261,6 +319,9 @@ impl Primary {
                 .primary_to_primary
                 .ip()
         );
+
+        // Hand out the cache so the caller can monitor its hit rate.
+        payload_cache
     }
 }
 
@@ -303,8 +364,8 @@ impl MessageHandler for PrimaryReceiverHandler {
 /// Defines how the network receiver handles incoming workers messages.
 #[derive(Clone)]
 struct WorkerReceiverHandler {
-    tx_our_digests: Sender<(Digest, WorkerId, Vec<u8>)>,
-    tx_others_digests: Sender<(Digest, WorkerId, Vec<u8>)>,
+    tx_our_digests: Sender<(Digest, WorkerId)>,
+    tx_others_digests: Sender<(Digest, WorkerId)>,
 }
 
 #[async_trait]
@@ -317,15 +378,15 @@ impl MessageHandler for WorkerReceiverHandler {
         // Hàm này được gọi cho mỗi gói tin nhận được từ một Worker.
         match bincode::deserialize(&serialized).map_err(DagError::SerializationError)? {
             // Nếu là batch của mình, gửi đến kênh của Proposer.
-            WorkerPrimaryMessage::OurBatch(digest, worker_id, batch) => self
+            WorkerPrimaryMessage::OurBatch(digest, worker_id) => self
                 .tx_our_digests
-                .send((digest, worker_id, batch))
+                .send((digest, worker_id))
                 .await
                 .expect("Failed to send workers' digests"),
                // Nếu là batch của node khác, gửi đến kênh của PayloadReceiver.   
-            WorkerPrimaryMessage::OthersBatch(digest, worker_id, batch) => self
+            WorkerPrimaryMessage::OthersBatch(digest, worker_id) => self
                 .tx_others_digests
-                .send((digest, worker_id, batch))
+                .send((digest, worker_id))
                 .await
                 .expect("Failed to send workers' digests"),
         }
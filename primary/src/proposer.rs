@@ -3,11 +3,12 @@ use crate::messages::{Certificate, Header};
 use crate::primary::Round;
 use config::{Committee, WorkerId};
 use crypto::Hash as _;
-use crypto::{Digest, PublicKey, SignatureService};
-use log::debug;
-#[cfg(feature = "benchmark")]
-use log::info;
-use store::Store;
+use crypto::{Digest, PublicKey, SignatureService, ThresholdSecretKey};
+use log::{debug, error, info};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{Store, StoreError};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::{sleep, Duration, Instant};
 
@@ -21,17 +22,25 @@ pub struct Proposer {
     name: PublicKey,
     /// Service to sign headers.
     signature_service: SignatureService,
+    /// Our share of the common coin (if the keys were dealt one).
+    coin: Option<ThresholdSecretKey>,
     /// The persistent storage.
     store: Store,
     /// The size of the headers' payload.
     header_size: usize,
     /// The maximum delay to wait for batches' digests.
     max_header_delay: u64,
+    /// The current consensus round (used to find our headers that can no longer be committed).
+    consensus_round: Arc<AtomicU64>,
+    /// The depth of the garbage collector.
+    gc_depth: Round,
 
     /// Receives the parents to include in the next header (along with their round number).
     rx_core: Receiver<(Vec<Digest>, Round)>,
     /// Receives the batches' digests from our workers.
-    rx_workers: Receiver<(Digest, WorkerId, Vec<u8>)>,
+    rx_workers: Receiver<(Digest, WorkerId)>,
+    /// Receives our own certificates once the consensus committed them.
+    rx_committed: Receiver<Certificate>,
     /// Sends newly created headers to the `Core`.
     tx_core: Sender<Header>,
 
@@ -43,19 +52,28 @@ pub struct Proposer {
     digests: Vec<(Digest, WorkerId)>,
     /// Keeps track of the size (in bytes) of batches' digests that we received so far.
     payload_size: usize,
+    /// The payload of our headers that are not committed yet, by round.
+    uncommitted: BTreeMap<Round, Vec<(Digest, WorkerId)>>,
 }
 
 impl Proposer {
+    /// The store key of the last header we proposed.
+    pub const LAST_HEADER_KEY: &'static [u8] = b"last_header";
+
     #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         name: PublicKey,
         committee: &Committee,
         signature_service: SignatureService,
+        coin: Option<ThresholdSecretKey>,
         store: Store,
         header_size: usize,
         max_header_delay: u64,
+        consensus_round: Arc<AtomicU64>,
+        gc_depth: Round,
         rx_core: Receiver<(Vec<Digest>, Round)>,
-        rx_workers: Receiver<(Digest, WorkerId, Vec<u8>)>,
+        rx_workers: Receiver<(Digest, WorkerId)>,
+        rx_committed: Receiver<Certificate>,
         tx_core: Sender<Header>,
     ) {
         let genesis = Certificate::genesis(committee)
@@ -64,32 +82,63 @@ impl Proposer {
             .collect();
 
         tokio::spawn(async move {
-            Self {
+            let mut proposer = Self {
                 name,
                 signature_service,
+                coin,
                 store,
                 header_size,
                 max_header_delay,
+                consensus_round,
+                gc_depth,
                 rx_core,
                 rx_workers,
+                rx_committed,
                 tx_core,
                 round: 1,
                 last_parents: genesis,
                 digests: Vec::with_capacity(2 * header_size),
                 payload_size: 0,
-            }
-            .run()
-            .await;
+                uncommitted: BTreeMap::new(),
+            };
+            proposer.recover().await;
+            proposer.run().await;
         });
     }
 
-    async fn make_header(&mut self) {
+    /// Resumes after the last header we proposed before crashing (if any). We never propose another
+    /// header for the same round, so we wait for the parents of that round (or of a later one).
+    async fn recover(&mut self) {
+        let bytes = self
+            .store
+            .read(Self::LAST_HEADER_KEY.to_vec())
+            .await
+            .expect("Failed to read our last header");
+        if let Some(bytes) = bytes {
+            let header: Header =
+                bincode::deserialize(&bytes).expect("Failed to deserialize our last header");
+            info!("Resuming after our header of round {}", header.round);
+            self.round = header.round;
+            self.last_parents.clear();
+        }
+    }
+
+    /// Makes a new header and sends it to the `Core`. If we fail to persist it, we keep its
+    /// payload and parents for the next attempt, and send nothing.
+    async fn make_header(&mut self) -> Result<(), StoreError> {
+        // Sign our share of the coin of this round.
+        let coin = self
+            .coin
+            .as_ref()
+            .map(|secret| secret.sign(&Header::coin_message(self.round)));
+
         // Make a new header.
         let header = Header::new(
             self.name,
             self.round,
-            self.digests.drain(..).collect(),
-            self.last_parents.drain(..).collect(),
+            self.digests.iter().cloned().collect(),
+            self.last_parents.iter().cloned().collect(),
+            coin,
             &mut self.signature_service,
         )
         .await;
@@ -101,11 +150,46 @@ impl Proposer {
             info!("Created {} -> {:?}", header, digest);
         }
 
+        // Persist the header before broadcasting it, so that we never propose another one for the
+        // same round after a crash.
+        let bytes = bincode::serialize(&header).expect("Failed to serialize our header");
+        self.store
+            .write_batch(vec![(Self::LAST_HEADER_KEY.to_vec(), bytes)])
+            .await?;
+
+        // Remember its payload until it is committed.
+        let payload: Vec<_> = self.digests.drain(..).collect();
+        if !payload.is_empty() {
+            self.uncommitted.insert(self.round, payload);
+        }
+        self.last_parents.clear();
+        self.payload_size = 0;
+
         // Send the new header to the `Core` that will broadcast and process it.
         self.tx_core
             .send(header)
             .await
             .expect("Failed to send header");
+        Ok(())
+    }
+
+    /// Re-proposes the payload of our uncommitted headers below `round`. The consensus never
+    /// commits these headers: it skips certificates below a committed certificate of the same
+    /// author, as well as garbage collected ones. So their batches are never committed twice.
+    fn reinject(&mut self, round: Round) {
+        let pending = self.uncommitted.split_off(&round);
+        let lost = std::mem::replace(&mut self.uncommitted, pending);
+        for (r, digests) in lost {
+            debug!(
+                "Re-proposing the {} digest(s) of our header of round {}",
+                digests.len(),
+                r
+            );
+            for (digest, worker_id) in digests {
+                self.payload_size += digest.size();
+                self.digests.push((digest, worker_id));
+            }
+        }
     }
 
     // Main loop listening to incoming messages.
@@ -126,12 +210,18 @@ impl Proposer {
             let timer_expired = timer.is_elapsed();
             if (timer_expired || enough_digests) && enough_parents {
                 // Make a new header.
-                self.make_header().await;
-                self.payload_size = 0;
-
-                // Reschedule the timer.
-                let deadline = Instant::now() + Duration::from_millis(self.max_header_delay);
-                timer.as_mut().reset(deadline);
+                match self.make_header().await {
+                    Ok(()) => {
+                        // Reschedule the timer.
+                        let deadline =
+                            Instant::now() + Duration::from_millis(self.max_header_delay);
+                        timer.as_mut().reset(deadline);
+                    }
+                    Err(e) => error!(
+                        "Failed to persist our header of round {}: {}",
+                        self.round, e
+                    ),
+                }
             }
 
             tokio::select! {
@@ -147,17 +237,26 @@ impl Proposer {
                     // Signal that we have enough parent certificates to propose a new header.
                     self.last_parents = parents;
                 }
-                Some((digest, worker_id, batch)) = self.rx_workers.recv() => {
-                    // Store the batch in the primary's store for the `analyze` function to find.
-                    self.store.write(digest.to_vec(), batch).await;
-
+                Some((digest, worker_id)) = self.rx_workers.recv() => {
                     self.payload_size += digest.size();
                     self.digests.push((digest, worker_id));
                 }
-                () = &mut timer => {
+                Some(certificate) = self.rx_committed.recv() => {
+                    // Our headers of earlier rounds can no longer be committed.
+                    let _ = self.uncommitted.remove(&certificate.round());
+                    self.reinject(certificate.round());
+                }
+                // Once expired, the timer would fire again on every loop: only wait for parents.
+                () = &mut timer, if !timer.is_elapsed() => {
                     // Nothing to do.
                 }
             }
+
+            // Neither can our headers that fell out of the garbage collection window.
+            let round = self.consensus_round.load(Ordering::Relaxed);
+            if round > self.gc_depth {
+                self.reinject(round - self.gc_depth);
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,60 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::certificate_index::CertificateIndex;
+use crate::primary::Round;
+use log::{debug, error};
+use store::{RoundIndex, Store, CERTIFICATES, HEADERS, INDICES};
+use tokio::sync::mpsc::Receiver;
+
+#[cfg(test)]
+#[path = "tests/pruner_tests.rs"]
+pub mod pruner_tests;
+
+/// Deletes from the store the headers, certificates and payload markers (see `PayloadReceiver`),
+/// along with their index entries, more than `retention_depth` rounds older than the last
+/// committed round.
+pub struct Pruner {
+    /// The round indices of the headers and certificates (see `Core`), and of the payload markers.
+    indices: Vec<RoundIndex>,
+    /// The index of the certificates by round and author.
+    certificate_index: CertificateIndex,
+    /// How many rounds (before the last committed round) we keep.
+    retention_depth: Round,
+    /// Receives the last committed round from the `GarbageCollector`.
+    rx_round: Receiver<Round>,
+}
+
+impl Pruner {
+    pub fn spawn(store: Store, retention_depth: Round, rx_round: Receiver<Round>) {
+        tokio::spawn(async move {
+            Self {
+                indices: vec![
+                    RoundIndex::new(&store, HEADERS),
+                    RoundIndex::new(&store, CERTIFICATES),
+                    RoundIndex::new(&store, INDICES),
+                ],
+                certificate_index: CertificateIndex::new(&store),
+                retention_depth,
+                rx_round,
+            }
+            .run()
+            .await;
+        });
+    }
+
+    async fn run(&mut self) {
+        while let Some(round) = self.rx_round.recv().await {
+            if round <= self.retention_depth {
+                continue;
+            }
+            let prune_round = round - self.retention_depth;
+            for index in &mut self.indices {
+                match index.prune(prune_round).await {
+                    Ok(0) => (),
+                    Ok(pruned) => debug!("Pruned {} entries before round {}", pruned, prune_round),
+                    Err(e) => error!("Failed to prune the store: {}", e),
+                }
+            }
+            self.certificate_index.prune(prune_round).await;
+        }
+    }
+}
@@ -2,20 +2,24 @@
 use crate::error::DagResult;
 use crate::header_waiter::WaiterMessage;
 use crate::messages::{Certificate, Header};
+use crate::payload_cache::PayloadCache;
+use crate::payload_receiver::PayloadReceiver;
 use config::Committee;
 use crypto::Hash as _;
 use crypto::{Digest, PublicKey};
 use std::collections::HashMap;
-use store::Store;
+use store::{Store, Table, CERTIFICATES, INDICES};
 use tokio::sync::mpsc::Sender;
-use crate::primary::PayloadCache;
+
 /// The `Synchronizer` checks if we have all batches and parents referenced by a header. If we don't, it sends
 /// a command to the `Waiter` to request the missing data.
 pub struct Synchronizer {
     /// The public key of this primary.
     name: PublicKey,
-    /// The persistent storage.
-    store: Store,
+    /// The certificates we processed.
+    certificates: Table<Digest, Certificate>,
+    /// The store indices (to check which batches our workers hold).
+    indices: Store,
     /// Send commands to the `HeaderWaiter`.
     tx_header_waiter: Sender<WaiterMessage>,
     /// Send commands to the `CertificateWaiter`.
@@ -38,7 +42,8 @@ impl Synchronizer {
     ) -> Self {
         Self {
             name,
-            store,
+            certificates: Table::new(&store, CERTIFICATES),
+            indices: store.column(INDICES),
             cache,
             tx_header_waiter,
             tx_certificate_waiter,
@@ -60,12 +65,13 @@ impl Synchronizer {
         let mut missing = HashMap::new();
         for (digest, worker_id) in header.payload.iter() {
             // KIỂM TRA CACHE TRƯỚC
-            if self.cache.contains_key(digest) {
+            if self.cache.contains(digest, worker_id) {
                 continue; // Tìm thấy trong RAM, không cần làm gì thêm
             }
 
             // Nếu không có trong cache, kiểm tra store (phương án dự phòng)
-            if self.store.read(digest.to_vec()).await?.is_none() {
+            let key = PayloadReceiver::key(digest, *worker_id);
+            if self.indices.read(key).await?.is_none() {
                 missing.insert(digest.clone(), *worker_id);
             }
         }
@@ -97,8 +103,8 @@ impl Synchronizer {
                 continue;
             }
 
-            match self.store.read(digest.to_vec()).await? {
-                Some(certificate) => parents.push(bincode::deserialize(&certificate)?),
+            match self.certificates.read(digest).await? {
+                Some(certificate) => parents.push(certificate),
                 None => missing.push(digest.clone()),
             };
         }
@@ -122,7 +128,7 @@ impl Synchronizer {
                 continue;
             }
 
-            if self.store.read(digest.to_vec()).await?.is_none() {
+            if self.certificates.read(digest).await?.is_none() {
                 self.tx_certificate_waiter
                     .send(certificate.clone())
                     .await
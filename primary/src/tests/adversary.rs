@@ -0,0 +1,315 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::messages::{Certificate, Header, Vote};
+use crate::primary::{PrimaryMessage, Round};
+use async_trait::async_trait;
+use bytes::Bytes;
+use config::{Committee, KeyPair, Stake, WorkerId};
+use crypto::{Digest, Hash as _, PublicKey, Signature, SignatureService};
+use futures::sink::SinkExt as _;
+use log::debug;
+use network::{MessageHandler, Receiver as NetworkReceiver, SimpleSender, Writer};
+use rand::rngs::StdRng;
+use rand::{Rng as _, SeedableRng as _};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::net::SocketAddr;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::{interval, Duration};
+
+/// The number of random digests in each of the certificates requests of a flood.
+const FLOOD_SIZE: usize = 50;
+
+/// The ways an `Adversary` misbehaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attack {
+    /// Proposes two headers for each round: half of the committee receives one of them first, and
+    /// the other half the other one.
+    Equivocate,
+    /// Follows each of its votes with a vote for a header that does not exist.
+    UnknownVotes,
+    /// Keeps asking the others for certificates: random digests, but also the digests of all the
+    /// headers, certificates and batches it heard of.
+    FloodRequests,
+    /// Fills its headers with batches it never serves.
+    WithholdBatches,
+}
+
+/// A Byzantine primary. It follows the protocol just enough to take part in the DAG (it votes for
+/// the headers of the others and proposes its own headers once it has a quorum of parents), mounts
+/// the specified attacks, and never helps the others to sync.
+pub struct Adversary {
+    /// The public key of this authority.
+    name: PublicKey,
+    /// The committee information.
+    committee: Committee,
+    /// The attacks to mount.
+    attacks: Vec<Attack>,
+    /// Service to sign headers and votes.
+    signature_service: SignatureService,
+    /// Receives the messages of the other primaries.
+    rx_messages: Receiver<PrimaryMessage>,
+    /// A network sender (the adversary does not care about reliability).
+    network: SimpleSender,
+    /// Picks random digests.
+    rng: StdRng,
+    /// The last round we proposed headers for.
+    round: Round,
+    /// The certificates we know of, by round.
+    certificates: HashMap<Round, BTreeMap<PublicKey, Digest>>,
+    /// Our headers that are not certified yet, along with the votes they gathered.
+    headers: HashMap<Digest, (Header, Vec<(PublicKey, Signature)>)>,
+    /// The digests the others have in store.
+    known: Vec<Digest>,
+}
+
+impl Adversary {
+    pub fn spawn(keypair: KeyPair, committee: Committee, attacks: Vec<Attack>, seed: u64) {
+        let (tx_messages, rx_messages) = channel(1_000);
+
+        let mut address = committee
+            .primary(&keypair.name)
+            .expect("Our public key is not in the committee")
+            .primary_to_primary;
+        address.set_ip("0.0.0.0".parse().unwrap());
+        NetworkReceiver::spawn(address, AdversaryHandler { tx_messages });
+
+        let genesis = Certificate::genesis(&committee)
+            .into_iter()
+            .map(|x| (x.origin(), x.digest()))
+            .collect();
+        tokio::spawn(async move {
+            Self {
+                name: keypair.name,
+                committee,
+                attacks,
+                signature_service: SignatureService::new(keypair.secret),
+                rx_messages,
+                network: SimpleSender::new(),
+                rng: StdRng::seed_from_u64(seed),
+                round: 0,
+                certificates: [(0, genesis)].iter().cloned().collect(),
+                headers: HashMap::new(),
+                known: Vec::new(),
+            }
+            .run()
+            .await;
+        });
+    }
+
+    fn mounts(&self, attack: Attack) -> bool {
+        self.attacks.contains(&attack)
+    }
+
+    fn random_digest(&mut self) -> Digest {
+        Digest(self.rng.gen())
+    }
+
+    fn address(&self, name: &PublicKey) -> SocketAddr {
+        self.committee
+            .primary(name)
+            .expect("Unknown authority")
+            .primary_to_primary
+    }
+
+    fn others(&self) -> Vec<SocketAddr> {
+        self.committee
+            .others_primaries(&self.name)
+            .iter()
+            .map(|(_, x)| x.primary_to_primary)
+            .collect()
+    }
+
+    async fn send(&mut self, address: SocketAddr, message: &PrimaryMessage) {
+        let bytes = bincode::serialize(message).expect("Failed to serialize message");
+        self.network.send(address, Bytes::from(bytes)).await;
+    }
+
+    async fn broadcast(&mut self, message: &PrimaryMessage) {
+        let bytes = bincode::serialize(message).expect("Failed to serialize message");
+        let addresses = self.others();
+        self.network.broadcast(addresses, Bytes::from(bytes)).await;
+    }
+
+    /// Makes one of our headers and votes for it.
+    async fn make_header(
+        &mut self,
+        round: Round,
+        parents: BTreeSet<Digest>,
+        withhold: bool,
+    ) -> Header {
+        let mut payload = BTreeMap::new();
+        if withhold {
+            payload.insert(self.random_digest(), 0 as WorkerId);
+        }
+        let header = Header::new(
+            self.name,
+            round,
+            payload,
+            parents,
+            None,
+            &mut self.signature_service,
+        )
+        .await;
+        let vote = Vote::new(&header, &self.name, &mut self.signature_service).await;
+        let votes = vec![(self.name, vote.signature)];
+        self.headers
+            .insert(header.id.clone(), (header.clone(), votes));
+        header
+    }
+
+    /// Proposes our header(s) for the round following `round`, if we have a quorum of parents.
+    async fn propose(&mut self, round: Round) {
+        let parents = match self.certificates.get(&round) {
+            Some(x) if round + 1 > self.round => x.clone(),
+            _ => return,
+        };
+        let mut stake = 0;
+        let mut quorum = BTreeSet::new();
+        for (origin, digest) in &parents {
+            if stake < self.committee.quorum_threshold() {
+                stake += self.committee.stake(origin);
+                quorum.insert(digest.clone());
+            }
+        }
+        if stake < self.committee.quorum_threshold() {
+            return;
+        }
+        self.round = round + 1;
+        let withhold = self.mounts(Attack::WithholdBatches);
+        let all: BTreeSet<_> = parents.values().cloned().collect();
+
+        if !self.mounts(Attack::Equivocate) {
+            let header = self.make_header(self.round, all, withhold).await;
+            self.broadcast(&PrimaryMessage::Header(header)).await;
+            return;
+        }
+
+        // Make two different headers: one with a quorum of parents and one with all of them (or,
+        // if we only have a quorum of parents, with a batch we never serve).
+        let first = self.make_header(self.round, quorum.clone(), withhold).await;
+        let second = match all.len() > quorum.len() {
+            true => self.make_header(self.round, all, withhold).await,
+            false => self.make_header(self.round, quorum, true).await,
+        };
+        debug!("Equivocating with {:?} and {:?}", first, second);
+
+        let mut others = self.committee.others_primaries(&self.name);
+        others.sort_by_key(|(name, _)| *name);
+        let half = others.len() / 2;
+        for (i, (_, addresses)) in others.into_iter().enumerate() {
+            let (x, y) = match i < half {
+                true => (&first, &second),
+                false => (&second, &first),
+            };
+            let address = addresses.primary_to_primary;
+            self.send(address, &PrimaryMessage::Header(x.clone())).await;
+            self.send(address, &PrimaryMessage::Header(y.clone())).await;
+        }
+    }
+
+    async fn handle_header(&mut self, header: Header) {
+        if header.verify(&self.committee).is_err() || header.author == self.name {
+            return;
+        }
+        self.known.push(header.id.clone());
+        self.known.extend(header.payload.keys().cloned());
+
+        let address = self.address(&header.author);
+        let vote = Vote::new(&header, &self.name, &mut self.signature_service).await;
+        self.send(address, &PrimaryMessage::Vote(vote)).await;
+
+        if self.mounts(Attack::UnknownVotes) {
+            let unknown = Header {
+                id: self.random_digest(),
+                ..header
+            };
+            let vote = Vote::new(&unknown, &self.name, &mut self.signature_service).await;
+            self.send(address, &PrimaryMessage::Vote(vote)).await;
+        }
+    }
+
+    async fn handle_vote(&mut self, vote: Vote) {
+        if vote.verify(&self.committee).is_err() {
+            return;
+        }
+        let committee = &self.committee;
+        let (header, votes) = match self.headers.get_mut(&vote.id) {
+            Some(x) => x,
+            None => return,
+        };
+        if votes.iter().any(|(name, _)| name == &vote.author) {
+            return;
+        }
+        votes.push((vote.author, vote.signature));
+        let stake: Stake = votes.iter().map(|(name, _)| committee.stake(name)).sum();
+        if stake < self.committee.quorum_threshold() {
+            return;
+        }
+
+        let certificate = Certificate {
+            header: header.clone(),
+            votes: votes.clone(),
+        };
+        self.headers.remove(&vote.id);
+        debug!("Assembled {:?}", certificate);
+        self.broadcast(&PrimaryMessage::Certificate(certificate.clone()))
+            .await;
+        self.handle_certificate(certificate).await;
+    }
+
+    async fn handle_certificate(&mut self, certificate: Certificate) {
+        if certificate.verify(&self.committee).is_err() {
+            return;
+        }
+        let round = certificate.round();
+        self.known.push(certificate.digest());
+        self.certificates
+            .entry(round)
+            .or_default()
+            .insert(certificate.origin(), certificate.digest());
+        self.propose(round).await;
+    }
+
+    /// Asks everyone for certificates they do not have, and for a lot of data they do have.
+    async fn flood(&mut self) {
+        let mut digests: Vec<_> = (0..FLOOD_SIZE).map(|_| self.random_digest()).collect();
+        digests.extend(self.known.iter().rev().take(FLOOD_SIZE).cloned());
+        let request = PrimaryMessage::CertificatesRequest(digests, self.name);
+        self.broadcast(&request).await;
+    }
+
+    async fn run(&mut self) {
+        let mut timer = interval(Duration::from_millis(10));
+        self.propose(0).await;
+        loop {
+            tokio::select! {
+                Some(message) = self.rx_messages.recv() => match message {
+                    PrimaryMessage::Header(header) => self.handle_header(header).await,
+                    PrimaryMessage::Vote(vote) => self.handle_vote(vote).await,
+                    PrimaryMessage::Certificate(certificate) => {
+                        self.handle_certificate(certificate).await
+                    }
+                    PrimaryMessage::CertificatesRequest(..)
+                    | PrimaryMessage::Equivocation(..) => (),
+                },
+                _ = timer.tick(), if self.mounts(Attack::FloodRequests) => self.flood().await,
+            }
+        }
+    }
+}
+
+/// Acknowledges the messages of the other primaries and hands them to the adversary.
+#[derive(Clone)]
+struct AdversaryHandler {
+    tx_messages: Sender<PrimaryMessage>,
+}
+
+#[async_trait]
+impl MessageHandler for AdversaryHandler {
+    async fn dispatch(&self, writer: &mut Writer, serialized: Bytes) -> Result<(), Box<dyn Error>> {
+        let _ = writer.send(Bytes::from("Ack")).await;
+        let message = bincode::deserialize(&serialized)?;
+        let _ = self.tx_messages.send(message).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{certificate, headers};
+use crate::messages::Header;
+
+#[tokio::test]
+async fn scan_by_round() {
+    // Create a new test store.
+    let mut store = Store::new_in_memory();
+    let mut index = CertificateIndex::new(&store);
+
+    // Store (and index) one certificate per author for rounds 1 to 3.
+    let certificates: Table<Digest, Certificate> = Table::new(&store, CERTIFICATES);
+    let mut batch = WriteBatch::default();
+    for round in 1..=3 {
+        for header in headers() {
+            let certificate = certificate(&Header { round, ..header });
+            batch.insert(&certificates, &certificate.digest(), &certificate);
+            index.insert(&mut batch, &certificate);
+        }
+    }
+    store.write_all(batch).await.unwrap();
+
+    // Get all the certificates of a round, sorted by author.
+    let result = index.round(2).await.unwrap();
+    assert_eq!(result.len(), headers().len());
+    assert!(result.iter().all(|x| x.round() == 2));
+    assert!(result.windows(2).all(|x| x[0].origin() < x[1].origin()));
+
+    // Get the certificates of an author between two rounds.
+    let author = headers()[0].author;
+    let result = index.author(&author, 2..=3).await.unwrap();
+    let rounds: Vec<_> = result.iter().map(|x| x.round()).collect();
+    assert_eq!(rounds, vec![2, 3]);
+    assert!(result.iter().all(|x| x.origin() == author));
+
+    // Pruned rounds are no longer indexed.
+    index.prune(3).await;
+    assert!(index.round(2).await.unwrap().is_empty());
+    assert_eq!(
+        index.digests(0..=Round::MAX).await.unwrap().len(),
+        headers().len()
+    );
+}
@@ -3,11 +3,13 @@ use super::*;
 use crate::common::{
     certificate, committee, committee_with_base_port, header, headers, keys, listener, votes,
 };
-use dashmap::DashMap; // Thêm import cho DashMap
+use crate::header_waiter::WaiterMessage;
+use crate::payload_cache::PayloadCache;
+use crypto::Signature;
 use futures::future::try_join_all;
-use std::fs;
 use std::sync::Arc; // Thêm import cho Arc
 use tokio::sync::mpsc::channel;
+use tokio::time::{timeout, Duration};
 
 #[tokio::test]
 async fn process_header() {
@@ -25,12 +27,11 @@ async fn process_header() {
     let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
     let (_tx_headers, rx_headers) = channel(1);
     let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
     let (tx_parents, _rx_parents) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_process_header";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Make the vote we expect to receive.
     let expected = Vote::new(&header(), &name, &mut signature_service).await;
@@ -43,7 +44,7 @@ async fn process_header() {
     let handle = listener(address);
 
     // Make a synchronizer for the core.
-    let payload_cache = Arc::new(DashMap::new());
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
     let synchronizer = Synchronizer::new(
         name,
         &committee,
@@ -62,11 +63,14 @@ async fn process_header() {
         signature_service,
         /* consensus_round */ Arc::new(AtomicU64::new(0)),
         /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
         /* rx_primaries */ rx_primary_messages,
         /* rx_header_waiter */ rx_headers_loopback,
         /* rx_certificate_waiter */ rx_certificates_loopback,
         /* rx_proposer */ rx_headers,
         tx_consensus,
+        tx_equivocations,
         /* tx_proposer */ tx_parents,
     );
 
@@ -84,14 +88,85 @@ async fn process_header() {
     }
 
     // Ensure the header is correctly stored.
-    let stored = store
-        .read(header().id.to_vec())
-        .await
-        .unwrap()
-        .map(|x| bincode::deserialize(&x).unwrap());
+    let mut headers = Table::<Digest, Header>::new(&store, HEADERS);
+    let stored = headers.read(&header().id).await.unwrap();
     assert_eq!(stored, Some(header()));
 }
 
+#[tokio::test]
+async fn process_header_after_restart() {
+    let mut keys = keys();
+    let _ = keys.pop().unwrap(); // Skip the header' author.
+    let (name, secret) = keys.pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let committee = committee_with_base_port(13_200);
+
+    let (tx_sync_headers, _rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, _rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(1);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (_tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
+    let (tx_parents, _rx_parents) = channel(1);
+
+    // Create a new test store, recording that we voted for a header of this round before crashing.
+    let mut store = Store::new_in_memory();
+    let bytes = bincode::serialize(&header().round).unwrap();
+    store.write(Core::vote_key(&header().author), bytes).await;
+
+    // Spawn a listener that should not receive any vote.
+    let address = committee
+        .primary(&header().author)
+        .unwrap()
+        .primary_to_primary;
+    let handle = listener(address);
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee,
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    Core::spawn(
+        name,
+        committee,
+        store.clone(),
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Send a header to the core.
+    tx_primary_messages
+        .send(PrimaryMessage::Header(header()))
+        .await
+        .unwrap();
+
+    // Ensure the header is stored but that the core does not vote for it.
+    let mut headers = Table::<Digest, Header>::new(&store, HEADERS);
+    assert_eq!(headers.notify_read(&header().id).await.unwrap(), header());
+    assert!(timeout(Duration::from_millis(100), handle).await.is_err());
+}
+
 #[tokio::test]
 async fn process_header_missing_parent() {
     let (name, secret) = keys().pop().unwrap();
@@ -104,15 +179,14 @@ async fn process_header_missing_parent() {
     let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
     let (_tx_headers, rx_headers) = channel(1);
     let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
     let (tx_parents, _rx_parents) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_process_header_missing_parent";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Make a synchronizer for the core.
-    let payload_cache = Arc::new(DashMap::new());
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
     let synchronizer = Synchronizer::new(
         name,
         &committee(),
@@ -131,11 +205,14 @@ async fn process_header_missing_parent() {
         signature_service,
         /* consensus_round */ Arc::new(AtomicU64::new(0)),
         /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
         /* rx_primaries */ rx_primary_messages,
         /* rx_header_waiter */ rx_headers_loopback,
         /* rx_certificate_waiter */ rx_certificates_loopback,
         /* rx_proposer */ rx_headers,
         tx_consensus,
+        tx_equivocations,
         /* tx_proposer */ tx_parents,
     );
 
@@ -151,7 +228,8 @@ async fn process_header_missing_parent() {
         .unwrap();
 
     // Ensure the header is not stored.
-    assert!(store.read(id.to_vec()).await.unwrap().is_none());
+    let mut headers = store.column(HEADERS);
+    assert!(headers.read(id.to_vec()).await.unwrap().is_none());
 }
 
 #[tokio::test]
@@ -166,15 +244,14 @@ async fn process_header_missing_payload() {
     let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
     let (_tx_headers, rx_headers) = channel(1);
     let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
     let (tx_parents, _rx_parents) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_process_header_missing_payload";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Make a synchronizer for the core.
-    let payload_cache = Arc::new(DashMap::new());
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
     let synchronizer = Synchronizer::new(
         name,
         &committee(),
@@ -193,11 +270,14 @@ async fn process_header_missing_payload() {
         signature_service,
         /* consensus_round */ Arc::new(AtomicU64::new(0)),
         /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
         /* rx_primaries */ rx_primary_messages,
         /* rx_header_waiter */ rx_headers_loopback,
         /* rx_certificate_waiter */ rx_certificates_loopback,
         /* rx_proposer */ rx_headers,
         tx_consensus,
+        tx_equivocations,
         /* tx_proposer */ tx_parents,
     );
 
@@ -213,7 +293,8 @@ async fn process_header_missing_payload() {
         .unwrap();
 
     // Ensure the header is not stored.
-    assert!(store.read(id.to_vec()).await.unwrap().is_none());
+    let mut headers = store.column(HEADERS);
+    assert!(headers.read(id.to_vec()).await.unwrap().is_none());
 }
 
 #[tokio::test]
@@ -230,15 +311,14 @@ async fn process_votes() {
     let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
     let (_tx_headers, rx_headers) = channel(1);
     let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
     let (tx_parents, _rx_parents) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_process_vote";
-    let _ = fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Make a synchronizer for the core.
-    let payload_cache = Arc::new(DashMap::new());
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
     let synchronizer = Synchronizer::new(
         name,
         &committee,
@@ -257,11 +337,14 @@ async fn process_votes() {
         signature_service,
         /* consensus_round */ Arc::new(AtomicU64::new(0)),
         /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
         /* rx_primaries */ rx_primary_messages,
         /* rx_header_waiter */ rx_headers_loopback,
         /* rx_certificate_waiter */ rx_certificates_loopback,
         /* rx_proposer */ rx_headers,
         tx_consensus,
+        tx_equivocations,
         /* tx_proposer */ tx_parents,
     );
 
@@ -304,15 +387,14 @@ async fn process_certificates() {
     let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
     let (_tx_headers, rx_headers) = channel(1);
     let (tx_consensus, mut rx_consensus) = channel(3);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
     let (tx_parents, mut rx_parents) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_process_certificates";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Make a synchronizer for the core.
-    let payload_cache = Arc::new(DashMap::new());
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
     let synchronizer = Synchronizer::new(
         name,
         &committee(),
@@ -331,11 +413,14 @@ async fn process_certificates() {
         signature_service,
         /* consensus_round */ Arc::new(AtomicU64::new(0)),
         /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
         /* rx_primaries */ rx_primary_messages,
         /* rx_header_waiter */ rx_headers_loopback,
         /* rx_certificate_waiter */ rx_certificates_loopback,
         /* rx_proposer */ rx_headers,
         tx_consensus,
+        tx_equivocations,
         /* tx_proposer */ tx_parents,
     );
 
@@ -365,9 +450,450 @@ async fn process_certificates() {
     }
 
     // Ensure the certificates are stored.
+    let mut table = Table::<Digest, Certificate>::new(&store, CERTIFICATES);
     for x in &certificates {
-        let stored = store.read(x.digest().to_vec()).await.unwrap();
-        let serialized = bincode::serialize(x).unwrap();
-        assert_eq!(stored, Some(serialized));
+        let stored = table.read(&x.digest()).await.unwrap();
+        assert_eq!(stored.as_ref(), Some(x));
     }
-}
\ No newline at end of file
+
+    // Ensure the certificates are indexed by round.
+    let mut indexed = CertificateIndex::new(&store).round(1).await.unwrap();
+    indexed.sort_by_key(|x| x.origin());
+    let mut expected = certificates;
+    expected.sort_by_key(|x| x.origin());
+    assert_eq!(indexed, expected);
+}
+
+#[tokio::test]
+async fn recover_certificates_after_restart() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let committee = committee_with_base_port(13_500);
+
+    let (tx_sync_headers, _rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, _rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(1);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (_tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, mut rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
+    let (tx_parents, mut rx_parents) = channel(1);
+
+    // Create a new test store, holding (and indexing) a quorum of certificates of round 1 that we
+    // processed before crashing.
+    let mut store = Store::new_in_memory();
+    let certificates: Vec<_> = headers()
+        .iter()
+        .take(3)
+        .map(certificate)
+        .collect();
+    let mut batch = WriteBatch::default();
+    let headers_table = Table::<Digest, Header>::new(&store, HEADERS);
+    let certificates_table = Table::<Digest, Certificate>::new(&store, CERTIFICATES);
+    for x in &certificates {
+        batch.insert(&headers_table, &x.header.id, &x.header);
+        batch.insert(&certificates_table, &x.digest(), x);
+        RoundIndex::new(&store, HEADERS).insert(&mut batch, 1, x.header.id.as_ref());
+        CertificateIndex::new(&store).insert(&mut batch, x);
+    }
+    store.write_all(batch).await.unwrap();
+
+    // Spawn a listener that should not receive any vote.
+    let address = committee
+        .primary(&certificates[0].origin())
+        .unwrap()
+        .primary_to_primary;
+    let handle = listener(address);
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee,
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    Core::spawn(
+        name,
+        committee,
+        store.clone(),
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Ensure the core sends the parents of the stored certificates to the proposer.
+    let (mut received, round) = rx_parents.recv().await.unwrap();
+    received.sort();
+    let mut parents: Vec<_> = certificates.iter().map(|x| x.digest()).collect();
+    parents.sort();
+    assert_eq!((received, round), (parents, 1));
+
+    // Send one of these certificates again: the core knows it processed its header, so it does
+    // not vote for it.
+    tx_primary_messages
+        .send(PrimaryMessage::Certificate(certificates[0].clone()))
+        .await
+        .unwrap();
+    assert_eq!(rx_consensus.recv().await.unwrap(), certificates[0]);
+    assert!(timeout(Duration::from_millis(100), handle).await.is_err());
+}
+
+#[tokio::test]
+async fn process_equivocating_headers() {
+    let mut keys = keys();
+    let (author, author_secret) = keys.pop().unwrap();
+    let (name, secret) = keys.pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let committee = committee_with_base_port(13_300);
+
+    let (tx_sync_headers, _rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, _rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(2);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (_tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, mut rx_equivocations) = channel(1);
+    let (tx_parents, _rx_parents) = channel(1);
+
+    // Create a new test store.
+    let mut store = Store::new_in_memory();
+
+    // Make a second header for the same round (with fewer parents).
+    let conflicting = Header {
+        parents: header().parents.into_iter().take(3).collect(),
+        ..header()
+    };
+    let conflicting = Header {
+        id: conflicting.digest(),
+        signature: Signature::new(&conflicting.digest(), &author_secret),
+        ..conflicting
+    };
+    let expected = EquivocationProof::Headers(Box::new(header()), Box::new(conflicting.clone()));
+
+    // Spawn listeners to receive the proof (the author first receives our vote).
+    let _author_handle = listener(committee.primary(&author).unwrap().primary_to_primary);
+    let handles: Vec<_> = keys
+        .iter()
+        .map(|(x, _)| listener(committee.primary(x).unwrap().primary_to_primary))
+        .collect();
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee,
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    Core::spawn(
+        name,
+        committee.clone(),
+        store.clone(),
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Send both headers to the core.
+    for header in [header(), conflicting] {
+        tx_primary_messages
+            .send(PrimaryMessage::Header(header))
+            .await
+            .unwrap();
+    }
+
+    // Ensure the core outputs the proof.
+    let proof = rx_equivocations.recv().await.unwrap();
+    assert_eq!(proof.digest(), expected.digest());
+    assert!(proof.verify(&committee).is_ok());
+
+    // Ensure the other primaries received the proof.
+    for received in try_join_all(handles).await.unwrap() {
+        match bincode::deserialize(&received).unwrap() {
+            PrimaryMessage::Equivocation(x) => assert_eq!(x.digest(), expected.digest()),
+            x => panic!("Unexpected message: {:?}", x),
+        }
+    }
+
+    // Ensure the proof is stored.
+    let stored = store
+        .read(Core::equivocation_key(&author, header().round))
+        .await
+        .unwrap()
+        .map(|x| bincode::deserialize::<EquivocationProof>(&x).unwrap());
+    assert_eq!(stored.map(|x| x.digest()), Some(expected.digest()));
+}
+
+#[tokio::test]
+async fn process_equivocating_votes() {
+    let mut keys = keys();
+    let (name, secret) = keys.pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+    let (voter, voter_secret) = keys.pop().unwrap();
+    let mut voter_signature_service = SignatureService::new(voter_secret);
+
+    let committee = committee_with_base_port(13_400);
+
+    let (tx_sync_headers, _rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, _rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(2);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, mut rx_equivocations) = channel(1);
+    let (tx_parents, _rx_parents) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Make a vote for our header, and one for another header of the same round.
+    let vote = Vote::new(&header(), &voter, &mut voter_signature_service).await;
+    let unknown = Header {
+        id: Digest([1; 32]),
+        ..header()
+    };
+    let conflicting = Vote::new(&unknown, &voter, &mut voter_signature_service).await;
+    let expected = EquivocationProof::Votes(Box::new(vote.clone()), Box::new(conflicting.clone()));
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee,
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    Core::spawn(
+        name,
+        committee.clone(),
+        store.clone(),
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Propose our header, then send both votes to the core.
+    tx_headers.send(header()).await.unwrap();
+    for vote in [vote, conflicting] {
+        tx_primary_messages
+            .send(PrimaryMessage::Vote(vote))
+            .await
+            .unwrap();
+    }
+
+    // Ensure the core outputs the proof.
+    let proof = rx_equivocations.recv().await.unwrap();
+    assert_eq!(proof.digest(), expected.digest());
+    assert!(proof.verify(&committee).is_ok());
+}
+
+// A header of the given round whose parents we do not have.
+fn header_with_missing_parents(round: Round) -> Header {
+    let (_, secret) = keys().pop().unwrap();
+    let header = Header {
+        round,
+        parents: [Digest::default()].iter().cloned().collect(),
+        ..header()
+    };
+    Header {
+        id: header.digest(),
+        signature: Signature::new(&header.digest(), &secret),
+        ..header
+    }
+}
+
+#[tokio::test]
+async fn process_header_too_far_ahead() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let (tx_sync_headers, mut rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, _rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(2);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (_tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
+    let (tx_parents, _rx_parents) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee(),
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    Core::spawn(
+        name,
+        committee(),
+        store,
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* certified_round */ Arc::new(AtomicU64::new(0)),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Send a header too far ahead, and then one just within the lookahead.
+    let within = header_with_missing_parents(100);
+    for header in [header_with_missing_parents(101), within.clone()] {
+        tx_primary_messages
+            .send(PrimaryMessage::Header(header))
+            .await
+            .unwrap();
+    }
+
+    // Ensure the core only syncs the parents of the second one.
+    match rx_sync_headers.recv().await.unwrap() {
+        WaiterMessage::SyncParents(_, x) => assert_eq!(x.id, within.id),
+        x => panic!("Unexpected message: {:?}", x),
+    }
+    assert!(timeout(Duration::from_millis(100), rx_sync_headers.recv())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn process_certificate_too_far_ahead() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let (tx_sync_headers, mut rx_sync_headers) = channel(1);
+    let (tx_sync_certificates, mut rx_sync_certificates) = channel(1);
+    let (tx_primary_messages, rx_primary_messages) = channel(2);
+    let (_tx_headers_loopback, rx_headers_loopback) = channel(1);
+    let (_tx_certificates_loopback, rx_certificates_loopback) = channel(1);
+    let (_tx_headers, rx_headers) = channel(1);
+    let (tx_consensus, mut rx_consensus) = channel(1);
+    let (tx_equivocations, _rx_equivocations) = channel(1);
+    let (tx_parents, _rx_parents) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Make a synchronizer for the core.
+    let payload_cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    let synchronizer = Synchronizer::new(
+        name,
+        &committee(),
+        store.clone(),
+        payload_cache,
+        /* tx_header_waiter */ tx_sync_headers,
+        /* tx_certificate_waiter */ tx_sync_certificates,
+    );
+
+    // Spawn the core.
+    let certified_round = Arc::new(AtomicU64::new(0));
+    Core::spawn(
+        name,
+        committee(),
+        store.clone(),
+        synchronizer,
+        signature_service,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        certified_round.clone(),
+        /* max_round_lookahead */ 100,
+        /* rx_primaries */ rx_primary_messages,
+        /* rx_header_waiter */ rx_headers_loopback,
+        /* rx_certificate_waiter */ rx_certificates_loopback,
+        /* rx_proposer */ rx_headers,
+        tx_consensus,
+        tx_equivocations,
+        /* tx_proposer */ tx_parents,
+    );
+
+    // Send a certificate too far ahead. The core drops it but learns that a quorum reached its
+    // round, so it then accepts headers further ahead.
+    let certificate = certificate(&header_with_missing_parents(150));
+    let ahead = header_with_missing_parents(250);
+    tx_primary_messages
+        .send(PrimaryMessage::Certificate(certificate.clone()))
+        .await
+        .unwrap();
+    tx_primary_messages
+        .send(PrimaryMessage::Header(ahead.clone()))
+        .await
+        .unwrap();
+
+    // Ensure the core only syncs the parents of the header.
+    match rx_sync_headers.recv().await.unwrap() {
+        WaiterMessage::SyncParents(_, x) => assert_eq!(x.id, ahead.id),
+        x => panic!("Unexpected message: {:?}", x),
+    }
+    assert_eq!(certified_round.load(Ordering::Relaxed), 150);
+
+    // Ensure the certificate is neither synced, stored, nor sent to the consensus.
+    assert!(rx_sync_certificates.try_recv().is_err());
+    assert!(rx_consensus.try_recv().is_err());
+    assert!(Table::<Digest, Certificate>::new(&store, CERTIFICATES)
+        .read(&certificate.digest())
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,58 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+
+fn digest(i: u8) -> Digest {
+    Digest([i; 32])
+}
+
+#[test]
+fn hit_rate() {
+    let cache = PayloadCache::new(1_000_000, Arc::new(AtomicU64::new(0)), 50);
+    cache.insert(digest(0), 0);
+
+    assert!(cache.contains(&digest(0), &0));
+    assert!(!cache.contains(&digest(0), &1)); // Held by another worker.
+    assert!(!cache.contains(&digest(1), &0));
+
+    let metrics = cache.metrics();
+    assert_eq!(metrics.hits, 1);
+    assert_eq!(metrics.misses, 2);
+    assert_eq!(metrics.entries, 1);
+}
+
+#[test]
+fn evict_garbage_collected() {
+    let consensus_round = Arc::new(AtomicU64::new(0));
+    let cache = PayloadCache::new(1_000_000, consensus_round.clone(), 50);
+    cache.insert(digest(0), 0);
+    consensus_round.store(10, Ordering::Relaxed);
+    cache.insert(digest(1), 0);
+
+    // The first batch is garbage collected once the consensus moves `gc_depth` rounds ahead.
+    consensus_round.store(55, Ordering::Relaxed);
+    assert!(!cache.contains(&digest(0), &0));
+    assert!(cache.contains(&digest(1), &0));
+
+    consensus_round.store(61, Ordering::Relaxed);
+    assert!(!cache.contains(&digest(1), &0));
+    assert_eq!(cache.metrics().evictions, 2);
+    assert_eq!(cache.metrics().entries, 0);
+}
+
+#[test]
+fn evict_over_budget() {
+    let consensus_round = Arc::new(AtomicU64::new(0));
+    let cache = PayloadCache::new(3 * ENTRY_SIZE, consensus_round.clone(), 50);
+    cache.insert(digest(0), 0);
+    consensus_round.store(1, Ordering::Relaxed);
+    cache.insert(digest(1), 0);
+    cache.insert(digest(2), 0);
+    cache.insert(digest(3), 0);
+
+    // The oldest batch makes room for the newest one.
+    assert!(!cache.contains(&digest(0), &0));
+    for i in 1..4 {
+        assert!(cache.contains(&digest(i), &0));
+    }
+    assert_eq!(cache.metrics().evictions, 1);
+}
@@ -0,0 +1,197 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::adversary::{Adversary, Attack};
+use crate::common::{committee, keys};
+use crate::messages::EquivocationProof;
+use crypto::Hash as _;
+use network::{SimulatedNetwork, SimulationConfig};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// The round every honest primary must reach despite the adversary.
+const ROUNDS: Round = 10;
+
+// Runs three honest primaries and an adversary (the last authority) mounting `attacks` on a
+// simulated network, until every honest primary delivered a certificate of round `ROUNDS` to the
+// consensus. Returns the certificates each honest primary delivered, and the proofs of equivocation
+// they output.
+fn byzantine_committee(attacks: Vec<Attack>) -> (Vec<Vec<Certificate>>, Vec<EquivocationProof>) {
+    let network = SimulatedNetwork::new(SimulationConfig {
+        seed: 0,
+        min_latency: 1,
+        max_latency: 20,
+        drop_probability: 0.01,
+    });
+    let parameters = Parameters {
+        max_header_delay: 20,
+        sync_retry_delay: 100,
+        ..Parameters::default()
+    };
+    let adversary = keys().len() - 1;
+
+    let (tx_certificates, rx_certificates) = mpsc::channel();
+    let (tx_proofs, rx_proofs) = mpsc::channel();
+    let (stops, handles): (Vec<_>, Vec<_>) = keys()
+        .into_iter()
+        .enumerate()
+        .map(|(id, (name, secret))| {
+            let (tx_stop, rx_stop) = oneshot::channel::<()>();
+            let network = network.clone();
+            let parameters = parameters.clone();
+            let tx_certificates = tx_certificates.clone();
+            let tx_proofs = tx_proofs.clone();
+            let attacks = attacks.clone();
+            let handle = thread::spawn(move || {
+                network.enter(id);
+                let keypair = KeyPair {
+                    name,
+                    secret,
+                    coin: None,
+                };
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async move {
+                    if id == adversary {
+                        Adversary::spawn(keypair, committee(), attacks, /* seed */ 0);
+                        let _ = rx_stop.await;
+                        return;
+                    }
+
+                    let store = Store::new_in_memory();
+                    let (tx_consensus, mut rx_consensus) = channel(CHANNEL_CAPACITY);
+                    let (_tx_feedback, rx_feedback) = channel(CHANNEL_CAPACITY);
+                    let (tx_equivocations, mut rx_equivocations) = channel(CHANNEL_CAPACITY);
+                    Primary::spawn(
+                        keypair,
+                        committee(),
+                        parameters,
+                        store,
+                        tx_consensus,
+                        rx_feedback,
+                        tx_equivocations,
+                    );
+                    tokio::pin!(rx_stop);
+                    loop {
+                        tokio::select! {
+                            Some(certificate) = rx_consensus.recv() => {
+                                let _ = tx_certificates.send((id, certificate));
+                            },
+                            Some(proof) = rx_equivocations.recv() => {
+                                let _ = tx_proofs.send(proof);
+                            },
+                            _ = &mut rx_stop => break,
+                        }
+                    }
+                });
+            });
+            (tx_stop, handle)
+        })
+        .unzip();
+
+    // Liveness: every honest primary keeps making progress.
+    let mut delivered = vec![Vec::new(); adversary];
+    while delivered
+        .iter()
+        .any(|x: &Vec<Certificate>| x.iter().all(|x| x.round() < ROUNDS))
+    {
+        let (id, certificate) = rx_certificates
+            .recv_timeout(Duration::from_secs(60))
+            .expect("The honest primaries stopped making progress");
+        delivered[id].push(certificate);
+    }
+
+    for stop in stops {
+        let _ = stop.send(());
+    }
+    for handle in handles {
+        handle.join().expect("A primary panicked");
+    }
+    (delivered, rx_proofs.try_iter().collect())
+}
+
+// Safety: the honest primaries never deliver two different certificates for the same author and
+// round (whoever the author is).
+fn assert_safety(delivered: &[Vec<Certificate>]) {
+    let mut certified = HashMap::new();
+    for certificate in delivered.iter().flatten() {
+        let digest = certified
+            .entry((certificate.origin(), certificate.round()))
+            .or_insert_with(|| certificate.digest());
+        assert_eq!(
+            digest,
+            &certificate.digest(),
+            "Two certificates for {} at round {}",
+            certificate.origin(),
+            certificate.round()
+        );
+    }
+}
+
+// Accountability: every proof of equivocation is valid and blames the adversary.
+fn assert_accountability(proofs: &[EquivocationProof]) {
+    let (adversary, _) = keys().pop().unwrap();
+    for proof in proofs {
+        assert!(proof.verify(&committee()).is_ok());
+        assert_eq!(proof.offender(), adversary);
+    }
+}
+
+#[test]
+fn equivocate() {
+    let (delivered, proofs) = byzantine_committee(vec![Attack::Equivocate]);
+    assert_safety(&delivered);
+    assert_accountability(&proofs);
+    assert!(proofs
+        .iter()
+        .any(|x| matches!(x, EquivocationProof::Headers(..))));
+
+    // Some of the adversary's headers still get certified (once).
+    let (adversary, _) = keys().pop().unwrap();
+    assert!(delivered.iter().flatten().any(|x| x.origin() == adversary));
+}
+
+#[test]
+fn unknown_votes() {
+    let (delivered, proofs) = byzantine_committee(vec![Attack::UnknownVotes]);
+    assert_safety(&delivered);
+    assert_accountability(&proofs);
+    assert!(proofs
+        .iter()
+        .any(|x| matches!(x, EquivocationProof::Votes(..))));
+}
+
+#[test]
+fn flood_requests() {
+    let (delivered, proofs) = byzantine_committee(vec![Attack::FloodRequests]);
+    assert_safety(&delivered);
+    assert!(proofs.is_empty());
+}
+
+#[test]
+fn withhold_batches() {
+    let (delivered, proofs) = byzantine_committee(vec![Attack::WithholdBatches]);
+    assert_safety(&delivered);
+    assert!(proofs.is_empty());
+
+    // Nobody can get the payload of the adversary's headers, so nobody votes for them.
+    let (adversary, _) = keys().pop().unwrap();
+    assert!(delivered.iter().flatten().all(|x| x.origin() != adversary));
+}
+
+#[test]
+fn all_attacks() {
+    let attacks = vec![
+        Attack::Equivocate,
+        Attack::UnknownVotes,
+        Attack::FloodRequests,
+        Attack::WithholdBatches,
+    ];
+    let (delivered, proofs) = byzantine_committee(attacks);
+    assert_safety(&delivered);
+    assert_accountability(&proofs);
+}
@@ -1,9 +1,11 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
 use crate::common::{committee, keys};
-use std::fs;
-use store::Store;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::AtomicU64;
+use store::{Column, MemoryBackend, Operation, StorageBackend, Store};
 use tokio::sync::mpsc::channel;
+use tokio::time::timeout;
 
 #[tokio::test]
 async fn propose_empty() {
@@ -12,23 +14,26 @@ async fn propose_empty() {
 
     let (_tx_parents, rx_parents) = channel(1);
     let (_tx_our_digests, rx_our_digests) = channel(1);
+    let (_tx_committed, rx_committed) = channel(1);
     let (tx_headers, mut rx_headers) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_propose_empty";
-    let _ = fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Spawn the proposer.
     Proposer::spawn(
         name,
         &committee(),
         signature_service,
+        /* coin */ None,
         store, // Thêm store
         /* header_size */ 1_000,
         /* max_header_delay */ 20,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
         /* rx_core */ rx_parents,
         /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
         /* tx_core */ tx_headers,
     );
 
@@ -46,32 +51,34 @@ async fn propose_payload() {
 
     let (_tx_parents, rx_parents) = channel(1);
     let (tx_our_digests, rx_our_digests) = channel(1);
+    let (_tx_committed, rx_committed) = channel(1);
     let (tx_headers, mut rx_headers) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_propose_payload";
-    let _ = fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Spawn the proposer.
     Proposer::spawn(
         name,
         &committee(),
         signature_service,
+        /* coin */ None,
         store, // Thêm store
         /* header_size */ 32,
         /* max_header_delay */ 1_000_000, // Ensure it is not triggered.
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
         /* rx_core */ rx_parents,
         /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
         /* tx_core */ tx_headers,
     );
 
     // Send enough digests for the header payload.
     let digest = Digest(name.0);
     let worker_id = 0;
-    let batch = Vec::new(); // Proposer giờ cần cả batch
     tx_our_digests
-        .send((digest.clone(), worker_id, batch)) // Gửi cả batch
+        .send((digest.clone(), worker_id))
         .await
         .unwrap();
 
@@ -80,4 +87,221 @@ async fn propose_payload() {
     assert_eq!(header.round, 1);
     assert_eq!(header.payload.get(&digest), Some(&worker_id));
     assert!(header.verify(&committee()).is_ok());
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn propose_after_restart() {
+    let (name, secret) = keys().pop().unwrap();
+    let mut signature_service = SignatureService::new(secret);
+
+    let (tx_parents, rx_parents) = channel(1);
+    let (_tx_our_digests, rx_our_digests) = channel(1);
+    let (_tx_committed, rx_committed) = channel(1);
+    let (tx_headers, mut rx_headers) = channel(1);
+
+    // Create a new test store, holding the header we proposed at round 3 before crashing.
+    let mut store = Store::new_in_memory();
+    let header = Header::new(
+        name,
+        3,
+        BTreeMap::new(),
+        BTreeSet::new(),
+        None,
+        &mut signature_service,
+    )
+    .await;
+    let bytes = bincode::serialize(&header).unwrap();
+    store.write(Proposer::LAST_HEADER_KEY.to_vec(), bytes).await;
+
+    // Spawn the proposer.
+    Proposer::spawn(
+        name,
+        &committee(),
+        signature_service,
+        /* coin */ None,
+        store,
+        /* header_size */ 1_000,
+        /* max_header_delay */ 20,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* rx_core */ rx_parents,
+        /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
+        /* tx_core */ tx_headers,
+    );
+
+    // The parents of round 2 are too old: the proposer already moved past them.
+    let parents = vec![Digest::default()];
+    tx_parents.send((parents.clone(), 2)).await.unwrap();
+    tx_parents.send((parents.clone(), 3)).await.unwrap();
+
+    // Ensure the proposer does not propose again for round 3.
+    let header = rx_headers.recv().await.unwrap();
+    assert_eq!(header.round, 4);
+    assert_eq!(header.parents, parents.into_iter().collect());
+}
+
+#[tokio::test]
+async fn repropose_after_later_commit() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let (tx_parents, rx_parents) = channel(1);
+    let (tx_our_digests, rx_our_digests) = channel(1);
+    let (tx_committed, rx_committed) = channel(1);
+    let (tx_headers, mut rx_headers) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn the proposer.
+    Proposer::spawn(
+        name,
+        &committee(),
+        signature_service,
+        /* coin */ None,
+        store,
+        /* header_size */ 32,
+        /* max_header_delay */ 1_000_000, // Ensure it is not triggered.
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* rx_core */ rx_parents,
+        /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
+        /* tx_core */ tx_headers,
+    );
+
+    // Our header of round 1 carries the digest.
+    let digest = Digest(name.0);
+    let worker_id = 0;
+    tx_our_digests
+        .send((digest.clone(), worker_id))
+        .await
+        .unwrap();
+    let header = rx_headers.recv().await.unwrap();
+    assert_eq!(header.round, 1);
+
+    // The consensus commits our certificate of round 2, skipping the one of round 1 (if any).
+    let certificate = Certificate {
+        header: Header {
+            author: name,
+            round: 2,
+            ..Header::default()
+        },
+        ..Certificate::default()
+    };
+    tx_committed.send(certificate).await.unwrap();
+
+    // Ensure the proposer includes the digest in its next header.
+    tx_parents.send((vec![Digest::default()], 1)).await.unwrap();
+    let header = rx_headers.recv().await.unwrap();
+    assert_eq!(header.round, 2);
+    assert_eq!(header.payload.get(&digest), Some(&worker_id));
+}
+
+#[tokio::test]
+async fn repropose_after_garbage_collection() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let (tx_parents, rx_parents) = channel(1);
+    let (tx_our_digests, rx_our_digests) = channel(1);
+    let (_tx_committed, rx_committed) = channel(1);
+    let (tx_headers, mut rx_headers) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn the proposer.
+    let consensus_round = Arc::new(AtomicU64::new(0));
+    Proposer::spawn(
+        name,
+        &committee(),
+        signature_service,
+        /* coin */ None,
+        store,
+        /* header_size */ 32,
+        /* max_header_delay */ 1_000_000, // Ensure it is not triggered.
+        consensus_round.clone(),
+        /* gc_depth */ 50,
+        /* rx_core */ rx_parents,
+        /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
+        /* tx_core */ tx_headers,
+    );
+
+    // Our header of round 1 carries the digest.
+    let digest = Digest(name.0);
+    let worker_id = 0;
+    tx_our_digests
+        .send((digest.clone(), worker_id))
+        .await
+        .unwrap();
+    let header = rx_headers.recv().await.unwrap();
+    assert_eq!(header.round, 1);
+
+    // The consensus moves on without committing it, until it falls out of the GC window.
+    consensus_round.store(52, Ordering::Relaxed);
+
+    // Ensure the proposer includes the digest in its next header.
+    tx_parents.send((vec![Digest::default()], 1)).await.unwrap();
+    let header = rx_headers.recv().await.unwrap();
+    assert_eq!(header.round, 2);
+    assert_eq!(header.payload.get(&digest), Some(&worker_id));
+}
+
+/// A storage backend whose writes always fail.
+struct FailingBackend(MemoryBackend);
+
+impl StorageBackend for FailingBackend {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.0.get(column, key)
+    }
+
+    fn write(&mut self, _operations: &[Operation]) -> Result<(), StoreError> {
+        Err(StoreError::new("Disk full"))
+    }
+
+    fn iter_from<'a>(
+        &'a self,
+        column: Column,
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), StoreError>> + 'a> {
+        self.0.iter_from(column, from)
+    }
+}
+
+#[tokio::test]
+async fn propose_nothing_unless_persisted() {
+    let (name, secret) = keys().pop().unwrap();
+    let signature_service = SignatureService::new(secret);
+
+    let (_tx_parents, rx_parents) = channel(1);
+    let (_tx_our_digests, rx_our_digests) = channel(1);
+    let (_tx_committed, rx_committed) = channel(1);
+    let (tx_headers, mut rx_headers) = channel(1);
+
+    // Create a store that fails to persist our headers.
+    let store = Store::with_backend(FailingBackend(MemoryBackend::default()));
+
+    // Spawn the proposer.
+    Proposer::spawn(
+        name,
+        &committee(),
+        signature_service,
+        /* coin */ None,
+        store,
+        /* header_size */ 1_000,
+        /* max_header_delay */ 20,
+        /* consensus_round */ Arc::new(AtomicU64::new(0)),
+        /* gc_depth */ 50,
+        /* rx_core */ rx_parents,
+        /* rx_workers */ rx_our_digests,
+        /* rx_committed */ rx_committed,
+        /* tx_core */ tx_headers,
+    );
+
+    // Ensure the proposer never sends a header it could propose again after a crash.
+    let result = timeout(Duration::from_millis(200), rx_headers.recv()).await;
+    assert!(result.is_err());
+}
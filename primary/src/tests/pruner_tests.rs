@@ -0,0 +1,89 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{certificate, header};
+use crate::messages::{Certificate, Header};
+use crate::payload_cache::PayloadCache;
+use crate::payload_receiver::PayloadReceiver;
+use crypto::Digest;
+use crypto::Hash as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{Table, WriteBatch, INDICES};
+use tokio::sync::mpsc::channel;
+use tokio::time::{sleep, timeout, Duration};
+
+#[tokio::test]
+async fn prune_old_certificates() {
+    // Create a new test store.
+    let mut store = Store::new_in_memory();
+    let mut certificates = Table::<Digest, Certificate>::new(&store, CERTIFICATES);
+
+    // Store (and index) a certificate of round 1 and one of round 5.
+    let old = certificate(&header());
+    let recent = certificate(&Header {
+        round: 5,
+        ..header()
+    });
+    let index = RoundIndex::new(&store, CERTIFICATES);
+    let mut batch = WriteBatch::default();
+    for certificate in [&old, &recent] {
+        let digest = certificate.digest();
+        batch.insert(&certificates, &digest, certificate);
+        index.insert(&mut batch, certificate.round(), digest.as_ref());
+    }
+    store.write_all(batch).await.unwrap();
+
+    // Spawn the pruner and commit round 4.
+    let (tx_round, rx_round) = channel(1);
+    Pruner::spawn(store.clone(), /* retention_depth */ 2, rx_round);
+    tx_round.send(4).await.unwrap();
+
+    // The certificate of round 1 is deleted, the other one is kept.
+    let deleted = async {
+        while certificates.read(&old.digest()).await.unwrap().is_some() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    };
+    assert!(timeout(Duration::from_secs(1), deleted).await.is_ok());
+    let kept = certificates.read(&recent.digest()).await.unwrap();
+    assert!(kept.is_some());
+}
+
+#[tokio::test]
+async fn prune_old_payload_markers() {
+    // Create a new test store.
+    let store = Store::new_in_memory();
+    let mut indices = store.column(INDICES);
+
+    // Receive the digest of a batch at round 1, then one at round 5.
+    let consensus_round = Arc::new(AtomicU64::new(1));
+    let cache = PayloadCache::new(1_000_000, consensus_round.clone(), 50);
+    let (tx_workers, rx_workers) = channel(1);
+    PayloadReceiver::spawn(store.clone(), consensus_round.clone(), cache, rx_workers);
+    let (old, recent) = (Digest([1; 32]), Digest([5; 32]));
+    for (round, digest) in [(1, &old), (5, &recent)] {
+        consensus_round.store(round, Ordering::Relaxed);
+        tx_workers.send((digest.clone(), 0)).await.unwrap();
+        let key = PayloadReceiver::key(digest, 0);
+        assert!(indices.notify_read(key).await.is_ok());
+    }
+
+    // Spawn the pruner and commit round 4.
+    let (tx_round, rx_round) = channel(1);
+    Pruner::spawn(store.clone(), /* retention_depth */ 2, rx_round);
+    tx_round.send(4).await.unwrap();
+
+    // The marker of round 1 is deleted, the other one is kept.
+    let deleted = async {
+        let key = PayloadReceiver::key(&old, 0);
+        while indices.read(key.clone()).await.unwrap().is_some() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    };
+    assert!(timeout(Duration::from_secs(1), deleted).await.is_ok());
+    let kept = indices
+        .read(PayloadReceiver::key(&recent, 0))
+        .await
+        .unwrap();
+    assert!(kept.is_some());
+}
@@ -46,10 +46,9 @@ mkdir -p "$LOG_DIR"
 echo "--- Stage 2: Generate Config ---"
 key_files=()
 for i in $(seq 0 $((NODES-1))); do
-    key_file="$BENCHMARK_DIR/.node-$i.json"
-    $NODE_BINARY generate_keys --filename "$key_file"
-    key_files+=("$key_file")
+    key_files+=("$BENCHMARK_DIR/.node-$i.json")
 done
+$NODE_BINARY deal_keys --filenames "${key_files[@]}"
 
 jq -n \
   --argjson batch_size "$BATCH_SIZE" \
@@ -0,0 +1,83 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::{Column, Key, Operation, StoreResult, Value, COLUMNS};
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(test)]
+#[path = "tests/backend_tests.rs"]
+pub mod backend_tests;
+
+/// The storage engine behind the `Store` actor. It holds one ordered key-value map per column
+/// family (see `COLUMNS`).
+pub trait StorageBackend: Send + 'static {
+    /// Reads the value of a key.
+    fn get(&self, column: Column, key: &[u8]) -> StoreResult<Option<Value>>;
+
+    /// Applies all the operations atomically: after a crash, either all of them or none are in
+    /// the store.
+    fn write(&mut self, operations: &[Operation]) -> StoreResult<()>;
+
+    /// Iterates over the pairs whose key is not lower than `from`, in the order of their keys.
+    fn iter_from<'a>(
+        &'a self,
+        column: Column,
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = StoreResult<(Key, Value)>> + 'a>;
+}
+
+/// A storage backend keeping everything in memory. It is mostly useful for tests: nothing
+/// survives the process.
+pub struct MemoryBackend {
+    columns: HashMap<Column, BTreeMap<Key, Value>>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self {
+            columns: COLUMNS.iter().map(|x| (*x, BTreeMap::new())).collect(),
+        }
+    }
+}
+
+impl MemoryBackend {
+    fn column(&self, column: Column) -> &BTreeMap<Key, Value> {
+        self.columns.get(column).expect("Unknown column family")
+    }
+
+    fn column_mut(&mut self, column: Column) -> &mut BTreeMap<Key, Value> {
+        self.columns.get_mut(column).expect("Unknown column family")
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, column: Column, key: &[u8]) -> StoreResult<Option<Value>> {
+        Ok(self.column(column).get(key).cloned())
+    }
+
+    fn write(&mut self, operations: &[Operation]) -> StoreResult<()> {
+        for operation in operations {
+            match operation {
+                Operation::Put(column, key, value) => {
+                    self.column_mut(column).insert(key.clone(), value.clone());
+                }
+                Operation::Delete(column, key) => {
+                    self.column_mut(column).remove(key);
+                }
+                Operation::DeleteRange(column, from, to) => {
+                    let map = self.column_mut(column);
+                    let mut tail = map.split_off(from);
+                    map.append(&mut tail.split_off(to));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn iter_from<'a>(
+        &'a self,
+        column: Column,
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = StoreResult<(Key, Value)>> + 'a> {
+        let iter = self.column(column).range(from.to_vec()..);
+        Box::new(iter.map(|(key, value)| Ok((key.clone(), value.clone()))))
+    }
+}
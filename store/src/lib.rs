@@ -1,62 +1,190 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use log::error;
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use tokio::sync::mpsc::{channel, Sender};
 use tokio::sync::oneshot;
 
+mod backend;
+#[cfg(feature = "rocksdb")]
+mod rocksdb_backend;
+mod round_index;
+mod table;
+pub use backend::{MemoryBackend, StorageBackend};
+#[cfg(feature = "rocksdb")]
+pub use rocksdb_backend::RocksDbBackend;
+pub use round_index::RoundIndex;
+pub use table::{Table, WriteBatch};
+
 #[cfg(test)]
 #[path = "tests/store_tests.rs"]
 pub mod store_tests;
 
-pub type StoreError = rocksdb::Error;
+/// An error of the storage backend.
+#[derive(Debug)]
+pub struct StoreError(String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl StoreError {
+    /// Makes an error out of a message (for the storage backends defined outside this crate).
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl From<rocksdb::Error> for StoreError {
+    fn from(e: rocksdb::Error) -> Self {
+        Self(e.into_string())
+    }
+}
+
 type StoreResult<T> = Result<T, StoreError>;
 
 type Key = Vec<u8>;
 type Value = Vec<u8>;
 
+/// The name of a column family of the store.
+pub type Column = &'static str;
+
+/// The column family holding everything that does not belong to a dedicated one.
+pub const DEFAULT: Column = "default";
+/// The headers the primary processed, by digest.
+pub const HEADERS: Column = "headers";
+/// The certificates the primary processed, by digest.
+pub const CERTIFICATES: Column = "certificates";
+/// The batches the worker holds, by digest.
+pub const BATCHES: Column = "batches";
+/// The state of the consensus.
+pub const CONSENSUS: Column = "consensus";
+/// The secondary indices over the other column families.
+pub const INDICES: Column = "indices";
+/// The round index over the other column families (see `RoundIndex`).
+pub const ROUNDS: Column = "rounds";
+
+/// All the column families of the store.
+const COLUMNS: [Column; 7] = [
+    DEFAULT,
+    HEADERS,
+    CERTIFICATES,
+    BATCHES,
+    CONSENSUS,
+    INDICES,
+    ROUNDS,
+];
+
+/// A single write of a `StoreCommand::WriteBatch`.
+pub enum Operation {
+    Put(Column, Key, Value),
+    Delete(Column, Key),
+    /// Deletes the keys from the first (included) to the second (excluded).
+    DeleteRange(Column, Key, Key),
+}
+
 pub enum StoreCommand {
-    Write(Key, Value),
-    Read(Key, oneshot::Sender<StoreResult<Option<Value>>>),
-    NotifyRead(Key, oneshot::Sender<StoreResult<Value>>),
+    Write(Column, Key, Value),
+    Delete(Column, Key),
+    /// Deletes the keys from the first (included) to the second (excluded).
+    DeleteRange(Column, Key, Key),
+    /// Applies all the operations atomically, and replies whether it succeeded.
+    WriteBatch(Vec<Operation>, oneshot::Sender<StoreResult<()>>),
+    Read(Column, Key, oneshot::Sender<StoreResult<Option<Value>>>),
+    /// Reads the pairs whose key lies between the first (included) and the second (excluded), in
+    /// the order of their keys.
+    ReadRange(
+        Column,
+        Key,
+        Key,
+        oneshot::Sender<StoreResult<Vec<(Key, Value)>>>,
+    ),
+    /// Reads the pairs whose key starts with the prefix, in the order of their keys.
+    ReadPrefix(Column, Key, oneshot::Sender<StoreResult<Vec<(Key, Value)>>>),
+    NotifyRead(Column, Key, oneshot::Sender<StoreResult<Value>>),
 }
 
+/// A handle to the store. It reads and writes a single column family (see `Store::column`).
 #[derive(Clone)]
 pub struct Store {
     channel: Sender<StoreCommand>,
+    column: Column,
 }
 
 impl Store {
+    /// Opens a store persisted with RocksDB at `path`, with the default options.
+    #[cfg(feature = "rocksdb")]
     pub fn new(path: &str) -> StoreResult<Self> {
-        let db = rocksdb::DB::open_default(path)?;
+        let backend = RocksDbBackend::new(path, &config::Parameters::default())?;
+        Ok(Self::with_backend(backend))
+    }
+
+    /// Creates a store that keeps everything in memory.
+    pub fn new_in_memory() -> Self {
+        Self::with_backend(MemoryBackend::default())
+    }
+
+    /// Spawns the store actor on top of a storage backend.
+    pub fn with_backend<B: StorageBackend>(mut backend: B) -> Self {
         //HashMap này sẽ được dùng để theo dõi các yêu cầu NotifyRead đang chờ dữ liệu. Hashmap lưu nhiều quue[oneshoot]
         let mut obligations = HashMap::<_, VecDeque<oneshot::Sender<_>>>::new();
         let (tx, mut rx) = channel(100);
         tokio::spawn(async move {
             while let Some(command) = rx.recv().await {
                 match command {
-                    //Ghi cặp (key, value) vào RocksDB.
+                    //Ghi cặp (key, value) vào backend.
                     // Kiểm tra xem có yêu cầu NotifyRead nào đang chờ key này trong obligations không.
                     // Nếu có, nó sẽ gửi value vừa được ghi cho tất cả những người đang chờ thông qua các kênh oneshot của họ và xóa key khỏi obligations.
-                    StoreCommand::Write(key, value) => {
-                        let _ = db.put(&key, &value);
-                        if let Some(mut senders) = obligations.remove(&key) {
-                            while let Some(s) = senders.pop_front() {
-                                let _ = s.send(Ok(value.clone()));
-                            }
-                        }
+                    StoreCommand::Write(column, key, value) => {
+                        let operations = vec![Operation::Put(column, key, value)];
+                        let _ = Self::apply(&mut backend, &mut obligations, operations);
+                    }
+                    StoreCommand::Delete(column, key) => {
+                        let operations = vec![Operation::Delete(column, key)];
+                        let _ = Self::apply(&mut backend, &mut obligations, operations);
+                    }
+                    StoreCommand::DeleteRange(column, from, to) => {
+                        let operations = vec![Operation::DeleteRange(column, from, to)];
+                        let _ = Self::apply(&mut backend, &mut obligations, operations);
+                    }
+                    // Ghi tất cả các thao tác cùng một lúc: hoặc tất cả, hoặc không thao tác nào
+                    // được ghi.
+                    StoreCommand::WriteBatch(operations, sender) => {
+                        let response = Self::apply(&mut backend, &mut obligations, operations);
+                        let _ = sender.send(response);
+                    }
+                    StoreCommand::Read(column, key, sender) => {
+                        let response = backend.get(column, &key);
+                        let _ = sender.send(response);
+                    }
+                    StoreCommand::ReadRange(column, from, to, sender) => {
+                        let response = backend
+                            .iter_from(column, &from)
+                            .take_while(|x| !matches!(x, Ok((key, _)) if key >= &to))
+                            .collect();
+                        let _ = sender.send(response);
                     }
-                    StoreCommand::Read(key, sender) => {
-                        let response = db.get(&key);
+                    StoreCommand::ReadPrefix(column, prefix, sender) => {
+                        let response = backend
+                            .iter_from(column, &prefix)
+                            .take_while(|x| !matches!(x, Ok((key, _)) if !key.starts_with(&prefix)))
+                            .collect();
                         let _ = sender.send(response);
                     }
-                    // Cố gắng đọc key từ RocksDB.
+                    // Cố gắng đọc key từ backend.
                     // Nếu key đã tồn tại, nó sẽ gửi ngay giá trị tìm được cho người yêu cầu.
                     // Nếu key chưa tồn tại (Ok(None)), thay vì trả về None, nó sẽ lưu sender (kênh oneshot) vào obligations dưới key đó.
                     // Khi key này được ghi vào (thông qua lệnh Write), tác vụ nền sẽ tìm thấy sender này và gửi giá trị mới qua nó.
-                    StoreCommand::NotifyRead(key, sender) => {
-                        let response = db.get(&key);
+                    StoreCommand::NotifyRead(column, key, sender) => {
+                        let response = backend.get(column, &key);
                         match response {
                             Ok(None) => obligations
-                                .entry(key)
+                                .entry((column, key))
                                 .or_insert_with(VecDeque::new)
                                 .push_back(sender),
                             _ => {
@@ -67,18 +195,116 @@ impl Store {
                 }
             }
         });
-        Ok(Self { channel: tx })
+        Self {
+            channel: tx,
+            column: DEFAULT,
+        }
+    }
+
+    /// Returns a handle to the specified column family of the store.
+    pub fn column(&self, column: Column) -> Self {
+        assert!(
+            COLUMNS.contains(&column),
+            "Unknown column family {}",
+            column
+        );
+        Self {
+            channel: self.channel.clone(),
+            column,
+        }
+    }
+
+    /// Applies the operations with the backend. If it succeeds, sends the values it wrote to
+    /// everyone waiting for them; otherwise, fails everyone waiting for them (the value they
+    /// wait for may never come).
+    fn apply<B: StorageBackend>(
+        backend: &mut B,
+        obligations: &mut HashMap<(Column, Key), VecDeque<oneshot::Sender<StoreResult<Value>>>>,
+        operations: Vec<Operation>,
+    ) -> StoreResult<()> {
+        let result = backend.write(&operations);
+        if let Err(e) = &result {
+            error!("Failed to write to the store: {}", e);
+        }
+        for operation in operations {
+            if let Operation::Put(column, key, value) = operation {
+                let response = match &result {
+                    Ok(()) => Ok(value),
+                    Err(e) => Err(StoreError(e.0.clone())),
+                };
+                Self::notify(obligations, (column, key), response);
+            }
+        }
+        result
+    }
+
+    /// Sends the outcome of a write to everyone waiting for its value.
+    fn notify(
+        obligations: &mut HashMap<(Column, Key), VecDeque<oneshot::Sender<StoreResult<Value>>>>,
+        key: (Column, Key),
+        response: StoreResult<Value>,
+    ) {
+        if let Some(mut senders) = obligations.remove(&key) {
+            while let Some(s) = senders.pop_front() {
+                let _ = s.send(match &response {
+                    Ok(value) => Ok(value.clone()),
+                    Err(e) => Err(StoreError(e.0.clone())),
+                });
+            }
+        }
     }
 
     pub async fn write(&mut self, key: Key, value: Value) {
-        if let Err(e) = self.channel.send(StoreCommand::Write(key, value)).await {
+        let command = StoreCommand::Write(self.column, key, value);
+        if let Err(e) = self.channel.send(command).await {
             panic!("Failed to send Write command to store: {}", e);
         }
     }
 
+    pub async fn delete(&mut self, key: Key) {
+        let command = StoreCommand::Delete(self.column, key);
+        if let Err(e) = self.channel.send(command).await {
+            panic!("Failed to send Delete command to store: {}", e);
+        }
+    }
+
+    /// Deletes the keys from `from` (included) to `to` (excluded).
+    pub async fn delete_range(&mut self, from: Key, to: Key) {
+        let command = StoreCommand::DeleteRange(self.column, from, to);
+        if let Err(e) = self.channel.send(command).await {
+            panic!("Failed to send DeleteRange command to store: {}", e);
+        }
+    }
+
+    /// Writes all the pairs atomically: after a crash, either all of them or none are in the store.
+    pub async fn write_batch(&mut self, pairs: Vec<(Key, Value)>) -> StoreResult<()> {
+        let operations = pairs
+            .into_iter()
+            .map(|(k, v)| Operation::Put(self.column, k, v))
+            .collect();
+        self.send_batch(operations).await
+    }
+
+    /// Applies atomically writes to (possibly) several column families.
+    pub async fn write_all(&mut self, batch: WriteBatch) -> StoreResult<()> {
+        self.send_batch(batch.into_operations()).await
+    }
+
+    async fn send_batch(&mut self, operations: Vec<Operation>) -> StoreResult<()> {
+        let (sender, receiver) = oneshot::channel();
+        let command = StoreCommand::WriteBatch(operations, sender);
+        if let Err(e) = self.channel.send(command).await {
+            panic!("Failed to send WriteBatch command to store: {}", e);
+        }
+        receiver
+            .await
+            .expect("Failed to receive reply to WriteBatch command from store")
+    }
+
     pub async fn read(&mut self, key: Key) -> StoreResult<Option<Value>> {
         let (sender, receiver) = oneshot::channel();
-        if let Err(e) = self.channel.send(StoreCommand::Read(key, sender)).await {
+        let command = StoreCommand::Read(self.column, key, sender);
+        if let Err(e) = self.channel.send(command).await {
             panic!("Failed to send Read command to store: {}", e);
         }
         receiver
@@ -86,13 +312,35 @@ impl Store {
             .expect("Failed to receive reply to Read command from store")
     }
 
-    pub async fn notify_read(&mut self, key: Key) -> StoreResult<Value> {
+    /// Reads the pairs whose key lies between `from` (included) and `to` (excluded), in the order
+    /// of their keys.
+    pub async fn read_range(&mut self, from: Key, to: Key) -> StoreResult<Vec<(Key, Value)>> {
+        let (sender, receiver) = oneshot::channel();
+        let command = StoreCommand::ReadRange(self.column, from, to, sender);
+        if let Err(e) = self.channel.send(command).await {
+            panic!("Failed to send ReadRange command to store: {}", e);
+        }
+        receiver
+            .await
+            .expect("Failed to receive reply to ReadRange command from store")
+    }
+
+    /// Reads the pairs whose key starts with `prefix`, in the order of their keys.
+    pub async fn read_prefix(&mut self, prefix: Key) -> StoreResult<Vec<(Key, Value)>> {
         let (sender, receiver) = oneshot::channel();
-        if let Err(e) = self
-            .channel
-            .send(StoreCommand::NotifyRead(key, sender))
+        let command = StoreCommand::ReadPrefix(self.column, prefix, sender);
+        if let Err(e) = self.channel.send(command).await {
+            panic!("Failed to send ReadPrefix command to store: {}", e);
+        }
+        receiver
             .await
-        {
+            .expect("Failed to receive reply to ReadPrefix command from store")
+    }
+
+    pub async fn notify_read(&mut self, key: Key) -> StoreResult<Value> {
+        let (sender, receiver) = oneshot::channel();
+        let command = StoreCommand::NotifyRead(self.column, key, sender);
+        if let Err(e) = self.channel.send(command).await {
             panic!("Failed to send NotifyRead command to store: {}", e);
         }
         receiver
@@ -0,0 +1,82 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::backend::StorageBackend;
+use crate::{Column, Key, Operation, StoreResult, Value, COLUMNS};
+use config::{Parameters, StoreCompression, WalSync};
+use rocksdb::{BlockBasedOptions, Cache, ColumnFamily, DBCompressionType, Direction, IteratorMode};
+
+/// A storage backend persisting everything with RocksDB, one column family of RocksDB per column
+/// family of the store.
+pub struct RocksDbBackend {
+    db: rocksdb::DB,
+    /// The options of all our writes (they set how the write-ahead log reaches the disk).
+    write_options: rocksdb::WriteOptions,
+}
+
+impl RocksDbBackend {
+    /// Opens (or creates) the database at `path`, with the store options of the parameters.
+    pub fn new(path: &str, parameters: &Parameters) -> StoreResult<Self> {
+        let mut table_options = BlockBasedOptions::default();
+        table_options.set_block_cache(&Cache::new_lru_cache(parameters.store_cache_size));
+        let mut column_options = rocksdb::Options::default();
+        column_options.set_block_based_table_factory(&table_options);
+        column_options.set_compression_type(match parameters.store_compression {
+            StoreCompression::None => DBCompressionType::None,
+            StoreCompression::Snappy => DBCompressionType::Snappy,
+            StoreCompression::Lz4 => DBCompressionType::Lz4,
+            StoreCompression::Zstd => DBCompressionType::Zstd,
+        });
+
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let descriptors = COLUMNS
+            .iter()
+            .map(|x| rocksdb::ColumnFamilyDescriptor::new(*x, column_options.clone()));
+        let db = rocksdb::DB::open_cf_descriptors(&options, path, descriptors)?;
+
+        let mut write_options = rocksdb::WriteOptions::default();
+        match parameters.store_wal_sync {
+            WalSync::Sync => write_options.set_sync(true),
+            WalSync::Buffered => (),
+            WalSync::Disabled => write_options.disable_wal(true),
+        }
+        Ok(Self { db, write_options })
+    }
+
+    fn cf(&self, column: Column) -> &ColumnFamily {
+        self.db.cf_handle(column).expect("Unknown column family")
+    }
+}
+
+impl StorageBackend for RocksDbBackend {
+    fn get(&self, column: Column, key: &[u8]) -> StoreResult<Option<Value>> {
+        Ok(self.db.get_cf(self.cf(column), key)?)
+    }
+
+    fn write(&mut self, operations: &[Operation]) -> StoreResult<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for operation in operations {
+            match operation {
+                Operation::Put(column, key, value) => batch.put_cf(self.cf(column), key, value),
+                Operation::Delete(column, key) => batch.delete_cf(self.cf(column), key),
+                Operation::DeleteRange(column, from, to) => {
+                    batch.delete_range_cf(self.cf(column), from, to)
+                }
+            }
+        }
+        Ok(self.db.write_opt(batch, &self.write_options)?)
+    }
+
+    fn iter_from<'a>(
+        &'a self,
+        column: Column,
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = StoreResult<(Key, Value)>> + 'a> {
+        let mode = IteratorMode::From(from, Direction::Forward);
+        let iter = self.db.iterator_cf(self.cf(column), mode);
+        Box::new(iter.map(|x| match x {
+            Ok((key, value)) => Ok((key.to_vec(), value.to_vec())),
+            Err(e) => Err(e.into()),
+        }))
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::{Column, Key, Store, StoreResult, WriteBatch, ROUNDS};
+
+#[cfg(test)]
+#[path = "tests/round_index_tests.rs"]
+pub mod round_index_tests;
+
+/// Indexes the keys of a column family by round, so that we can delete everything older than a
+/// given round. The entries of the index (in the `ROUNDS` column family) are the name of the
+/// indexed column family, the round (big-endian, so that the entries sort by round), and the key.
+#[derive(Clone)]
+pub struct RoundIndex {
+    /// The indexed column family.
+    store: Store,
+    /// The index itself.
+    index: Store,
+}
+
+impl RoundIndex {
+    pub fn new(store: &Store, column: Column) -> Self {
+        Self {
+            store: store.column(column),
+            index: store.column(ROUNDS),
+        }
+    }
+
+    fn prefix(&self, round: u64) -> Key {
+        [self.store.column.as_bytes(), b"/", &round.to_be_bytes()].concat()
+    }
+
+    /// Adds the index entry of a key to the batch. The key must be written to the indexed column
+    /// family by the same batch, so that one is never in the store without the other.
+    pub fn insert(&self, batch: &mut WriteBatch, round: u64, key: &[u8]) {
+        let entry = [&self.prefix(round), key].concat();
+        batch.put(&self.index, entry, Vec::new());
+    }
+
+    /// Returns the keys indexed at round `round` or higher, along with their round, sorted by
+    /// round.
+    pub async fn keys(&mut self, round: u64) -> StoreResult<Vec<(u64, Key)>> {
+        let from = self.prefix(round);
+        // The entries of the column family end before its name followed by '0' (after '/').
+        let to = [self.store.column.as_bytes(), b"0"].concat();
+        let entries = self.index.read_range(from.clone(), to).await?;
+        let keys = entries
+            .into_iter()
+            .map(|(entry, _)| {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&entry[from.len() - 8..from.len()]);
+                (u64::from_be_bytes(bytes), entry[from.len()..].to_vec())
+            })
+            .collect();
+        Ok(keys)
+    }
+
+    /// Deletes (atomically) the keys indexed at a round lower than `round`, along with their index
+    /// entries. Returns the number of keys deleted.
+    pub async fn prune(&mut self, round: u64) -> StoreResult<usize> {
+        let from = self.prefix(0);
+        let to = self.prefix(round);
+        let entries = self.index.read_range(from.clone(), to.clone()).await?;
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut batch = WriteBatch::default();
+        for (entry, _) in &entries {
+            batch.delete(&self.store, entry[from.len()..].to_vec());
+        }
+        batch.delete_range(&self.index, from, to);
+        self.index.write_all(batch).await?;
+        Ok(entries.len())
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::{Column, Key, Operation, Store, StoreResult, Value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+#[path = "tests/table_tests.rs"]
+pub mod table_tests;
+
+/// A typed view of a column family: keys and values are bincode-encoded.
+pub struct Table<K, V> {
+    store: Store,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Clone for Table<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<K: Serialize, V: Serialize + DeserializeOwned> Table<K, V> {
+    pub fn new(store: &Store, column: Column) -> Self {
+        Self {
+            store: store.column(column),
+            _types: PhantomData,
+        }
+    }
+
+    fn key(key: &K) -> Key {
+        bincode::serialize(key).expect("Failed to serialize key")
+    }
+
+    fn value(value: Value) -> V {
+        bincode::deserialize(&value).expect("Failed to deserialize value")
+    }
+
+    pub async fn write(&mut self, key: &K, value: &V) {
+        let value = bincode::serialize(value).expect("Failed to serialize value");
+        self.store.write(Self::key(key), value).await
+    }
+
+    pub async fn read(&mut self, key: &K) -> StoreResult<Option<V>> {
+        let value = self.store.read(Self::key(key)).await?;
+        Ok(value.map(Self::value))
+    }
+
+    pub async fn notify_read(&mut self, key: &K) -> StoreResult<V> {
+        self.store
+            .notify_read(Self::key(key))
+            .await
+            .map(Self::value)
+    }
+}
+
+/// Writes to (possibly) several tables, applied atomically by `Store::write_all`.
+#[derive(Default)]
+pub struct WriteBatch {
+    operations: Vec<Operation>,
+}
+
+impl WriteBatch {
+    pub fn insert<K, V>(&mut self, table: &Table<K, V>, key: &K, value: &V)
+    where
+        K: Serialize,
+        V: Serialize + DeserializeOwned,
+    {
+        let value = bincode::serialize(value).expect("Failed to serialize value");
+        self.put(&table.store, Table::<K, V>::key(key), value);
+    }
+
+    /// Writes a raw pair to the column family of the store handle.
+    pub fn put(&mut self, store: &Store, key: Key, value: Value) {
+        self.operations
+            .push(Operation::Put(store.column, key, value));
+    }
+
+    /// Deletes a raw key from the column family of the store handle.
+    pub fn delete(&mut self, store: &Store, key: Key) {
+        self.operations.push(Operation::Delete(store.column, key));
+    }
+
+    /// Deletes the raw keys from `from` (included) to `to` (excluded) from the column family of
+    /// the store handle.
+    pub fn delete_range(&mut self, store: &Store, from: Key, to: Key) {
+        self.operations
+            .push(Operation::DeleteRange(store.column, from, to));
+    }
+
+    pub(crate) fn into_operations(self) -> Vec<Operation> {
+        self.operations
+    }
+}
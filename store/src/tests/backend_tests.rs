@@ -0,0 +1,61 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::{Store, BATCHES, HEADERS};
+
+/// Runs the same operations against a backend, checking it behaves as expected.
+fn check_backend<B: StorageBackend>(mut backend: B) {
+    // Write a few values to a column family, and one to another column family.
+    let mut operations: Vec<_> = (0u8..5)
+        .map(|i| Operation::Put(HEADERS, vec![i; 4], vec![i + 10; 4]))
+        .collect();
+    operations.push(Operation::Put(BATCHES, vec![2u8; 4], vec![0u8; 4]));
+    backend.write(&operations).unwrap();
+    assert_eq!(
+        backend.get(HEADERS, &[1u8; 4]).unwrap(),
+        Some(vec![11u8; 4])
+    );
+    assert_eq!(backend.get(HEADERS, &[9u8; 4]).unwrap(), None);
+
+    // Iterate in the order of the keys, starting from a key that is not in the store.
+    let keys: Vec<_> = backend
+        .iter_from(HEADERS, &[1u8, 2u8])
+        .map(|x| x.unwrap().0)
+        .collect();
+    assert_eq!(keys, vec![vec![2u8; 4], vec![3u8; 4], vec![4u8; 4]]);
+
+    // Delete a key and a range of keys, leaving the other column family untouched.
+    let operations = vec![
+        Operation::Delete(HEADERS, vec![0u8; 4]),
+        Operation::DeleteRange(HEADERS, vec![2u8; 4], vec![4u8; 4]),
+    ];
+    backend.write(&operations).unwrap();
+    let keys: Vec<_> = backend
+        .iter_from(HEADERS, &[])
+        .map(|x| x.unwrap().0)
+        .collect();
+    assert_eq!(keys, vec![vec![1u8; 4], vec![4u8; 4]]);
+    assert_eq!(backend.get(BATCHES, &[2u8; 4]).unwrap(), Some(vec![0u8; 4]));
+}
+
+#[test]
+fn memory_backend() {
+    check_backend(MemoryBackend::default());
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_backend() {
+    let path = ".db_test_rocksdb_backend";
+    let _ = std::fs::remove_dir_all(path);
+    let parameters = config::Parameters::default();
+    check_backend(crate::RocksDbBackend::new(path, &parameters).unwrap());
+}
+
+#[tokio::test]
+async fn in_memory_store() {
+    let mut store = Store::new_in_memory().column(HEADERS);
+    let mut store_copy = store.clone();
+    let handle = tokio::spawn(async move { store_copy.notify_read(vec![0u8; 4]).await });
+    store.write(vec![0u8; 4], vec![1u8; 4]).await;
+    assert_eq!(handle.await.unwrap().unwrap(), vec![1u8; 4]);
+}
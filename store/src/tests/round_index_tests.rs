@@ -0,0 +1,40 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::{BATCHES, HEADERS};
+
+#[tokio::test]
+async fn prune() {
+    // Create new store.
+    let mut store = Store::new_in_memory();
+    let mut headers = store.column(HEADERS);
+    let mut index = RoundIndex::new(&store, HEADERS);
+
+    // Write and index one key per round.
+    let mut batch = WriteBatch::default();
+    for round in 1..=4 {
+        let key = vec![round as u8; 4];
+        batch.put(&headers, key.clone(), vec![0u8; 4]);
+        index.insert(&mut batch, round, &key);
+    }
+    store.write_all(batch).await.unwrap();
+
+    // The same key in another column family is not indexed.
+    let mut batches = store.column(BATCHES);
+    batches.write(vec![1u8; 4], vec![0u8; 4]).await;
+
+    // Get the keys of round 3 and higher.
+    let keys = index.keys(3).await.unwrap();
+    assert_eq!(keys, vec![(3, vec![3u8; 4]), (4, vec![4u8; 4])]);
+
+    // Prune the keys of the rounds before round 3.
+    assert_eq!(index.prune(3).await.unwrap(), 2);
+    for round in 1..=4u8 {
+        let value = headers.read(vec![round; 4]).await.unwrap();
+        assert_eq!(value.is_some(), round >= 3);
+    }
+    assert!(batches.read(vec![1u8; 4]).await.unwrap().is_some());
+
+    // The index forgot the pruned keys.
+    assert_eq!(index.prune(3).await.unwrap(), 0);
+    assert_eq!(index.prune(5).await.unwrap(), 2);
+}
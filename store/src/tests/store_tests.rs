@@ -1,12 +1,12 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use std::fs;
 
+#[cfg(feature = "rocksdb")]
 #[tokio::test]
 async fn create_store() {
     // Create new store.
     let path = ".db_test_create_store";
-    let _ = fs::remove_dir_all(path);
+    let _ = std::fs::remove_dir_all(path);
     let store = Store::new(path);
     assert!(store.is_ok());
 }
@@ -14,9 +14,7 @@ async fn create_store() {
 #[tokio::test]
 async fn read_write_value() {
     // Create new store.
-    let path = ".db_test_read_write_value";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let mut store = Store::new_in_memory();
 
     // Write value to the store.
     let key = vec![0u8, 1u8, 2u8, 3u8];
@@ -34,9 +32,7 @@ async fn read_write_value() {
 #[tokio::test]
 async fn read_unknown_key() {
     // Create new store.
-    let path = ".db_test_read_unknown_key";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let mut store = Store::new_in_memory();
 
     // Try to read unknown key.
     let key = vec![0u8, 1u8, 2u8, 3u8];
@@ -48,9 +44,7 @@ async fn read_unknown_key() {
 #[tokio::test]
 async fn read_notify() {
     // Create new store.
-    let path = ".db_test_read_notify";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let mut store = Store::new_in_memory();
 
     // Try to read a kew that does not yet exist. Then write a value
     // for that key and check that notify read returns the result.
@@ -72,3 +66,91 @@ async fn read_notify() {
     store.write(key, value).await;
     assert!(handle.await.is_ok());
 }
+
+#[tokio::test]
+async fn write_batch() {
+    // Create new store.
+    let mut store = Store::new_in_memory();
+
+    // Wait for one of the keys of the batch.
+    let pairs: Vec<_> = (0u8..3).map(|i| (vec![i; 4], vec![i + 10; 4])).collect();
+    let mut store_copy = store.clone();
+    let (key, value) = pairs[1].clone();
+    let handle = tokio::spawn(async move {
+        match store_copy.notify_read(key).await {
+            Ok(v) => assert_eq!(v, value),
+            _ => panic!("Failed to read from store"),
+        }
+    });
+
+    // Write the batch and ensure all its values are in the store.
+    store.write_batch(pairs.clone()).await.unwrap();
+    assert!(handle.await.is_ok());
+    for (key, value) in pairs {
+        assert_eq!(store.read(key).await.unwrap(), Some(value));
+    }
+}
+
+#[tokio::test]
+async fn delete_values() {
+    // Create new store.
+    let mut store = Store::new_in_memory();
+
+    // Write a few values.
+    let pairs: Vec<_> = (0u8..5).map(|i| (vec![i; 4], vec![i + 10; 4])).collect();
+    store.write_batch(pairs.clone()).await.unwrap();
+
+    // Read a range of values, then the values under a prefix.
+    let result = store.read_range(vec![1u8; 4], vec![3u8; 4]).await.unwrap();
+    assert_eq!(result, pairs[1..3].to_vec());
+    let result = store.read_prefix(vec![2u8, 2u8]).await.unwrap();
+    assert_eq!(result, pairs[2..3].to_vec());
+
+    // Delete a value, then a range of values.
+    store.delete(vec![0u8; 4]).await;
+    store.delete_range(vec![1u8; 4], vec![3u8; 4]).await;
+    for (key, value) in pairs {
+        let expected = if key[0] < 3 { None } else { Some(value) };
+        assert_eq!(store.read(key).await.unwrap(), expected);
+    }
+}
+
+/// A storage backend whose writes always fail.
+struct FailingBackend(MemoryBackend);
+
+impl StorageBackend for FailingBackend {
+    fn get(&self, column: Column, key: &[u8]) -> StoreResult<Option<Value>> {
+        self.0.get(column, key)
+    }
+
+    fn write(&mut self, _operations: &[Operation]) -> StoreResult<()> {
+        Err(StoreError::new("Disk full"))
+    }
+
+    fn iter_from<'a>(
+        &'a self,
+        column: Column,
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = StoreResult<(Key, Value)>> + 'a> {
+        self.0.iter_from(column, from)
+    }
+}
+
+#[tokio::test]
+async fn failed_write() {
+    let mut store = Store::with_backend(FailingBackend(MemoryBackend::default()));
+
+    // Wait for one of the keys of the batch.
+    let pairs: Vec<_> = (0u8..3).map(|i| (vec![i; 4], vec![i + 10; 4])).collect();
+    let mut store_copy = store.clone();
+    let key = pairs[1].0.clone();
+    let handle = tokio::spawn(async move { store_copy.notify_read(key).await });
+    tokio::task::yield_now().await;
+
+    // The batch fails, and so does everyone waiting for its values.
+    assert!(store.write_batch(pairs.clone()).await.is_err());
+    assert!(handle.await.unwrap().is_err());
+    for (key, _) in pairs {
+        assert_eq!(store.read(key).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::{CERTIFICATES, HEADERS};
+
+#[tokio::test]
+async fn read_write_table() {
+    // Create new store.
+    let store = Store::new_in_memory();
+
+    // Write a value to a table.
+    let mut table = Table::<u64, String>::new(&store, HEADERS);
+    table.write(&1, &"one".to_string()).await;
+    assert_eq!(table.read(&1).await.unwrap(), Some("one".to_string()));
+
+    // Tables do not see each other's keys, nor the default column family.
+    let mut other = Table::<u64, String>::new(&store, CERTIFICATES);
+    assert!(other.read(&1).await.unwrap().is_none());
+    let key = bincode::serialize(&1u64).unwrap();
+    assert!(store.clone().read(key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn write_batch_to_tables() {
+    // Create new store.
+    let mut store = Store::new_in_memory();
+    let mut headers = Table::<u64, String>::new(&store, HEADERS);
+    let mut certificates = Table::<u64, u64>::new(&store, CERTIFICATES);
+
+    // Wait for a value of the batch.
+    let mut certificates_copy = certificates.clone();
+    let handle = tokio::spawn(async move { certificates_copy.notify_read(&1).await.unwrap() });
+
+    // Write to both tables at once.
+    let mut batch = WriteBatch::default();
+    batch.insert(&headers, &1, &"one".to_string());
+    batch.insert(&certificates, &1, &10);
+    store.write_all(batch).await.unwrap();
+
+    assert_eq!(handle.await.unwrap(), 10);
+    assert_eq!(headers.read(&1).await.unwrap(), Some("one".to_string()));
+    assert_eq!(certificates.read(&1).await.unwrap(), Some(10));
+}
@@ -0,0 +1,175 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::batch_maker::Batch;
+use crate::worker::WorkerMessage;
+use bytes::Bytes;
+use config::{Committee, WorkerId};
+use crypto::{Digest, PublicKey};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use futures::future::join_all;
+use futures::stream::futures_unordered::FuturesUnordered;
+use futures::stream::StreamExt as _;
+use log::warn;
+use network::{CancelHandler, ReliableSender};
+use primary::PrimaryWorkerMessage;
+use std::cmp::min;
+use std::convert::TryInto as _;
+use tokio::time::{sleep_until, timeout_at, Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/batch_fetcher_tests.rs"]
+pub mod batch_fetcher_tests;
+
+/// Fetches the batches referenced by committed certificates, so that executors can materialize
+/// the transactions. It first asks the worker of this authority holding the batch, and then asks
+/// the corresponding workers of all authorities until one of them serves the batch.
+pub struct BatchFetcher {
+    /// The public key of this authority.
+    name: PublicKey,
+    /// The committee information.
+    committee: Committee,
+    /// How long we first wait for the replies before asking again. Denominated in ms.
+    retry_delay: u64,
+    /// A network sender to request the batches and receive the replies.
+    network: ReliableSender,
+}
+
+impl BatchFetcher {
+    /// The maximum delay between two requests for the missing batches, in multiples of
+    /// `retry_delay`.
+    pub const MAX_BACKOFF: u64 = 64;
+
+    pub fn new(name: PublicKey, committee: Committee, retry_delay: u64) -> Self {
+        Self {
+            name,
+            committee,
+            retry_delay,
+            network: ReliableSender::new(),
+        }
+    }
+
+    /// Fetches the specified batches. The returned batches are in the same order as the requested
+    /// digests. The workers of at least f+1 authorities hold each committed batch, so we ask again
+    /// (backing off) until one of them serves it: executors never skip a committed batch.
+    pub async fn fetch(&mut self, payload: &[(Digest, WorkerId)]) -> Vec<Batch> {
+        let mut batches: Vec<Option<Batch>> = vec![None; payload.len()];
+        let mut delay = self.retry_delay;
+        // Only ask our own worker first: if it does not have a batch, ask everyone.
+        let mut remote = false;
+        loop {
+            // Request all the batches we are still missing.
+            let mut requests = Vec::new();
+            for ((digest, worker_id), batch) in payload.iter().zip(&batches) {
+                if batch.is_none() {
+                    let handlers = self.request(digest, worker_id, remote).await;
+                    requests.push((digest, handlers));
+                }
+            }
+            if requests.is_empty() {
+                break;
+            }
+
+            // Wait for the replies (for at most `delay` ms).
+            let deadline = Instant::now() + Duration::from_millis(delay);
+            let replies = join_all(requests.into_iter().map(|(digest, handlers)| async move {
+                let reply = timeout_at(deadline, Self::first_valid(digest, handlers)).await;
+                reply.ok().flatten()
+            }))
+            .await;
+
+            let missing = batches.iter_mut().filter(|batch| batch.is_none());
+            for (batch, reply) in missing.zip(replies) {
+                *batch = reply;
+            }
+
+            if remote && batches.iter().any(|x| x.is_none()) {
+                let missing: Vec<_> = payload
+                    .iter()
+                    .zip(&batches)
+                    .filter(|(_, batch)| batch.is_none())
+                    .map(|((digest, _), _)| digest)
+                    .collect();
+                warn!("Batches {:?} are still missing, retrying", missing);
+                sleep_until(deadline).await;
+                delay = min(2 * delay, Self::MAX_BACKOFF * self.retry_delay);
+            }
+            remote = true;
+        }
+        batches.into_iter().flatten().collect()
+    }
+
+    /// Asks our worker `worker_id` for a batch and, if `remote` is set, the workers of the other
+    /// authorities with the same id.
+    async fn request(
+        &mut self,
+        digest: &Digest,
+        worker_id: &WorkerId,
+        remote: bool,
+    ) -> Vec<CancelHandler> {
+        let mut handlers = Vec::new();
+        match self.committee.worker(&self.name, worker_id) {
+            Ok(x) => {
+                let message = PrimaryWorkerMessage::RequestBatch(digest.clone());
+                let bytes = bincode::serialize(&message).expect("Failed to serialize request");
+                let address = x.primary_to_worker;
+                handlers.push(self.network.send(address, Bytes::from(bytes)).await);
+            }
+            Err(e) => warn!("Cannot fetch batch {} from our worker: {}", digest, e),
+        }
+        if remote {
+            let addresses = self
+                .committee
+                .others_workers(&self.name, worker_id)
+                .into_iter()
+                .map(|(_, x)| x.worker_to_worker)
+                .collect();
+            let message = WorkerMessage::RequestBatch(digest.clone());
+            let bytes = bincode::serialize(&message).expect("Failed to serialize request");
+            let others = self.network.broadcast(addresses, Bytes::from(bytes)).await;
+            handlers.extend(others);
+
+            // The certificates only reference the workers of the committee.
+            assert!(
+                !handlers.is_empty(),
+                "No worker {} to fetch batch {} from",
+                worker_id,
+                digest
+            );
+        }
+        handlers
+    }
+
+    /// Returns the first batch matching the digest among the replies.
+    async fn first_valid(digest: &Digest, handlers: Vec<CancelHandler>) -> Option<Batch> {
+        let mut replies: FuturesUnordered<_> = handlers.into_iter().collect();
+        while let Some(reply) = replies.next().await {
+            if let Some(batch) = reply.ok().and_then(|x| Self::parse(digest, x)) {
+                return Some(batch);
+            }
+        }
+        None
+    }
+
+    /// Parses the reply to a batch request, and checks that it holds the requested batch.
+    fn parse(digest: &Digest, reply: Bytes) -> Option<Batch> {
+        let serialized = match bincode::deserialize::<Option<Vec<u8>>>(&reply) {
+            Ok(x) => x?,
+            Err(e) => {
+                warn!("Invalid reply to the request for batch {}: {}", digest, e);
+                return None;
+            }
+        };
+        let hash = Digest(Sha512::digest(&serialized)[..32].try_into().unwrap());
+        if &hash != digest {
+            warn!("Received a batch that does not match digest {}", digest);
+            return None;
+        }
+        match bincode::deserialize(&serialized) {
+            Ok(WorkerMessage::Batch(batch)) => Some(batch),
+            _ => {
+                warn!("Received an invalid batch for {}", digest);
+                None
+            }
+        }
+    }
+}
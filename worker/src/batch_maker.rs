@@ -1,19 +1,21 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use crate::compression::compress;
+use crate::erasure::ErasureCoder;
 use crate::quorum_waiter::QuorumWaiterMessage;
 use crate::worker::WorkerMessage;
 use bytes::Bytes;
-#[cfg(feature = "benchmark")]
-use crypto::Digest;
-use crypto::PublicKey;
-#[cfg(feature = "benchmark")]
+use config::BatchCompression;
+use crypto::{Digest, PublicKey};
 use ed25519_dalek::{Digest as _, Sha512};
 #[cfg(feature = "benchmark")]
 use log::info;
 use network::ReliableSender;
-#[cfg(feature = "benchmark")]
 use std::convert::TryInto as _;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use tokio::time::{sleep, Duration, Instant};
 
 #[cfg(test)]
@@ -29,27 +31,41 @@ pub struct BatchMaker {
     batch_size: usize,
     /// The maximum delay after which to seal the batch (in ms).
     max_batch_delay: u64,
-    /// Channel to receive transactions from the network.
-    rx_transaction: Receiver<Transaction>,
+    /// Channel to receive transactions from the network, along with the channel to notify once
+    /// their batch is sealed (if the client asked for a receipt).
+    rx_transaction: Receiver<(Transaction, Option<oneshot::Sender<Digest>>)>,
     /// Output channel to deliver sealed batches to the `QuorumWaiter`.
     tx_message: Sender<QuorumWaiterMessage>,
     /// The network addresses of the other workers that share our worker id.
     workers_addresses: Vec<(PublicKey, SocketAddr)>,
+    /// The number of sealed batches awaiting acknowledgements (decremented by the `QuorumWaiter`).
+    pending_batches: Arc<AtomicUsize>,
+    /// How we compress the batches we broadcast.
+    compression: BatchCompression,
+    /// Encodes the batches into chunks if we only send every other worker its chunk (rather
+    /// than the whole batch).
+    coder: Option<ErasureCoder>,
     /// Holds the current batch.
     current_batch: Batch,
     /// Holds the size of the current batch (in bytes).
     current_batch_size: usize,
+    /// The clients waiting for the digest of the current batch.
+    current_receipts: Vec<oneshot::Sender<Digest>>,
     /// A network sender to broadcast the batches to the other workers.
     network: ReliableSender,
 }
 
 impl BatchMaker {
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         batch_size: usize,
         max_batch_delay: u64,
-        rx_transaction: Receiver<Transaction>,
+        rx_transaction: Receiver<(Transaction, Option<oneshot::Sender<Digest>>)>,
         tx_message: Sender<QuorumWaiterMessage>,
         workers_addresses: Vec<(PublicKey, SocketAddr)>,
+        pending_batches: Arc<AtomicUsize>,
+        compression: BatchCompression,
+        coder: Option<ErasureCoder>,
     ) {
         tokio::spawn(async move {
             Self {
@@ -58,8 +74,12 @@ impl BatchMaker {
                 rx_transaction,
                 tx_message,
                 workers_addresses,
+                pending_batches,
+                compression,
+                coder,
                 current_batch: Batch::with_capacity(batch_size * 2),
                 current_batch_size: 0,
+                current_receipts: Vec::new(),
                 network: ReliableSender::new(),
             }
             .run()
@@ -75,9 +95,10 @@ impl BatchMaker {
         loop {
             tokio::select! {
                 // Assemble client transactions into batches of preset size.
-                Some(transaction) = self.rx_transaction.recv() => {
+                Some((transaction, receipt)) = self.rx_transaction.recv() => {
                     self.current_batch_size += transaction.len();
                     self.current_batch.push(transaction);
+                    self.current_receipts.extend(receipt);
                     if self.current_batch_size >= self.batch_size {
                         self.seal().await;
                         timer.as_mut().reset(Instant::now() + Duration::from_millis(self.max_batch_delay));
@@ -118,34 +139,61 @@ impl BatchMaker {
         let message = WorkerMessage::Batch(batch);
         let serialized = bincode::serialize(&message).expect("Failed to serialize our own batch");
 
-        #[cfg(feature = "benchmark")]
-        {
-            // NOTE: This is one extra hash that is only needed to print the following log entries.
-            let digest = Digest(
-                Sha512::digest(&serialized).as_slice()[..32]
-                    .try_into()
-                    .unwrap(),
-            );
-
-            for id in tx_ids {
-                // NOTE: This log entry is used to compute performance.
-                info!(
-                    "Batch {:?} contains sample tx {}",
-                    digest,
-                    u64::from_be_bytes(id)
-                );
+        // NOTE: This is one extra hash that is only needed to send receipts to the clients, to
+        // tag the chunks of the batch, and to print the benchmark log entries.
+        let receipts = std::mem::take(&mut self.current_receipts);
+        let needs_digest = !receipts.is_empty() || self.coder.is_some();
+        let digest = match cfg!(feature = "benchmark") || needs_digest {
+            true => Some(Digest(
+                Sha512::digest(&serialized)[..32].try_into().unwrap(),
+            )),
+            false => None,
+        };
+        if let Some(digest) = &digest {
+            for receipt in receipts {
+                let _ = receipt.send(digest.clone());
             }
 
-            // NOTE: This log entry is used to compute performance.
-            info!("Batch {:?} contains {} B", digest, size);
+            #[cfg(feature = "benchmark")]
+            {
+                for id in tx_ids {
+                    // NOTE: This log entry is used to compute performance.
+                    info!(
+                        "Batch {:?} contains sample tx {}",
+                        digest,
+                        u64::from_be_bytes(id)
+                    );
+                }
+
+                // NOTE: This log entry is used to compute performance.
+                info!("Batch {:?} contains {} B", digest, size);
+            }
         }
 
-        // Broadcast the batch through the network.
+        // Broadcast the batch (or its chunks) through the network. We only compress it for the
+        // network: its digest is computed over its uncompressed form.
         let (names, addresses): (Vec<_>, _) = self.workers_addresses.iter().cloned().unzip();
-        let bytes = Bytes::from(serialized.clone());
-        let handlers = self.network.broadcast(addresses, bytes).await;
+        let compressed = compress(self.compression, serialized.clone());
+        let handlers = match (&self.coder, digest) {
+            (Some(coder), Some(digest)) => {
+                let chunks = coder.encode(&digest, &compressed);
+                let mut handlers = Vec::new();
+                for (name, address) in &self.workers_addresses {
+                    let index = coder.index(name).expect("Unknown authority");
+                    let message = WorkerMessage::Chunk(chunks[index].clone());
+                    let bytes = bincode::serialize(&message).expect("Failed to serialize chunk");
+                    handlers.push(self.network.send(*address, Bytes::from(bytes)).await);
+                }
+                handlers
+            }
+            _ => {
+                let bytes = Bytes::from(compressed);
+                self.network.broadcast(addresses, bytes).await
+            }
+        };
 
         // Send the batch through the deliver channel for further processing.
+        self.pending_batches.fetch_add(1, Ordering::Relaxed);
         self.tx_message
             .send(QuorumWaiterMessage {
                 batch: serialized,
@@ -0,0 +1,81 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::worker::Round;
+use crypto::Digest;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+#[cfg(test)]
+#[path = "tests/commit_notifier_tests.rs"]
+pub mod commit_notifier_tests;
+
+#[derive(Default)]
+struct Inner {
+    /// The batches committed during the last `gc_depth` rounds, along with the round of the
+    /// certificate committing them.
+    committed: HashMap<Digest, Round>,
+    /// The clients waiting for batches to be committed, along with the round of the last commit
+    /// when they started waiting.
+    waiting: HashMap<Digest, Vec<(Round, oneshot::Sender<Round>)>>,
+    /// The round of the last commit.
+    round: Round,
+}
+
+/// Tells clients when the batches they submitted are committed. Our primary notifies us of the
+/// batches committed by the consensus, and we remember them for `gc_depth` rounds so that clients
+/// may ask after the fact. Likewise, clients wait for at most `gc_depth` rounds.
+#[derive(Clone)]
+pub struct CommitNotifier {
+    inner: Arc<Mutex<Inner>>,
+    /// The depth of the garbage collector.
+    gc_depth: Round,
+}
+
+impl CommitNotifier {
+    pub fn new(gc_depth: Round) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::default())),
+            gc_depth,
+        }
+    }
+
+    /// Returns the round of the certificate committing the batch, once it is committed. Returns an
+    /// error if it is not committed within `gc_depth` rounds.
+    pub async fn wait(&self, digest: Digest) -> Result<Round, oneshot::error::RecvError> {
+        let receiver = {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(round) = inner.committed.get(&digest) {
+                return Ok(*round);
+            }
+            let (sender, receiver) = oneshot::channel();
+            let round = inner.round;
+            inner
+                .waiting
+                .entry(digest)
+                .or_default()
+                .push((round, sender));
+            receiver
+        };
+        receiver.await
+    }
+
+    /// Records that the batches were committed by a certificate of the specified round.
+    pub fn commit(&self, digests: Vec<Digest>, round: Round) {
+        let mut inner = self.inner.lock().unwrap();
+        for digest in digests {
+            for (_, sender) in inner.waiting.remove(&digest).unwrap_or_default() {
+                let _ = sender.send(round);
+            }
+            inner.committed.insert(digest, round);
+        }
+        inner.round = inner.round.max(round);
+
+        // Cleanup internal state.
+        let gc_depth = self.gc_depth;
+        inner.committed.retain(|_, r| *r + gc_depth >= round);
+        inner.waiting.retain(|_, senders| {
+            senders.retain(|(r, x)| !x.is_closed() && r + gc_depth >= round);
+            !senders.is_empty()
+        });
+    }
+}
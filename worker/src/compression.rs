@@ -0,0 +1,76 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::processor::SerializedBatchMessage;
+use crate::worker::WorkerMessage;
+use config::BatchCompression;
+use std::convert::TryInto as _;
+use std::error::Error;
+
+#[cfg(test)]
+#[path = "tests/compression_tests.rs"]
+pub mod compression_tests;
+
+/// Encodes a serialized `WorkerMessage::Batch` for the network: returns a serialized
+/// `WorkerMessage::CompressedBatch`, or the batch itself if compression is disabled.
+pub fn compress(
+    compression: BatchCompression,
+    batch: SerializedBatchMessage,
+) -> SerializedBatchMessage {
+    let compressed = match compression {
+        BatchCompression::None => return batch,
+        BatchCompression::Lz4 => lz4_flex::compress_prepend_size(&batch),
+        BatchCompression::Zstd => zstd::bulk::compress(&batch, zstd::DEFAULT_COMPRESSION_LEVEL)
+            .expect("Failed to compress batch"),
+    };
+    let message = WorkerMessage::CompressedBatch(compression, compressed);
+    bincode::serialize(&message).expect("Failed to serialize compressed batch")
+}
+
+/// Returns the serialized `WorkerMessage::Batch` held in a compressed batch. It fails if the batch
+/// is larger than `max_size` bytes, so that a peer cannot make us allocate arbitrary amounts of
+/// memory.
+pub fn decompress(
+    compression: BatchCompression,
+    compressed: &[u8],
+    max_size: usize,
+) -> Result<SerializedBatchMessage, Box<dyn Error>> {
+    let batch = match compression {
+        BatchCompression::None if compressed.len() > max_size => {
+            return Err(format!("Batch too large ({} B)", compressed.len()).into())
+        }
+        BatchCompression::None => compressed.to_vec(),
+        BatchCompression::Lz4 => {
+            let size = compressed
+                .get(..4)
+                .ok_or("Compressed batch too short")?
+                .try_into()
+                .map(u32::from_le_bytes)?;
+            if size as usize > max_size {
+                return Err(format!("Compressed batch too large ({} B)", size).into());
+            }
+            lz4_flex::decompress_size_prepended(compressed)?
+        }
+        BatchCompression::Zstd => zstd::bulk::decompress(compressed, max_size)?,
+    };
+    Ok(batch)
+}
+
+/// Returns the uncompressed form of a batch, checking that it holds a `WorkerMessage::Batch` of
+/// at most `max_size` bytes.
+pub fn uncompressed(
+    compression: BatchCompression,
+    batch: SerializedBatchMessage,
+    max_size: usize,
+) -> Result<SerializedBatchMessage, Box<dyn Error>> {
+    if compression == BatchCompression::None {
+        return Ok(batch);
+    }
+    let (compression, compressed) = match bincode::deserialize(&batch)? {
+        WorkerMessage::CompressedBatch(compression, compressed) => (compression, compressed),
+        _ => return Ok(batch),
+    };
+    let batch = decompress(compression, &compressed, max_size)?;
+    match bincode::deserialize(&batch)? {
+        WorkerMessage::Batch(..) => Ok(batch),
+        _ => Err("Compressed message is not a batch".into()),
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::batch_maker::{Batch, Transaction};
+use crate::worker::Round;
+use crypto::Digest;
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use log::debug;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryInto as _;
+use store::{Store, StoreError, WriteBatch};
+
+#[cfg(test)]
+#[path = "tests/deduplicator_tests.rs"]
+pub mod deduplicator_tests;
+
+/// Computes the digest identifying a transaction.
+pub fn transaction_digest(transaction: &Transaction) -> Digest {
+    Digest(Sha512::digest(transaction)[..32].try_into().unwrap())
+}
+
+/// Filters the committed transactions that were already output within the last `window` rounds,
+/// so that a transaction submitted to several workers (or resubmitted by a client) is only
+/// executed once. The digests of the output transactions are persisted, so a restarted node does
+/// not output them again.
+pub struct Deduplicator {
+    /// The persistent storage.
+    store: Store,
+    /// The number of rounds during which we remember the output transactions. Zero disables the
+    /// deduplication.
+    window: Round,
+    /// The digests of the transactions we output.
+    seen: HashSet<Digest>,
+    /// The digests of `seen`, indexed by the round at which we output them.
+    by_round: BTreeMap<Round, Vec<Digest>>,
+}
+
+impl Deduplicator {
+    /// The key holding the last round we persisted.
+    const LAST_ROUND_KEY: &'static [u8] = b"dedup_last_round";
+
+    /// The key holding the digests of the transactions output at a round.
+    fn round_key(round: Round) -> Vec<u8> {
+        [b"dedup_round".as_ref(), &round.to_le_bytes()].concat()
+    }
+
+    /// Makes a new deduplicator, loading the transactions output within the window.
+    pub async fn new(mut store: Store, window: Round) -> Result<Self, StoreError> {
+        let mut seen = HashSet::new();
+        let mut by_round = BTreeMap::new();
+        if window > 0 {
+            if let Some(bytes) = store.read(Self::LAST_ROUND_KEY.to_vec()).await? {
+                let last: Round = bincode::deserialize(&bytes).expect("Failed to load dedup state");
+                for round in last.saturating_sub(window)..=last {
+                    if let Some(bytes) = store.read(Self::round_key(round)).await? {
+                        let digests: Vec<Digest> =
+                            bincode::deserialize(&bytes).expect("Failed to load dedup state");
+                        seen.extend(digests.iter().cloned());
+                        by_round.insert(round, digests);
+                    }
+                }
+                debug!(
+                    "Loaded {} transaction digests up to round {}",
+                    seen.len(),
+                    last
+                );
+            }
+        }
+        Ok(Self {
+            store,
+            window,
+            seen,
+            by_round,
+        })
+    }
+
+    /// Returns the transactions of the batch that we did not output yet, and marks them as output
+    /// at the specified round. Call `persist` once the application got them: a crash in between
+    /// outputs them again after the restart (rather than losing them).
+    pub fn filter(&mut self, round: Round, batch: Batch) -> Batch {
+        if self.window == 0 {
+            return batch;
+        }
+        batch
+            .into_iter()
+            .filter(|transaction| {
+                let digest = transaction_digest(transaction);
+                let fresh = self.seen.insert(digest.clone());
+                if fresh {
+                    self.by_round.entry(round).or_default().push(digest);
+                } else {
+                    debug!("Filtered duplicate transaction {}", digest);
+                }
+                fresh
+            })
+            .collect()
+    }
+
+    /// Persists (atomically) the transactions output at the specified round and forgets the ones
+    /// that are out of the window.
+    pub async fn persist(&mut self, round: Round) -> Result<(), StoreError> {
+        if self.window == 0 {
+            return Ok(());
+        }
+        let mut batch = WriteBatch::default();
+        if let Some(digests) = self.by_round.get(&round) {
+            let bytes = bincode::serialize(digests).expect("Failed to serialize digests");
+            batch.put(&self.store, Self::round_key(round), bytes);
+        }
+        let bytes = bincode::serialize(&round).expect("Failed to serialize round");
+        batch.put(&self.store, Self::LAST_ROUND_KEY.to_vec(), bytes);
+        self.store.write_all(batch).await?;
+
+        // Cleanup internal state.
+        let keep = self.by_round.split_off(&round.saturating_sub(self.window));
+        let expired = std::mem::replace(&mut self.by_round, keep);
+        for digest in expired.into_values().flatten() {
+            self.seen.remove(&digest);
+        }
+        Ok(())
+    }
+}
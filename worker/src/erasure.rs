@@ -0,0 +1,116 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use config::Committee;
+use crypto::{Digest, PublicKey};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[path = "tests/erasure_tests.rs"]
+pub mod erasure_tests;
+
+/// A chunk of an erasure-coded batch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The digest of the batch.
+    pub digest: Digest,
+    /// The index of the chunk, which is also the index of the authority storing it.
+    pub index: usize,
+    /// The size of the encoded batch (without padding). Denominated in bytes.
+    pub size: usize,
+    /// The chunk itself.
+    pub data: Vec<u8>,
+}
+
+/// Returns the key under which we store our chunk of a batch.
+pub fn chunk_key(digest: &Digest) -> Vec<u8> {
+    [digest.as_ref(), b"chunk"].concat()
+}
+
+/// Reed-Solomon encodes batches into one chunk per authority, such that any f+1 chunks suffice
+/// to reconstruct them. This assumes all authorities have the same stake.
+#[derive(Clone)]
+pub struct ErasureCoder {
+    /// The authorities, in the order of their chunks.
+    names: Vec<PublicKey>,
+    /// The number of chunks needed to reconstruct a batch.
+    data_chunks: usize,
+    /// The Reed-Solomon codec. A committee of one authority does not need one: its only chunk is
+    /// the batch itself.
+    codec: Option<ReedSolomon>,
+}
+
+impl ErasureCoder {
+    pub fn new(committee: &Committee) -> Self {
+        let names: Vec<_> = committee.authorities.keys().cloned().collect();
+        let faults = names.len().saturating_sub(1) / 3;
+        let data_chunks = faults + 1;
+        let codec = ReedSolomon::new(data_chunks, names.len() - data_chunks).ok();
+        Self {
+            names,
+            data_chunks,
+            codec,
+        }
+    }
+
+    /// Returns the number of chunks needed to reconstruct a batch.
+    pub fn data_chunks(&self) -> usize {
+        self.data_chunks
+    }
+
+    /// Returns the index of the chunk stored by the specified authority.
+    pub fn index(&self, name: &PublicKey) -> Option<usize> {
+        self.names.iter().position(|x| x == name)
+    }
+
+    /// Encodes a batch into one chunk per authority.
+    pub fn encode(&self, digest: &Digest, batch: &[u8]) -> Vec<Chunk> {
+        let chunk_size = std::cmp::max(1, batch.len().div_ceil(self.data_chunks));
+        let mut shards: Vec<_> = batch.chunks(chunk_size).map(|x| x.to_vec()).collect();
+        shards.resize(self.names.len(), Vec::new());
+        for shard in &mut shards {
+            shard.resize(chunk_size, 0);
+        }
+        if let Some(codec) = &self.codec {
+            codec.encode(&mut shards).expect("Failed to encode batch");
+        }
+        shards
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| Chunk {
+                digest: digest.clone(),
+                index,
+                size: batch.len(),
+                data,
+            })
+            .collect()
+    }
+
+    /// Reconstructs a batch from its chunks. The caller must check the digest of the output: a
+    /// Byzantine author may encode its batch inconsistently.
+    pub fn decode(&self, chunks: &[Chunk]) -> Option<Vec<u8>> {
+        let first = chunks.first()?;
+        let mut shards: Vec<Option<Vec<u8>>> = vec![None; self.names.len()];
+        for chunk in chunks {
+            if chunk.index < shards.len()
+                && chunk.size == first.size
+                && chunk.data.len() == first.data.len()
+            {
+                shards[chunk.index] = Some(chunk.data.clone());
+            }
+        }
+        if let Some(codec) = &self.codec {
+            codec.reconstruct_data(&mut shards).ok()?;
+        }
+        let mut batch: Vec<u8> = shards
+            .into_iter()
+            .take(self.data_chunks)
+            .flatten()
+            .flatten()
+            .collect();
+        if batch.len() < first.size {
+            return None;
+        }
+        batch.truncate(first.size);
+        Some(batch)
+    }
+}
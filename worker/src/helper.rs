@@ -1,6 +1,9 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use crate::compression::compress;
+use crate::erasure::chunk_key;
+use crate::worker::WorkerMessage;
 use bytes::Bytes;
-use config::{Committee, WorkerId};
+use config::{BatchCompression, Committee, WorkerId};
 use crypto::{Digest, PublicKey};
 use log::{error, warn};
 use network::SimpleSender;
@@ -13,6 +16,8 @@ pub mod helper_tests;
 
 /// A task dedicated to help other authorities by replying to their batch requests.
 pub struct Helper {
+    /// The public key of this authority.
+    name: PublicKey,
     /// The id of this worker.
     id: WorkerId,
     /// The committee information.
@@ -21,23 +26,29 @@ pub struct Helper {
     store: Store,
     /// Input channel to receive batch requests.
     rx_request: Receiver<(Vec<Digest>, PublicKey)>,
+    /// How we compress the batches we send.
+    compression: BatchCompression,
     /// A network sender to send the batches to the other workers.
     network: SimpleSender,
 }
 
 impl Helper {
     pub fn spawn(
+        name: PublicKey,
         id: WorkerId,
         committee: Committee,
         store: Store,
         rx_request: Receiver<(Vec<Digest>, PublicKey)>,
+        compression: BatchCompression,
     ) {
         tokio::spawn(async move {
             Self {
+                name,
                 id,
                 committee,
                 store,
                 rx_request,
+                compression,
                 network: SimpleSender::new(),
             }
             .run()
@@ -61,8 +72,19 @@ impl Helper {
             // Reply to the request (the best we can).
             for digest in digests {
                 match self.store.read(digest.to_vec()).await {
-                    Ok(Some(data)) => self.network.send(address, Bytes::from(data)).await,
-                    Ok(None) => (),
+                    Ok(Some(data)) => {
+                        let data = compress(self.compression, data);
+                        let message = WorkerMessage::BatchReply(data, self.name);
+                        let serialized = bincode::serialize(&message)
+                            .expect("Failed to serialize our own message");
+                        self.network.send(address, Bytes::from(serialized)).await
+                    }
+                    // If the batch was erasure-coded, we may only hold our chunk of it.
+                    Ok(None) => match self.store.read(chunk_key(&digest)).await {
+                        Ok(Some(data)) => self.network.send(address, Bytes::from(data)).await,
+                        Ok(None) => (),
+                        Err(e) => error!("{}", e),
+                    },
                     Err(e) => error!("{}", e),
                 }
             }
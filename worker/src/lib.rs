@@ -1,9 +1,17 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+mod batch_fetcher;
 mod batch_maker;
+mod commit_notifier;
+mod compression;
+mod deduplicator;
+mod erasure;
 mod helper;
 mod primary_connector;
 mod processor;
+mod pruner;
 mod quorum_waiter;
+mod rate_limiter;
+mod reconstructor;
 mod synchronizer;
 mod worker;
 
@@ -11,5 +19,9 @@ mod worker;
 #[path = "tests/common.rs"]
 mod common;
 
+pub use crate::batch_fetcher::BatchFetcher;
+pub use crate::batch_maker::Batch;
+pub use crate::deduplicator::{transaction_digest, Deduplicator};
 pub use crate::worker::Worker;
 pub use crate::worker::WorkerMessage;
+pub use crate::worker::{ClientMessage, ClientReply};
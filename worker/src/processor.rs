@@ -1,49 +1,78 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use crate::compression::uncompressed;
 use crate::worker::SerializedBatchDigestMessage;
-use config::WorkerId;
+use config::{BatchCompression, WorkerId};
 use crypto::Digest;
 use ed25519_dalek::Digest as _;
 use ed25519_dalek::Sha512;
+use log::{error, warn};
 use primary::WorkerPrimaryMessage;
 use std::convert::TryInto;
-use store::Store;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{RoundIndex, Store, WriteBatch, BATCHES};
 use tokio::sync::mpsc::{Receiver, Sender};
 
 #[cfg(test)]
 #[path = "tests/processor_tests.rs"]
 pub mod processor_tests;
 
-/// Indicates a serialized `WorkerMessage::Batch` message.
+/// Indicates a serialized `WorkerMessage::Batch` message (or `WorkerMessage::CompressedBatch`
+/// when sent over the network).
 pub type SerializedBatchMessage = Vec<u8>;
 
 /// Hashes and stores batches, it then outputs the batch's digest.
 pub struct Processor;
 
 impl Processor {
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         // Our worker's id.
         id: WorkerId,
         // The persistent storage.
         mut store: Store,
+        // The last committed round, under which we index the batches we store (see `Pruner`).
+        consensus_round: Arc<AtomicU64>,
         // Input channel to receive batches.
         mut rx_batch: Receiver<SerializedBatchMessage>,
         // Output channel to send out batches' digests.
         tx_digest: Sender<SerializedBatchDigestMessage>,
         // Whether we are processing our own batches or the batches of other nodes.
         own_digest: bool,
+        // The compression of the batches we receive.
+        compression: BatchCompression,
+        // The maximum size of the (uncompressed) batches we receive.
+        max_batch_size: usize,
     ) {
         tokio::spawn(async move {
+            let index = RoundIndex::new(&store, BATCHES);
             while let Some(batch) = rx_batch.recv().await {
+                // Decompress the batch: its digest is computed over its uncompressed form.
+                let batch = match uncompressed(compression, batch, max_batch_size) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        warn!("Received an invalid compressed batch: {}", e);
+                        continue;
+                    }
+                };
+
                 // Hash the batch.
                 let digest = Digest(Sha512::digest(&batch).as_slice()[..32].try_into().unwrap());
 
-                // Store the batch.
-                store.write(digest.to_vec(), batch.clone()).await; // SỬA ĐỔI: clone batch
+                // Store the batch, along with its round index entry.
+                let round = consensus_round.load(Ordering::Relaxed);
+                let mut write = WriteBatch::default();
+                write.put(&store, digest.to_vec(), batch);
+                index.insert(&mut write, round, digest.as_ref());
+                if let Err(e) = store.write_all(write).await {
+                    error!("Failed to store batch {}: {}", digest, e);
+                    continue;
+                }
 
-                // SỬA ĐỔI: Gửi kèm dữ liệu batch trong message
+                // Deliver the batch's digest (the primary never needs the batch itself).
                 let message = match own_digest {
-                    true => WorkerPrimaryMessage::OurBatch(digest, id, batch),
-                    false => WorkerPrimaryMessage::OthersBatch(digest, id, batch),
+                    true => WorkerPrimaryMessage::OurBatch(digest, id),
+                    false => WorkerPrimaryMessage::OthersBatch(digest, id),
                 };
                 let message = bincode::serialize(&message)
                     .expect("Failed to serialize our own worker-primary message");
@@ -0,0 +1,49 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::worker::Round;
+use log::{debug, error};
+use store::{RoundIndex, Store, BATCHES};
+use tokio::sync::mpsc::Receiver;
+
+#[cfg(test)]
+#[path = "tests/pruner_tests.rs"]
+pub mod pruner_tests;
+
+/// Deletes from the store the batches (and chunks) we stored more than `retention_depth` rounds
+/// before the last committed round. The `Processor` and the `Reconstructor` index what they store
+/// under the last committed round at the time.
+pub struct Pruner {
+    /// The round index of the batches.
+    index: RoundIndex,
+    /// How many rounds (before the last committed round) we keep.
+    retention_depth: Round,
+    /// Receives the last committed round from our primary.
+    rx_round: Receiver<Round>,
+}
+
+impl Pruner {
+    pub fn spawn(store: Store, retention_depth: Round, rx_round: Receiver<Round>) {
+        tokio::spawn(async move {
+            Self {
+                index: RoundIndex::new(&store, BATCHES),
+                retention_depth,
+                rx_round,
+            }
+            .run()
+            .await;
+        });
+    }
+
+    async fn run(&mut self) {
+        while let Some(round) = self.rx_round.recv().await {
+            if round <= self.retention_depth {
+                continue;
+            }
+            let prune_round = round - self.retention_depth;
+            match self.index.prune(prune_round).await {
+                Ok(0) => (),
+                Ok(pruned) => debug!("Pruned {} batches before round {}", pruned, prune_round),
+                Err(e) => error!("Failed to prune the store: {}", e),
+            }
+        }
+    }
+}
@@ -5,6 +5,8 @@ use crypto::PublicKey;
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
 use network::CancelHandler;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 #[cfg(test)]
@@ -29,6 +31,8 @@ pub struct QuorumWaiter {
     rx_message: Receiver<QuorumWaiterMessage>,
     /// Channel to deliver batches for which we have enough acknowledgements.
     tx_batch: Sender<SerializedBatchMessage>,
+    /// The number of sealed batches awaiting acknowledgements (incremented by the `BatchMaker`).
+    pending_batches: Arc<AtomicUsize>,
 }
 
 impl QuorumWaiter {
@@ -38,6 +42,7 @@ impl QuorumWaiter {
         stake: Stake,
         rx_message: Receiver<QuorumWaiterMessage>,
         tx_batch: Sender<Vec<u8>>,
+        pending_batches: Arc<AtomicUsize>,
     ) {
         tokio::spawn(async move {
             Self {
@@ -45,6 +50,7 @@ impl QuorumWaiter {
                 stake,
                 rx_message,
                 tx_batch,
+                pending_batches,
             }
             .run()
             .await;
@@ -82,6 +88,7 @@ impl QuorumWaiter {
                     break;
                 }
             }
+            self.pending_batches.fetch_sub(1, Ordering::Relaxed);
         }
     }
 }
@@ -0,0 +1,59 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[cfg(test)]
+#[path = "tests/rate_limiter_tests.rs"]
+pub mod rate_limiter_tests;
+
+struct Bucket {
+    /// The number of transactions the client may still submit right away.
+    tokens: f64,
+    /// The last time we refilled the bucket.
+    last_refill: Instant,
+}
+
+/// A token bucket limiting the rate at which a client submits transactions, allowing bursts of
+/// up to one second worth of transactions. Cloning a limiter yields a new, full bucket: since the
+/// network receiver clones its handler for every connection, each connection is limited
+/// separately.
+pub struct RateLimiter {
+    /// The maximum rate. Denominated in transactions per second. Zero disables the limit.
+    rate: u64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes one token from the bucket. Returns false if the client exceeded its rate.
+    pub fn try_acquire(&self) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        bucket.last_refill = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+impl Clone for RateLimiter {
+    fn clone(&self) -> Self {
+        Self::new(self.rate)
+    }
+}
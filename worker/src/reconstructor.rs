@@ -0,0 +1,265 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use crate::compression::uncompressed;
+use crate::erasure::{chunk_key, Chunk, ErasureCoder};
+use crate::processor::SerializedBatchMessage;
+use crate::worker::{SerializedBatchDigestMessage, WorkerMessage};
+use bytes::Bytes;
+use config::{BatchCompression, Committee, WorkerId};
+use crypto::{Digest, PublicKey};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use log::{debug, error, warn};
+use network::SimpleSender;
+use primary::WorkerPrimaryMessage;
+use std::collections::HashMap;
+use std::convert::TryInto as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use store::{RoundIndex, Store, WriteBatch, BATCHES};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/reconstructor_tests.rs"]
+pub mod reconstructor_tests;
+
+/// Handles the chunks of erasure-coded batches. It stores the chunk each author sends us and
+/// tells our primary that the batch is available (any f+1 chunks suffice to reconstruct it). When
+/// we need a batch we only hold a chunk of, it collects the chunks of the other workers and
+/// reconstructs the batch.
+pub struct Reconstructor {
+    /// The public key of this authority.
+    name: PublicKey,
+    /// The id of this worker.
+    id: WorkerId,
+    /// The committee information.
+    committee: Committee,
+    /// The persistent storage.
+    store: Store,
+    /// Indexes the chunks we store by round (see `Pruner`).
+    index: RoundIndex,
+    /// The last committed round, under which we index the chunks we store.
+    consensus_round: Arc<AtomicU64>,
+    /// Encodes and decodes the chunks.
+    coder: ErasureCoder,
+    /// The compression of the batches (before encoding them).
+    compression: BatchCompression,
+    /// The maximum size of the (uncompressed) batches we reconstruct.
+    max_batch_size: usize,
+    /// How long we wait for chunks before requesting them again. Denominated in ms.
+    sync_retry_delay: u64,
+    /// Input channel to receive chunks from the other workers.
+    rx_chunk: Receiver<Chunk>,
+    /// Input channel to receive the digests of the batches we need.
+    rx_request: Receiver<Digest>,
+    /// Output channel to deliver reconstructed batches to the `Processor`.
+    tx_processor: Sender<SerializedBatchMessage>,
+    /// Output channel to tell our primary that we hold a chunk.
+    tx_primary: Sender<SerializedBatchDigestMessage>,
+    /// A network sender to request the chunks.
+    network: SimpleSender,
+    /// The chunks we collected to reconstruct batches, along with the time we requested them.
+    pending: HashMap<Digest, (Instant, HashMap<usize, Chunk>)>,
+}
+
+impl Reconstructor {
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        name: PublicKey,
+        id: WorkerId,
+        committee: Committee,
+        store: Store,
+        consensus_round: Arc<AtomicU64>,
+        compression: BatchCompression,
+        max_batch_size: usize,
+        sync_retry_delay: u64,
+        rx_chunk: Receiver<Chunk>,
+        rx_request: Receiver<Digest>,
+        tx_processor: Sender<SerializedBatchMessage>,
+        tx_primary: Sender<SerializedBatchDigestMessage>,
+    ) {
+        tokio::spawn(async move {
+            Self {
+                name,
+                id,
+                coder: ErasureCoder::new(&committee),
+                committee,
+                index: RoundIndex::new(&store, BATCHES),
+                store,
+                consensus_round,
+                compression,
+                max_batch_size,
+                sync_retry_delay,
+                rx_chunk,
+                rx_request,
+                tx_processor,
+                tx_primary,
+                network: SimpleSender::new(),
+                pending: HashMap::new(),
+            }
+            .run()
+            .await;
+        });
+    }
+
+    /// Main loop receiving chunks and requests.
+    async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                Some(chunk) = self.rx_chunk.recv() => match self.coder.index(&self.name) {
+                    Some(index) if index == chunk.index => self.store_chunk(chunk).await,
+                    _ => self.collect(chunk).await,
+                },
+                Some(digest) = self.rx_request.recv() => self.request(digest).await,
+            }
+        }
+    }
+
+    /// Stores the chunk an author sent us, and tells our primary we hold its batch.
+    async fn store_chunk(&mut self, chunk: Chunk) {
+        let key = chunk_key(&chunk.digest);
+        match self.store.read(key.clone()).await {
+            Ok(None) => (),
+            Ok(Some(_)) => return,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        }
+        let digest = chunk.digest.clone();
+        let message = WorkerMessage::Chunk(chunk);
+        let serialized = bincode::serialize(&message).expect("Failed to serialize our chunk");
+        let round = self.consensus_round.load(Ordering::Relaxed);
+        let mut batch = WriteBatch::default();
+        batch.put(&self.store, key.clone(), serialized);
+        self.index.insert(&mut batch, round, &key);
+        if let Err(e) = self.store.write_all(batch).await {
+            error!("{}", e);
+            return;
+        }
+
+        let message = WorkerPrimaryMessage::OthersBatch(digest, self.id);
+        let message = bincode::serialize(&message)
+            .expect("Failed to serialize our own worker-primary message");
+        self.tx_primary
+            .send(message)
+            .await
+            .expect("Failed to send digest");
+    }
+
+    /// Asks all the other workers for a batch (or their chunk of it), unless we already did it
+    /// recently, we already hold the batch, or we do not hold a chunk of it (in which case it was
+    /// not erasure-coded).
+    async fn request(&mut self, digest: Digest) {
+        self.cleanup();
+        if self.pending.contains_key(&digest) {
+            return;
+        }
+        if self.has_batch(&digest).await {
+            return;
+        }
+        let chunk = match self.our_chunk(&digest).await {
+            Some(x) => x,
+            None => return,
+        };
+        let chunks = [(chunk.index, chunk)].iter().cloned().collect();
+        self.pending
+            .insert(digest.clone(), (Instant::now(), chunks));
+
+        debug!("Reconstructing batch {}", digest);
+        let addresses = self
+            .committee
+            .others_workers(&self.name, &self.id)
+            .iter()
+            .map(|(_, address)| address.worker_to_worker)
+            .collect();
+        let message = WorkerMessage::BatchRequest(vec![digest], self.name);
+        let serialized = bincode::serialize(&message).expect("Failed to serialize our own message");
+        self.network
+            .broadcast(addresses, Bytes::from(serialized))
+            .await;
+    }
+
+    /// Checks whether we hold the (whole) batch.
+    async fn has_batch(&mut self, digest: &Digest) -> bool {
+        match self.store.read(digest.to_vec()).await {
+            Ok(x) => x.is_some(),
+            Err(e) => {
+                error!("{}", e);
+                true
+            }
+        }
+    }
+
+    /// Loads our chunk of a batch from the store.
+    async fn our_chunk(&mut self, digest: &Digest) -> Option<Chunk> {
+        match self.store.read(chunk_key(digest)).await {
+            Ok(Some(serialized)) => match bincode::deserialize(&serialized) {
+                Ok(WorkerMessage::Chunk(chunk)) => Some(chunk),
+                _ => None,
+            },
+            Ok(None) => None,
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Forgets the chunks we collected more than `sync_retry_delay` ago (we request them again
+    /// when retrying).
+    fn cleanup(&mut self) {
+        let delay = Duration::from_millis(self.sync_retry_delay);
+        self.pending
+            .retain(|_, (timestamp, _)| timestamp.elapsed() < delay);
+    }
+
+    /// Adds a chunk to the ones we collected, and reconstructs the batch once we have enough. We
+    /// receive the chunks of the other workers in reply to the batch requests of the `Reconstructor`
+    /// or of the `Synchronizer`.
+    async fn collect(&mut self, chunk: Chunk) {
+        self.cleanup();
+        let digest = chunk.digest.clone();
+        if !self.pending.contains_key(&digest) {
+            if self.has_batch(&digest).await {
+                return;
+            }
+            let mut chunks = HashMap::new();
+            if let Some(ours) = self.our_chunk(&digest).await {
+                chunks.insert(ours.index, ours);
+            }
+            self.pending
+                .insert(digest.clone(), (Instant::now(), chunks));
+        }
+
+        let (_, chunks) = self.pending.get_mut(&digest).unwrap();
+        chunks.insert(chunk.index, chunk);
+        if chunks.len() < self.coder.data_chunks() {
+            return;
+        }
+        let chunks: Vec<_> = chunks.values().cloned().collect();
+        self.pending.remove(&digest);
+
+        match self.reconstruct(&digest, &chunks) {
+            Some(batch) => {
+                debug!("Reconstructed batch {}", digest);
+                self.tx_processor
+                    .send(batch)
+                    .await
+                    .expect("Failed to deliver batch")
+            }
+            None => warn!("Failed to reconstruct batch {}", digest),
+        }
+    }
+
+    /// Decodes the chunks of a batch, and checks that they decode into the batch.
+    fn reconstruct(&self, digest: &Digest, chunks: &[Chunk]) -> Option<SerializedBatchMessage> {
+        let batch = self.coder.decode(chunks)?;
+        let batch = uncompressed(self.compression, batch, self.max_batch_size).ok()?;
+        let hash = Digest(Sha512::digest(&batch)[..32].try_into().unwrap());
+        match bincode::deserialize(&batch) {
+            Ok(WorkerMessage::Batch(..)) if &hash == digest => Some(batch),
+            _ => None,
+        }
+    }
+}
@@ -1,14 +1,21 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
+use crate::compression::uncompressed;
+use crate::erasure::chunk_key;
+use crate::processor::SerializedBatchMessage;
 use crate::worker::{Round, WorkerMessage};
 use bytes::Bytes;
-use config::{Committee, WorkerId};
+use config::{BatchCompression, Committee, WorkerId};
 use crypto::{Digest, PublicKey};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
-use log::{debug, error};
+use log::{debug, error, warn};
 use network::SimpleSender;
 use primary::PrimaryWorkerMessage;
 use std::collections::HashMap;
+use std::convert::TryInto as _;
+use std::net::SocketAddr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use store::{Store, StoreError};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -21,6 +28,10 @@ pub mod synchronizer_tests;
 /// Resolution of the timer managing retrials of sync requests (in ms).
 const TIMER_RESOLUTION: u64 = 1_000;
 
+/// The number of invalid or unsolicited batches after which we stop sending sync requests to a
+/// peer (unless no other peer is left).
+const MAX_PEER_FAULTS: usize = 10;
+
 // The `Synchronizer` is responsible to keep the worker in sync with the others.
 pub struct Synchronizer {
     /// The public key of this authority.
@@ -40,6 +51,14 @@ pub struct Synchronizer {
     sync_retry_nodes: usize,
     /// Input channel to receive the commands from the primary.
     rx_message: Receiver<PrimaryWorkerMessage>,
+    /// The compression of the batches we receive.
+    compression: BatchCompression,
+    /// The maximum size of the (uncompressed) batches we accept.
+    max_batch_size: usize,
+    /// Input channel to receive the batches the other workers send in reply to our requests.
+    rx_batch: Receiver<(SerializedBatchMessage, PublicKey)>,
+    /// Output channel to deliver the batches we requested to the `Processor`.
+    tx_processor: Sender<SerializedBatchMessage>,
     /// A network sender to send requests to the other workers.
     network: SimpleSender,
     /// Loosely keep track of the primary's round number (only used for cleanup).
@@ -48,6 +67,8 @@ pub struct Synchronizer {
     /// processing will resume when we get the missing batches in the store or we no longer need them.
     /// It also keeps the round number and a timestamp (`u128`) of each request we sent.
     pending: HashMap<Digest, (Round, Sender<()>, u128)>,
+    /// The number of invalid or unsolicited batches each peer sent us.
+    faults: HashMap<PublicKey, usize>,
 }
 
 impl Synchronizer {
@@ -61,6 +82,10 @@ impl Synchronizer {
         sync_retry_delay: u64,
         sync_retry_nodes: usize,
         rx_message: Receiver<PrimaryWorkerMessage>,
+        compression: BatchCompression,
+        max_batch_size: usize,
+        rx_batch: Receiver<(SerializedBatchMessage, PublicKey)>,
+        tx_processor: Sender<SerializedBatchMessage>,
     ) {
         tokio::spawn(async move {
             Self {
@@ -72,9 +97,14 @@ impl Synchronizer {
                 sync_retry_delay,
                 sync_retry_nodes,
                 rx_message,
+                compression,
+                max_batch_size,
+                rx_batch,
+                tx_processor,
                 network: SimpleSender::new(),
                 round: Round::default(),
                 pending: HashMap::new(),
+                faults: HashMap::new(),
             }
             .run()
             .await;
@@ -97,6 +127,63 @@ impl Synchronizer {
         }
     }
 
+    /// Checks a batch a peer sent in reply to a batch request, and delivers it to the `Processor`
+    /// if we asked for it. We also accept the batches we hold a chunk of, as the `Reconstructor`
+    /// requests them.
+    async fn handle_reply(&mut self, batch: SerializedBatchMessage, origin: PublicKey) {
+        let batch = match uncompressed(self.compression, batch, self.max_batch_size) {
+            Ok(x) => x,
+            Err(e) => {
+                self.fault(origin, &format!("an invalid batch ({})", e));
+                return;
+            }
+        };
+        let digest = Digest(Sha512::digest(&batch)[..32].try_into().unwrap());
+
+        if self.pending.contains_key(&digest) || self.holds(chunk_key(&digest)).await {
+            self.tx_processor
+                .send(batch)
+                .await
+                .expect("Failed to deliver batch");
+        } else if !self.holds(digest.to_vec()).await {
+            // Late replies to requests we already served are expected.
+            self.fault(origin, &format!("an unsolicited batch {}", digest));
+        }
+    }
+
+    /// Checks whether the store holds the specified key.
+    async fn holds(&mut self, key: Vec<u8>) -> bool {
+        match self.store.read(key).await {
+            Ok(x) => x.is_some(),
+            Err(e) => {
+                error!("{}", e);
+                false
+            }
+        }
+    }
+
+    /// Records that a peer sent us invalid or unsolicited data.
+    fn fault(&mut self, origin: PublicKey, reason: &str) {
+        let faults = self.faults.entry(origin).or_insert(0);
+        *faults += 1;
+        warn!("{} sent us {} ({} faults)", origin, reason, faults);
+    }
+
+    /// Returns the addresses of the peers we may send sync requests to, leaving out the ones that
+    /// sent us too many invalid or unsolicited batches.
+    fn sync_peers(&self) -> Vec<SocketAddr> {
+        let peers = self.committee.others_workers(&self.name, &self.id);
+        let honest: Vec<_> = peers
+            .iter()
+            .filter(|(name, _)| self.faults.get(name).copied().unwrap_or(0) < MAX_PEER_FAULTS)
+            .map(|(_, address)| address.worker_to_worker)
+            .collect();
+        match honest.is_empty() {
+            true => peers.iter().map(|(_, x)| x.worker_to_worker).collect(),
+            false => honest,
+        }
+    }
+
     /// Main loop listening to the primary's messages.
     async fn run(&mut self) {
         let mut waiting = FuturesUnordered::new();
@@ -157,6 +244,10 @@ impl Synchronizer {
                         let serialized = bincode::serialize(&message).expect("Failed to serialize our own message");
                         self.network.send(address, Bytes::from(serialized)).await;
                     },
+                    PrimaryWorkerMessage::RequestBatch(..)
+                    | PrimaryWorkerMessage::Committed(..) => {
+                        unreachable!("Handled by the primary receiver handler")
+                    },
                     PrimaryWorkerMessage::Cleanup(round) => {
                         // Keep track of the primary's round number.
                         self.round = round;
@@ -176,6 +267,11 @@ impl Synchronizer {
                     }
                 },
 
+                // Handle the batches the other workers send in reply to our requests.
+                Some((batch, origin)) = self.rx_batch.recv() => {
+                    self.handle_reply(batch, origin).await
+                },
+
                 // Stream out the futures of the `FuturesUnordered` that completed.
                 Some(result) = waiting.next() => match result {
                     Ok(Some(digest)) => {
@@ -206,10 +302,7 @@ impl Synchronizer {
                         }
                     }
                     if !retry.is_empty() {
-                        let addresses = self.committee
-                            .others_workers(&self.name, &self.id)
-                            .iter().map(|(_, address)| address.worker_to_worker)
-                            .collect();
+                        let addresses = self.sync_peers();
                         let message = WorkerMessage::BatchRequest(retry, self.name);
                         let serialized = bincode::serialize(&message).expect("Failed to serialize our own message");
                         self.network
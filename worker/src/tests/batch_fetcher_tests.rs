@@ -0,0 +1,80 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{batch, batch_digest, committee_with_base_port, keys, serialized_batch};
+use crate::worker::Worker;
+use config::Parameters;
+use store::{Store, BATCHES};
+use tokio::time::sleep;
+
+// Spawns the worker `id` of `name`, holding the specified batches.
+async fn spawn_worker(
+    name: PublicKey,
+    id: WorkerId,
+    committee: Committee,
+    batches: Vec<(Digest, Vec<u8>)>,
+) {
+    let store = Store::new_in_memory();
+    let mut stored = store.column(BATCHES);
+    for (digest, batch) in batches {
+        stored.write(digest.to_vec(), batch).await;
+    }
+    Worker::spawn(name, id, committee, Parameters::default(), store);
+}
+
+#[tokio::test]
+async fn fetch_from_our_worker() {
+    let (name, _) = keys().pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(12_000);
+
+    // Spawn our worker, holding the batch.
+    let batches = vec![(batch_digest(), serialized_batch())];
+    spawn_worker(name, id, committee.clone(), batches).await;
+
+    // Fetch the batch.
+    let mut batch_fetcher = BatchFetcher::new(name, committee, /* retry_delay */ 100);
+    let batches = batch_fetcher.fetch(&[(batch_digest(), id)]).await;
+    assert_eq!(batches, vec![batch()]);
+}
+
+#[tokio::test]
+async fn fetch_from_other_authorities() {
+    let mut keys = keys();
+    let (name, _) = keys.pop().unwrap();
+    let (other, _) = keys.pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(12_500);
+
+    // Spawn our worker (missing the batch) and the worker of another authority (holding it).
+    spawn_worker(name, id, committee.clone(), Vec::new()).await;
+    let batches = vec![(batch_digest(), serialized_batch())];
+    spawn_worker(other, id, committee.clone(), batches).await;
+
+    // Fetch the batch.
+    let mut batch_fetcher = BatchFetcher::new(name, committee, /* retry_delay */ 100);
+    let batches = batch_fetcher.fetch(&[(batch_digest(), id)]).await;
+    assert_eq!(batches, vec![batch()]);
+}
+
+#[tokio::test]
+async fn wait_for_missing_batch() {
+    let mut keys = keys();
+    let (name, _) = keys.pop().unwrap();
+    let (other, _) = keys.pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(16_500);
+
+    // Spawn our worker, missing the batch.
+    spawn_worker(name, id, committee.clone(), Vec::new()).await;
+
+    // Start fetching the batch: nobody serves it yet.
+    let mut batch_fetcher = BatchFetcher::new(name, committee.clone(), /* retry_delay */ 10);
+    let handle = tokio::spawn(async move { batch_fetcher.fetch(&[(batch_digest(), id)]).await });
+    sleep(Duration::from_millis(500)).await;
+    assert!(!handle.is_finished());
+
+    // Ensure we get the batch once the worker of another authority holds it.
+    let batches = vec![(batch_digest(), serialized_batch())];
+    spawn_worker(other, id, committee, batches).await;
+    assert_eq!(handle.await.unwrap(), vec![batch()]);
+}
@@ -1,6 +1,9 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use crate::common::transaction;
+use crate::common::{
+    batch, batch_digest, committee_with_base_port, keys, listener, serialized_batch, transaction,
+};
+use futures::future::try_join_all;
 use tokio::sync::mpsc::channel;
 
 #[tokio::test]
@@ -16,11 +19,14 @@ async fn make_batch() {
         rx_transaction,
         tx_message,
         /* workers_addresses */ dummy_addresses,
+        /* pending_batches */ Arc::new(AtomicUsize::new(0)),
+        BatchCompression::None,
+        /* coder */ None,
     );
 
     // Send enough transactions to seal a batch.
-    tx_transaction.send(transaction()).await.unwrap();
-    tx_transaction.send(transaction()).await.unwrap();
+    tx_transaction.send((transaction(), None)).await.unwrap();
+    tx_transaction.send((transaction(), None)).await.unwrap();
 
     // Ensure the batch is as expected.
     let expected_batch = vec![transaction(), transaction()];
@@ -44,10 +50,13 @@ async fn batch_timeout() {
         rx_transaction,
         tx_message,
         /* workers_addresses */ dummy_addresses,
+        /* pending_batches */ Arc::new(AtomicUsize::new(0)),
+        BatchCompression::None,
+        /* coder */ None,
     );
 
     // Do not send enough transactions to seal a batch..
-    tx_transaction.send(transaction()).await.unwrap();
+    tx_transaction.send((transaction(), None)).await.unwrap();
 
     // Ensure the batch is as expected.
     let expected_batch = vec![transaction()];
@@ -57,3 +66,81 @@ async fn batch_timeout() {
         _ => panic!("Unexpected message"),
     }
 }
+
+#[tokio::test]
+async fn receipts() {
+    let (tx_transaction, rx_transaction) = channel(1);
+    let (tx_message, mut rx_message) = channel(1);
+    let dummy_addresses = vec![(PublicKey::default(), "127.0.0.1:0".parse().unwrap())];
+
+    // Spawn a `BatchMaker` instance.
+    BatchMaker::spawn(
+        /* max_batch_size */ 200,
+        /* max_batch_delay */ 1_000_000, // Ensure the timer is not triggered.
+        rx_transaction,
+        tx_message,
+        /* workers_addresses */ dummy_addresses,
+        /* pending_batches */ Arc::new(AtomicUsize::new(0)),
+        BatchCompression::None,
+        /* coder */ None,
+    );
+
+    // Send enough transactions to seal a batch, asking for a receipt.
+    let (sender, receiver) = oneshot::channel();
+    tx_transaction
+        .send((transaction(), Some(sender)))
+        .await
+        .unwrap();
+    tx_transaction.send((transaction(), None)).await.unwrap();
+
+    // Ensure the receipt holds the digest of the batch.
+    let QuorumWaiterMessage { batch, handlers: _ } = rx_message.recv().await.unwrap();
+    let digest = Digest(Sha512::digest(&batch)[..32].try_into().unwrap());
+    assert_eq!(receiver.await.unwrap(), digest);
+}
+
+#[tokio::test]
+async fn make_chunks() {
+    let (tx_transaction, rx_transaction) = channel(1);
+    let (tx_message, mut rx_message) = channel(1);
+    let (myself, _) = keys().pop().unwrap();
+    let committee = committee_with_base_port(14_000);
+    let coder = ErasureCoder::new(&committee);
+
+    // Spawn a listener for every other worker, expecting its chunk of the batch.
+    let chunks = coder.encode(&batch_digest(), &serialized_batch());
+    let mut addresses = Vec::new();
+    let mut handles = Vec::new();
+    for (name, address) in committee.others_workers(&myself, /* id */ &0) {
+        let chunk = chunks[coder.index(&name).unwrap()].clone();
+        let expected = bincode::serialize(&WorkerMessage::Chunk(chunk)).unwrap();
+        handles.push(listener(
+            address.worker_to_worker,
+            Some(Bytes::from(expected)),
+        ));
+        addresses.push((name, address.worker_to_worker));
+    }
+
+    // Spawn a `BatchMaker` instance.
+    BatchMaker::spawn(
+        /* max_batch_size */ 200,
+        /* max_batch_delay */ 1_000_000, // Ensure the timer is not triggered.
+        rx_transaction,
+        tx_message,
+        /* workers_addresses */ addresses,
+        /* pending_batches */ Arc::new(AtomicUsize::new(0)),
+        BatchCompression::None,
+        Some(coder),
+    );
+
+    // Send enough transactions to seal a batch.
+    for transaction in batch() {
+        tx_transaction.send((transaction, None)).await.unwrap();
+    }
+
+    // Ensure we keep the whole batch and every other worker receives its chunk.
+    let QuorumWaiterMessage { batch, handlers } = rx_message.recv().await.unwrap();
+    assert_eq!(batch, serialized_batch());
+    assert_eq!(handlers.len(), 3);
+    assert!(try_join_all(handles).await.is_ok());
+}
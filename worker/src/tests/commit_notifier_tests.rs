@@ -0,0 +1,33 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+
+#[tokio::test]
+async fn notify_commit() {
+    let notifier = CommitNotifier::new(/* gc_depth */ 50);
+    let digest = Digest([0; 32]);
+
+    // Wait for the batch to be committed.
+    let waiter = notifier.clone();
+    let cloned = digest.clone();
+    let handle = tokio::spawn(async move { waiter.wait(cloned).await });
+    tokio::task::yield_now().await;
+    notifier.commit(vec![digest.clone()], 10);
+    assert_eq!(handle.await.unwrap().unwrap(), 10);
+
+    // Clients asking after the fact get an answer right away.
+    assert_eq!(notifier.wait(digest.clone()).await.unwrap(), 10);
+
+    // Until the commit is garbage collected.
+    notifier.commit(Vec::new(), 61);
+    assert!(notifier.inner.lock().unwrap().committed.is_empty());
+
+    // Clients stop waiting for batches that are not committed within `gc_depth` rounds.
+    let waiter = notifier.clone();
+    let handle = tokio::spawn(async move { waiter.wait(Digest([1; 32])).await });
+    tokio::task::yield_now().await;
+    notifier.commit(Vec::new(), 111);
+    assert_eq!(notifier.inner.lock().unwrap().waiting.len(), 1);
+    notifier.commit(Vec::new(), 112);
+    assert!(handle.await.unwrap().is_err());
+    assert!(notifier.inner.lock().unwrap().waiting.is_empty());
+}
@@ -0,0 +1,38 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::serialized_batch;
+
+#[test]
+fn compress_and_decompress() {
+    for compression in [BatchCompression::Lz4, BatchCompression::Zstd] {
+        let serialized = compress(compression, serialized_batch());
+        match bincode::deserialize(&serialized).unwrap() {
+            WorkerMessage::CompressedBatch(x, compressed) => {
+                assert_eq!(x, compression);
+                assert!(compressed.len() < serialized_batch().len());
+                let batch = decompress(compression, &compressed, 1_000).unwrap();
+                assert_eq!(batch, serialized_batch());
+            }
+            _ => panic!("Unexpected message"),
+        }
+    }
+}
+
+#[test]
+fn no_compression() {
+    let serialized = compress(BatchCompression::None, serialized_batch());
+    assert_eq!(serialized, serialized_batch());
+}
+
+#[test]
+fn reject_large_batches() {
+    // A forged size prefix must not make us allocate the claimed size.
+    let mut compressed = lz4_flex::compress_prepend_size(&serialized_batch());
+    compressed[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(decompress(BatchCompression::Lz4, &compressed, 1_000).is_err());
+
+    let large = vec![0u8; 1_001];
+    let compressed = zstd::bulk::compress(&large, zstd::DEFAULT_COMPRESSION_LEVEL).unwrap();
+    assert!(decompress(BatchCompression::Zstd, &compressed, 1_000).is_err());
+    assert!(decompress(BatchCompression::Zstd, &compressed, 1_001).is_ok());
+}
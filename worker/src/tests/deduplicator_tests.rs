@@ -0,0 +1,51 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+
+fn transaction(i: u8) -> Transaction {
+    vec![i; 100]
+}
+
+#[tokio::test]
+async fn filter_duplicates() {
+    let store = Store::new_in_memory();
+    let mut deduplicator = Deduplicator::new(store, /* window */ 10).await.unwrap();
+
+    // Duplicates are filtered within a batch and across batches.
+    let batch = vec![transaction(0), transaction(1), transaction(0)];
+    assert_eq!(
+        deduplicator.filter(1, batch),
+        vec![transaction(0), transaction(1)]
+    );
+    let batch = vec![transaction(1), transaction(2)];
+    assert_eq!(deduplicator.filter(2, batch), vec![transaction(2)]);
+    deduplicator.persist(2).await.unwrap();
+
+    // Transactions are forgotten once out of the window.
+    deduplicator.persist(12).await.unwrap();
+    let batch = vec![transaction(0), transaction(2)];
+    assert_eq!(deduplicator.filter(13, batch), vec![transaction(0)]);
+}
+
+#[tokio::test]
+async fn survive_restarts() {
+    let store = Store::new_in_memory();
+    let mut deduplicator = Deduplicator::new(store.clone(), /* window */ 10)
+        .await
+        .unwrap();
+    let batch = vec![transaction(0), transaction(1)];
+    let _ = deduplicator.filter(1, batch);
+    deduplicator.persist(1).await.unwrap();
+
+    // A restarted deduplicator remembers the output transactions.
+    let mut deduplicator = Deduplicator::new(store, /* window */ 10).await.unwrap();
+    let batch = vec![transaction(1), transaction(2)];
+    assert_eq!(deduplicator.filter(2, batch), vec![transaction(2)]);
+}
+
+#[tokio::test]
+async fn disabled() {
+    let store = Store::new_in_memory();
+    let mut deduplicator = Deduplicator::new(store, /* window */ 0).await.unwrap();
+    let batch = vec![transaction(0), transaction(0)];
+    assert_eq!(deduplicator.filter(1, batch.clone()), batch);
+}
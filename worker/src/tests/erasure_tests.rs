@@ -0,0 +1,26 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{batch_digest, committee, serialized_batch};
+
+#[test]
+fn encode_and_decode() {
+    let coder = ErasureCoder::new(&committee());
+    assert_eq!(coder.data_chunks(), 2);
+
+    // Any f+1 chunks suffice to reconstruct the batch.
+    let chunks = coder.encode(&batch_digest(), &serialized_batch());
+    assert_eq!(chunks.len(), 4);
+    for i in 0..chunks.len() {
+        for j in i + 1..chunks.len() {
+            let subset = [chunks[i].clone(), chunks[j].clone()];
+            assert_eq!(coder.decode(&subset), Some(serialized_batch()));
+        }
+    }
+}
+
+#[test]
+fn not_enough_chunks() {
+    let coder = ErasureCoder::new(&committee());
+    let chunks = coder.encode(&batch_digest(), &serialized_batch());
+    assert_eq!(coder.decode(&chunks[..1]), None);
+}
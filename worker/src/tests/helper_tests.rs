@@ -1,20 +1,21 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
 use crate::common::{batch_digest, committee_with_base_port, keys, listener, serialized_batch};
-use std::fs;
+use crate::erasure::ErasureCoder;
+use crate::worker::WorkerMessage;
 use tokio::sync::mpsc::channel;
 
 #[tokio::test]
 async fn batch_reply() {
     let (tx_request, rx_request) = channel(1);
-    let (requestor, _) = keys().pop().unwrap();
+    let mut keys = keys();
+    let (requestor, _) = keys.pop().unwrap();
+    let (name, _) = keys.pop().unwrap();
     let id = 0;
     let committee = committee_with_base_port(8_000);
 
     // Create a new test store.
-    let path = ".db_test_batch_reply";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let mut store = Store::new_in_memory();
 
     // Add a batch to the store.
     store
@@ -22,11 +23,19 @@ async fn batch_reply() {
         .await;
 
     // Spawn an `Helper` instance.
-    Helper::spawn(id, committee.clone(), store, rx_request);
+    Helper::spawn(
+        name,
+        id,
+        committee.clone(),
+        store,
+        rx_request,
+        BatchCompression::None,
+    );
 
     // Spawn a listener to receive the batch reply.
     let address = committee.worker(&requestor, &id).unwrap().worker_to_worker;
-    let expected = Bytes::from(serialized_batch());
+    let message = WorkerMessage::BatchReply(serialized_batch(), name);
+    let expected = Bytes::from(bincode::serialize(&message).unwrap());
     let handle = listener(address, Some(expected));
 
     // Send a batch request.
@@ -36,3 +45,45 @@ async fn batch_reply() {
     // Ensure the requestor received the batch (ie. it did not panic).
     assert!(handle.await.is_ok());
 }
+
+#[tokio::test]
+async fn chunk_reply() {
+    let (tx_request, rx_request) = channel(1);
+    let mut keys = keys();
+    let (requestor, _) = keys.pop().unwrap();
+    let (name, _) = keys.pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(15_000);
+
+    // Create a new test store.
+    let mut store = Store::new_in_memory();
+
+    // Add our chunk of a batch to the store (but not the batch itself).
+    let coder = ErasureCoder::new(&committee);
+    let chunk = coder.encode(&batch_digest(), &serialized_batch()).remove(0);
+    let serialized = bincode::serialize(&WorkerMessage::Chunk(chunk)).unwrap();
+    store
+        .write(chunk_key(&batch_digest()), serialized.clone())
+        .await;
+
+    // Spawn an `Helper` instance.
+    Helper::spawn(
+        name,
+        id,
+        committee.clone(),
+        store,
+        rx_request,
+        BatchCompression::None,
+    );
+
+    // Spawn a listener to receive the chunk.
+    let address = committee.worker(&requestor, &id).unwrap().worker_to_worker;
+    let handle = listener(address, Some(Bytes::from(serialized)));
+
+    // Send a batch request.
+    let digests = vec![batch_digest()];
+    tx_request.send((digests, requestor)).await.unwrap();
+
+    // Ensure the requestor received our chunk.
+    assert!(handle.await.is_ok());
+}
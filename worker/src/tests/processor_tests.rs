@@ -1,10 +1,9 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use crate::common::{batch, serialized_batch}; // Sửa đổi: sử dụng serialized_batch từ common
-use crate::worker::WorkerMessage;
-use primary::WorkerPrimaryMessage; // Thêm import
+use crate::common::{batch_digest, serialized_batch};
+use crate::compression::compress;
+use primary::WorkerPrimaryMessage;
 use std::convert::TryInto;
-use std::fs;
 use tokio::sync::mpsc::channel;
 
 #[tokio::test]
@@ -13,18 +12,19 @@ async fn hash_and_store() {
     let (tx_digest, mut rx_digest) = channel(1);
 
     // Create a new test store.
-    let path = ".db_test_hash_and_store";
-    let _ = fs::remove_dir_all(path);
-    let mut store = Store::new(path).unwrap();
+    let mut store = Store::new_in_memory();
 
     // Spawn a new `Processor` instance.
     let id = 0;
     Processor::spawn(
         id,
         store.clone(),
+        Arc::new(AtomicU64::new(0)),
         rx_batch,
         tx_digest,
         /* own_batch */ true,
+        BatchCompression::None,
+        /* max_batch_size */ 1_000,
     );
 
     // Send a batch to the `Processor`.
@@ -32,7 +32,7 @@ async fn hash_and_store() {
     let batch_data = serialized_batch();
     tx_batch.send(batch_data.clone()).await.unwrap();
 
-    // Ensure the `Processor` outputs the batch's digest along with the batch data.
+    // Ensure the `Processor` outputs the batch's digest.
     let output = rx_digest.recv().await.unwrap();
 
     // Tính toán digest và tạo message kỳ vọng
@@ -41,7 +41,7 @@ async fn hash_and_store() {
             .try_into()
             .unwrap(),
     );
-    let expected_message = WorkerPrimaryMessage::OurBatch(digest.clone(), id, batch_data.clone());
+    let expected_message = WorkerPrimaryMessage::OurBatch(digest.clone(), id);
     let expected_serialized =
         bincode::serialize(&expected_message).expect("Failed to serialize our own worker-primary message");
 
@@ -51,4 +51,38 @@ async fn hash_and_store() {
     let stored_batch = store.read(digest.to_vec()).await.unwrap();
     assert!(stored_batch.is_some(), "The batch is not in the store");
     assert_eq!(stored_batch.unwrap(), batch_data);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn decompress_and_store() {
+    let (tx_batch, rx_batch) = channel(1);
+    let (tx_digest, mut rx_digest) = channel(1);
+
+    // Create a new test store.
+    let mut store = Store::new_in_memory();
+
+    // Spawn a new `Processor` instance.
+    let id = 0;
+    Processor::spawn(
+        id,
+        store.clone(),
+        Arc::new(AtomicU64::new(0)),
+        rx_batch,
+        tx_digest,
+        /* own_batch */ false,
+        BatchCompression::Zstd,
+        /* max_batch_size */ 1_000,
+    );
+
+    // Send a compressed batch to the `Processor`.
+    let compressed = compress(BatchCompression::Zstd, serialized_batch());
+    tx_batch.send(compressed).await.unwrap();
+
+    // Ensure the digest is computed over the uncompressed batch.
+    let output = rx_digest.recv().await.unwrap();
+    let expected = bincode::serialize(&WorkerPrimaryMessage::OthersBatch(batch_digest(), id));
+    assert_eq!(output, expected.unwrap());
+
+    // Ensure the `Processor` stored the uncompressed batch.
+    let stored_batch = store.read(batch_digest().to_vec()).await.unwrap();
+    assert_eq!(stored_batch, Some(serialized_batch()));
+}
@@ -0,0 +1,50 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{batch_digest, serialized_batch};
+use crate::processor::Processor;
+use config::BatchCompression;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+use tokio::time::{sleep, timeout, Duration};
+
+#[tokio::test]
+async fn prune_old_batches() {
+    let (tx_batch, rx_batch) = channel(1);
+    let (tx_digest, mut rx_digest) = channel(1);
+    let (tx_round, rx_round) = channel(1);
+
+    // Create a new test store.
+    let store = Store::new_in_memory().column(BATCHES);
+
+    // Store a batch at round 1.
+    let consensus_round = Arc::new(AtomicU64::new(1));
+    Processor::spawn(
+        /* id */ 0,
+        store.clone(),
+        consensus_round,
+        rx_batch,
+        tx_digest,
+        /* own_batch */ true,
+        BatchCompression::None,
+        /* max_batch_size */ 1_000,
+    );
+    tx_batch.send(serialized_batch()).await.unwrap();
+    rx_digest.recv().await.unwrap();
+
+    // Spawn the pruner. The batch is deleted once the consensus moves past the retention depth.
+    Pruner::spawn(store.clone(), /* retention_depth */ 2, rx_round);
+    tx_round.send(4).await.unwrap();
+    let mut reader = store.clone();
+    let deleted = async {
+        while reader
+            .read(batch_digest().to_vec())
+            .await
+            .unwrap()
+            .is_some()
+        {
+            sleep(Duration::from_millis(10)).await;
+        }
+    };
+    assert!(timeout(Duration::from_secs(1), deleted).await.is_ok());
+}
@@ -15,7 +15,13 @@ async fn wait_for_quorum() {
     let committee = committee_with_base_port(7_000);
 
     // Spawn a `QuorumWaiter` instance.
-    QuorumWaiter::spawn(committee.clone(), /* stake */ 1, rx_message, tx_batch);
+    QuorumWaiter::spawn(
+        committee.clone(),
+        /* stake */ 1,
+        rx_message,
+        tx_batch,
+        /* pending_batches */ Arc::new(AtomicUsize::new(1)),
+    );
 
     // Make a batch.
     let message = WorkerMessage::Batch(batch());
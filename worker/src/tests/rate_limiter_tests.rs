@@ -0,0 +1,35 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[test]
+fn limit_rate() {
+    let limiter = RateLimiter::new(10);
+
+    // The client may send a burst of one second worth of transactions.
+    assert!((0..10).all(|_| limiter.try_acquire()));
+    assert!(!limiter.try_acquire());
+
+    // The bucket refills over time.
+    sleep(Duration::from_millis(150));
+    assert!(limiter.try_acquire());
+}
+
+#[test]
+fn limit_per_connection() {
+    let limiter = RateLimiter::new(1);
+    assert!(limiter.try_acquire());
+    assert!(!limiter.try_acquire());
+
+    // A clone has its own bucket.
+    let other = limiter.clone();
+    assert!(other.try_acquire());
+    assert!(!limiter.try_acquire());
+}
+
+#[test]
+fn unlimited() {
+    let limiter = RateLimiter::new(0);
+    assert!((0..1_000).all(|_| limiter.try_acquire()));
+}
@@ -0,0 +1,61 @@
+// Copyright(C) Facebook, Inc. and its affiliates.
+use super::*;
+use crate::common::{batch_digest, committee_with_base_port, keys, listener, serialized_batch};
+use futures::future::try_join_all;
+use tokio::sync::mpsc::channel;
+
+#[tokio::test]
+async fn reconstruct_batch() {
+    let (tx_chunk, rx_chunk) = channel(1);
+    let (tx_request, rx_request) = channel(1);
+    let (tx_processor, mut rx_processor) = channel(1);
+    let (tx_primary, mut rx_primary) = channel(1);
+    let (name, _) = keys().pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(14_500);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn a `Reconstructor` instance.
+    Reconstructor::spawn(
+        name,
+        id,
+        committee.clone(),
+        store,
+        Arc::new(AtomicU64::new(0)),
+        BatchCompression::None,
+        /* max_batch_size */ 1_000,
+        /* sync_retry_delay */ 1_000_000,
+        rx_chunk,
+        rx_request,
+        tx_processor,
+        tx_primary,
+    );
+
+    // Receive our chunk of the batch. Our primary should hear that we hold the batch.
+    let coder = ErasureCoder::new(&committee);
+    let chunks = coder.encode(&batch_digest(), &serialized_batch());
+    let ours = coder.index(&name).unwrap();
+    tx_chunk.send(chunks[ours].clone()).await.unwrap();
+
+    let expected = WorkerPrimaryMessage::OthersBatch(batch_digest(), id);
+    let output = rx_primary.recv().await.unwrap();
+    assert_eq!(output, bincode::serialize(&expected).unwrap());
+
+    // Ask to reconstruct the batch: the other workers should receive a batch request.
+    let message = WorkerMessage::BatchRequest(vec![batch_digest()], name);
+    let expected = Bytes::from(bincode::serialize(&message).unwrap());
+    let handles: Vec<_> = committee
+        .others_workers(&name, &id)
+        .into_iter()
+        .map(|(_, address)| listener(address.worker_to_worker, Some(expected.clone())))
+        .collect();
+    tx_request.send(batch_digest()).await.unwrap();
+    assert!(try_join_all(handles).await.is_ok());
+
+    // Receive the chunk of another worker. We now have enough chunks to reconstruct the batch.
+    let other = (ours + 1) % chunks.len();
+    tx_chunk.send(chunks[other].clone()).await.unwrap();
+    assert_eq!(rx_processor.recv().await.unwrap(), serialized_batch());
+}
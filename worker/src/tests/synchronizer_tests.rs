@@ -1,12 +1,13 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use crate::common::{batch_digest, committee_with_base_port, keys, listener};
-use std::fs;
+use crate::common::{batch_digest, committee_with_base_port, keys, listener, serialized_batch};
 use tokio::sync::mpsc::channel;
 
 #[tokio::test]
 async fn synchronize() {
     let (tx_message, rx_message) = channel(1);
+    let (_tx_batch, rx_batch) = channel(1);
+    let (tx_processor, _rx_processor) = channel(1);
 
     let mut keys = keys();
     let (name, _) = keys.pop().unwrap();
@@ -14,9 +15,7 @@ async fn synchronize() {
     let committee = committee_with_base_port(9_000);
 
     // Create a new test store.
-    let path = ".db_test_synchronize";
-    let _ = fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Spawn a `Synchronizer` instance.
     Synchronizer::spawn(
@@ -28,6 +27,10 @@ async fn synchronize() {
         /* sync_retry_delay */ 1_000_000, // Ensure it is not triggered.
         /* sync_retry_nodes */ 3, // Not used in this test.
         rx_message,
+        BatchCompression::None,
+        /* max_batch_size */ 1_000,
+        rx_batch,
+        tx_processor,
     );
 
     // Spawn a listener to receive our batch requests.
@@ -45,3 +48,51 @@ async fn synchronize() {
     // Ensure the target receives the sync request.
     assert!(handle.await.is_ok());
 }
+
+#[tokio::test]
+async fn reject_unsolicited_batches() {
+    let (tx_message, rx_message) = channel(1);
+    let (tx_batch, rx_batch) = channel(2);
+    let (tx_processor, mut rx_processor) = channel(2);
+
+    let mut keys = keys();
+    let (name, _) = keys.pop().unwrap();
+    let (target, _) = keys.pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(15_500);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn a `Synchronizer` instance.
+    Synchronizer::spawn(
+        name,
+        id,
+        committee.clone(),
+        store,
+        /* gc_depth */ 50, // Not used in this test.
+        /* sync_retry_delay */ 1_000_000, // Ensure it is not triggered.
+        /* sync_retry_nodes */ 3, // Not used in this test.
+        rx_message,
+        BatchCompression::None,
+        /* max_batch_size */ 1_000,
+        rx_batch,
+        tx_processor,
+    );
+
+    // Request a batch, and wait for the target to receive our request.
+    let address = committee.worker(&target, &id).unwrap().worker_to_worker;
+    let handle = listener(address, None);
+    let message = PrimaryWorkerMessage::Synchronize(vec![batch_digest()], target);
+    tx_message.send(message).await.unwrap();
+    assert!(handle.await.is_ok());
+
+    // Receive a batch we did not request, and then the batch we requested.
+    let unsolicited = bincode::serialize(&WorkerMessage::Batch(vec![vec![1; 10]])).unwrap();
+    tx_batch.send((unsolicited, target)).await.unwrap();
+    tx_batch.send((serialized_batch(), target)).await.unwrap();
+
+    // Ensure only the requested batch reaches the `Processor`.
+    assert_eq!(rx_processor.recv().await.unwrap(), serialized_batch());
+    assert!(rx_processor.try_recv().is_err());
+}
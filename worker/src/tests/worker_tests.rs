@@ -1,12 +1,12 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use super::*;
-use crate::common::{
-    batch, batch_digest, committee_with_base_port, keys, listener, serialized_batch, transaction,
-}; // Thêm import
+use crate::common::{batch_digest, committee_with_base_port, keys, listener, transaction};
 use bytes::Bytes; // Thêm import
-use network::SimpleSender;
+use futures::stream::StreamExt as _;
+use network::{ReliableSender, SimpleSender};
 use primary::WorkerPrimaryMessage;
-use std::fs;
+use tokio::net::TcpStream;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 #[tokio::test]
 async fn handle_clients_transactions() {
@@ -19,9 +19,7 @@ async fn handle_clients_transactions() {
     };
 
     // Create a new test store.
-    let path = ".db_test_handle_clients_transactions";
-    let _ = fs::remove_dir_all(path);
-    let store = Store::new(path).unwrap();
+    let store = Store::new_in_memory();
 
     // Spawn a `Worker` instance.
     Worker::spawn(name, id, committee.clone(), parameters, store);
@@ -29,12 +27,8 @@ async fn handle_clients_transactions() {
     // Spawn a network listener to receive our batch's digest.
     let primary_address = committee.primary(&name).unwrap().worker_to_primary;
 
-    // Listener bây giờ sẽ mong đợi nhận được message có cả nội dung batch.
-    let batch_content = serialized_batch();
-    let expected_message =
-        WorkerPrimaryMessage::OurBatch(batch_digest(), id, batch_content.clone());
-    let expected_serialized = bincode::serialize(&expected_message).unwrap();
-    let handle = listener(primary_address, Some(Bytes::from(expected_serialized)));
+    let expected = bincode::serialize(&WorkerPrimaryMessage::OurBatch(batch_digest(), id)).unwrap();
+    let handle = listener(primary_address, Some(Bytes::from(expected)));
 
     // Spawn enough workers' listeners to acknowledge our batches.
     for (_, addresses) in committee.others_workers(&name, &id) {
@@ -45,9 +39,170 @@ async fn handle_clients_transactions() {
     // Send enough transactions to create a batch.
     let mut network = SimpleSender::new();
     let address = committee.worker(&name, &id).unwrap().transactions;
-    network.send(address, Bytes::from(transaction())).await;
-    network.send(address, Bytes::from(transaction())).await;
+    let message = bincode::serialize(&ClientMessage::Transaction(transaction())).unwrap();
+    network.send(address, Bytes::from(message.clone())).await;
+    network.send(address, Bytes::from(message)).await;
 
     // Ensure the primary received the batch's digest (ie. it did not panic).
     assert!(handle.await.is_ok());
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn submit_and_wait_for_commit() {
+    let (name, _) = keys().pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(13_000);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn a `Worker` instance.
+    Worker::spawn(name, id, committee.clone(), Parameters::default(), store);
+
+    // Submit a transaction and get its receipt (the timer seals the batch).
+    let mut network = ReliableSender::new();
+    let address = committee.worker(&name, &id).unwrap().transactions;
+    let message = ClientMessage::SubmitTransaction(transaction());
+    let bytes = bincode::serialize(&message).unwrap();
+    let reply = network
+        .send(address, Bytes::from(bytes))
+        .await
+        .await
+        .unwrap();
+    let batch = match bincode::deserialize(&reply).unwrap() {
+        ClientReply::Receipt {
+            transaction: x,
+            batch,
+        } => {
+            assert_eq!(x, transaction_digest(&transaction()));
+            batch
+        }
+        x => panic!("Unexpected reply: {:?}", x),
+    };
+
+    // Wait for the batch to be committed.
+    let message = ClientMessage::WaitForCommit(batch.clone());
+    let bytes = bincode::serialize(&message).unwrap();
+    let handler = network.send(address, Bytes::from(bytes)).await;
+
+    // Our primary tells the worker the batch is committed.
+    let primary_address = committee.worker(&name, &id).unwrap().primary_to_worker;
+    let message = PrimaryWorkerMessage::Committed(vec![batch.clone()], 5);
+    let bytes = bincode::serialize(&message).unwrap();
+    SimpleSender::new()
+        .send(primary_address, Bytes::from(bytes))
+        .await;
+
+    let reply = handler.await.unwrap();
+    let expected = ClientReply::Committed { batch, round: 5 };
+    assert_eq!(
+        bincode::deserialize::<ClientReply>(&reply).unwrap(),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn wait_for_commit_does_not_block_connection() {
+    let (name, _) = keys().pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(16_000);
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn a `Worker` instance.
+    Worker::spawn(name, id, committee.clone(), Parameters::default(), store);
+
+    // Connect to the worker.
+    let address = committee.worker(&name, &id).unwrap().transactions;
+    let socket = loop {
+        match TcpStream::connect(address).await {
+            Ok(socket) => break socket,
+            Err(_) => tokio::task::yield_now().await,
+        }
+    };
+    let mut connection = Framed::new(socket, LengthDelimitedCodec::new());
+
+    // Wait for the commit of a batch, and then submit a transaction on the same connection.
+    let batch = Digest([1; 32]);
+    let message = ClientMessage::WaitForCommit(batch.clone());
+    let bytes = bincode::serialize(&message).unwrap();
+    connection.send(Bytes::from(bytes)).await.unwrap();
+    let message = ClientMessage::SubmitTransaction(transaction());
+    let bytes = bincode::serialize(&message).unwrap();
+    connection.send(Bytes::from(bytes)).await.unwrap();
+
+    // We get the receipt of the transaction while the batch is not committed.
+    let reply = connection.next().await.unwrap().unwrap();
+    match bincode::deserialize(&reply).unwrap() {
+        ClientReply::Receipt { transaction: x, .. } => {
+            assert_eq!(x, transaction_digest(&transaction()))
+        }
+        x => panic!("Unexpected reply: {:?}", x),
+    }
+
+    // Our primary tells the worker the batch is committed.
+    let primary_address = committee.worker(&name, &id).unwrap().primary_to_worker;
+    let message = PrimaryWorkerMessage::Committed(vec![batch.clone()], 5);
+    let bytes = bincode::serialize(&message).unwrap();
+    SimpleSender::new()
+        .send(primary_address, Bytes::from(bytes))
+        .await;
+
+    let reply = connection.next().await.unwrap().unwrap();
+    let expected = ClientReply::Committed { batch, round: 5 };
+    assert_eq!(
+        bincode::deserialize::<ClientReply>(&reply).unwrap(),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn reject_transactions() {
+    let (name, _) = keys().pop().unwrap();
+    let id = 0;
+    let committee = committee_with_base_port(13_500);
+    let parameters = Parameters {
+        max_transaction_size: 50,
+        max_pending_batches: 0, // Always overloaded.
+        ..Parameters::default()
+    };
+
+    // Create a new test store.
+    let store = Store::new_in_memory();
+
+    // Spawn a `Worker` instance.
+    Worker::spawn(name, id, committee.clone(), parameters, store);
+
+    // Submit a transaction that is too large.
+    let mut network = ReliableSender::new();
+    let address = committee.worker(&name, &id).unwrap().transactions;
+    let message = ClientMessage::Transaction(transaction());
+    let bytes = bincode::serialize(&message).unwrap();
+    let reply = network
+        .send(address, Bytes::from(bytes))
+        .await
+        .await
+        .unwrap();
+    let expected = ClientReply::TransactionTooLarge {
+        size: 100,
+        max_size: 50,
+    };
+    assert_eq!(
+        bincode::deserialize::<ClientReply>(&reply).unwrap(),
+        expected
+    );
+
+    // Submit a transaction while the worker is overloaded.
+    let message = ClientMessage::SubmitTransaction(vec![0; 10]);
+    let bytes = bincode::serialize(&message).unwrap();
+    let reply = network
+        .send(address, Bytes::from(bytes))
+        .await
+        .await
+        .unwrap();
+    assert_eq!(
+        bincode::deserialize::<ClientReply>(&reply).unwrap(),
+        ClientReply::Overloaded
+    );
+}
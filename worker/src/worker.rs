@@ -1,22 +1,33 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 use crate::batch_maker::{Batch, BatchMaker, Transaction};
+use crate::commit_notifier::CommitNotifier;
+use crate::deduplicator::transaction_digest;
+use crate::erasure::{Chunk, ErasureCoder};
 use crate::helper::Helper;
 use crate::primary_connector::PrimaryConnector;
 use crate::processor::{Processor, SerializedBatchMessage};
+use crate::pruner::Pruner;
 use crate::quorum_waiter::QuorumWaiter;
+use crate::rate_limiter::RateLimiter;
+use crate::reconstructor::Reconstructor;
 use crate::synchronizer::Synchronizer;
 use async_trait::async_trait;
 use bytes::Bytes;
-use config::{Committee, Parameters, WorkerId};
+use config::{BatchCompression, BatchDissemination, Committee, Parameters, WorkerId};
 use crypto::{Digest, PublicKey};
 use futures::sink::SinkExt as _;
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use network::{MessageHandler, Receiver, Writer};
 use primary::PrimaryWorkerMessage;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use store::Store;
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use store::{Store, BATCHES};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::oneshot;
 
 #[cfg(test)]
 #[path = "tests/worker_tests.rs"]
@@ -37,6 +48,42 @@ pub type SerializedBatchDigestMessage = Vec<u8>;
 pub enum WorkerMessage {
     Batch(Batch), // Một batch hoàn chỉnh.
     BatchRequest(Vec<Digest>, /* origin */ PublicKey), // Yêu cầu xin các batch bị thiếu.
+    /// Hỏi một batch (thường là từ executor). Worker trả lời bằng `Option<Vec<u8>>` thay vì Ack.
+    RequestBatch(Digest),
+    /// Một batch đã nén: chứa `WorkerMessage::Batch` đã serialize rồi nén (xem `compression`).
+    CompressedBatch(BatchCompression, Vec<u8>),
+    /// Một mảnh của batch đã mã hóa xóa (erasure-coded), xem `erasure`.
+    Chunk(Chunk),
+    /// Trả lời một `BatchRequest`: batch đã serialize (có thể đã nén) và worker gửi nó.
+    BatchReply(SerializedBatchMessage, /* origin */ PublicKey),
+}
+
+/// The messages clients send to the transactions port of a worker.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Submits a transaction. The worker only replies if it drops the transaction.
+    Transaction(Transaction),
+    /// Submits a transaction. The worker replies with a `ClientReply::Receipt` once it sealed the
+    /// batch holding the transaction.
+    SubmitTransaction(Transaction),
+    /// Asks to be notified when a batch is committed. The worker replies with a
+    /// `ClientReply::Committed` once the consensus ordered a certificate holding the batch, and
+    /// stops waiting (without replying) after `gc_depth` rounds.
+    WaitForCommit(Digest),
+}
+
+/// The replies of a worker to its clients.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ClientReply {
+    /// The digest of a submitted transaction and of the batch holding it.
+    Receipt { transaction: Digest, batch: Digest },
+    /// The batch is committed by a certificate of the specified round.
+    Committed { batch: Digest, round: Round },
+    /// The worker dropped the transaction because it is overloaded or because the client exceeds
+    /// its rate. The client should retry later, or submit its transaction to another worker.
+    Overloaded,
+    /// The worker dropped the transaction because it is larger than the maximum size.
+    TransactionTooLarge { size: usize, max_size: usize },
 }
 
 pub struct Worker {
@@ -48,8 +95,13 @@ pub struct Worker {
     committee: Committee,
     /// The configuration parameters.
     parameters: Parameters,
-    /// The persistent storage.
+    /// The persistent storage. The worker only stores batches (and their chunks).
     store: Store,
+    /// Tells clients when their batches are committed.
+    commit_notifier: CommitNotifier,
+    /// The last committed round, as our primary tells us. We index the batches we store under it,
+    /// so that the `Pruner` eventually deletes them.
+    consensus_round: Arc<AtomicU64>,
 }
 
 impl Worker {
@@ -61,19 +113,31 @@ impl Worker {
         store: Store,
     ) {
         // Define a worker instance.
+        let commit_notifier = CommitNotifier::new(parameters.gc_depth);
         let worker = Self {
             name,
             id,
             committee,
             parameters,
-            store,
+            store: store.column(BATCHES),
+            commit_notifier,
+            consensus_round: Arc::new(AtomicU64::new(0)),
         };
 
         // Spawn all worker tasks.
         let (tx_primary, rx_primary) = channel(CHANNEL_CAPACITY);
-        worker.handle_primary_messages(); // Dây chuyền nhận lệnh từ Primary.
+        let (tx_reconstructor, rx_reconstructor) = channel(CHANNEL_CAPACITY);
+        let (tx_batch_reply, rx_batch_reply) = channel(CHANNEL_CAPACITY);
+        let (tx_processor, rx_processor) = channel(CHANNEL_CAPACITY);
+        worker.handle_primary_messages(tx_reconstructor, rx_batch_reply, tx_processor.clone()); // Dây chuyền nhận lệnh từ Primary.
         worker.handle_clients_transactions(tx_primary.clone()); // Dây chuyền sản xuất chính, từ giao dịch của client.
-        worker.handle_workers_messages(tx_primary); // Dây chuyền xử lý tin nhắn từ các Worker khác.
+        worker.handle_workers_messages(
+            tx_primary,
+            rx_reconstructor,
+            tx_batch_reply,
+            tx_processor,
+            rx_processor,
+        ); // Dây chuyền xử lý tin nhắn từ các Worker khác.
 
 
          // Khởi chạy 'người giao liên' chuyên gửi tin nhắn LÊN cho Primary.
@@ -103,8 +167,14 @@ impl Worker {
 
     /// Thiết lập và khởi chạy dây chuyền xử lý các mệnh lệnh từ Primary.
     /// Spawn all tasks responsible to handle messages from our primary.
-    fn handle_primary_messages(&self) {
+    fn handle_primary_messages(
+        &self,
+        tx_reconstructor: Sender<Digest>,
+        rx_batch_reply: tokio::sync::mpsc::Receiver<(SerializedBatchMessage, PublicKey)>,
+        tx_processor: Sender<SerializedBatchMessage>,
+    ) {
         let (tx_synchronizer, rx_synchronizer) = channel(CHANNEL_CAPACITY);
+        let (tx_pruner, rx_pruner) = channel(CHANNEL_CAPACITY);
 
          // Mở cổng mạng để lắng nghe các chỉ thị từ Primary của chính node này.
         // Receive incoming messages from our primary.
@@ -118,12 +188,33 @@ impl Worker {
         Receiver::spawn(
             address,
             /* handler */
-            PrimaryReceiverHandler { tx_synchronizer },
+            PrimaryReceiverHandler {
+                store: self.store.clone(),
+                commit_notifier: self.commit_notifier.clone(),
+                consensus_round: self.consensus_round.clone(),
+                tx_synchronizer,
+                tx_reconstructor,
+                tx_pruner,
+            },
+        );
+
+        // The `Pruner` deletes from the store the batches that are too old for anyone to need them.
+        Pruner::spawn(
+            self.store.clone(),
+            self.parameters.retention_depth,
+            rx_pruner,
         );
 
         // Khởi chạy 'bộ phận đồng bộ hóa'.
         // Chịu trách nhiệm thực hiện các lệnh từ Primary, chủ yếu là đi tìm các batch bị thiếu
         // hoặc dọn dẹp dữ liệu cũ.
+        // It only hands the `Processor` the batches it asked for (see `Synchronizer::handle_reply`).
+        // Reconstructing an erasure-coded batch takes the chunks of f+1 authorities, so we
+        // retry with all of them.
+        let sync_retry_nodes = match self.parameters.batch_dissemination {
+            BatchDissemination::Full => self.parameters.sync_retry_nodes,
+            BatchDissemination::ErasureCoded => self.committee.size(),
+        };
         Synchronizer::spawn(
             self.name,
             self.id,
@@ -131,8 +222,12 @@ impl Worker {
             self.store.clone(),
             self.parameters.gc_depth,
             self.parameters.sync_retry_delay,
-            self.parameters.sync_retry_nodes,
+            sync_retry_nodes,
             /* rx_message */ rx_synchronizer,
+            self.parameters.batch_compression,
+            self.parameters.max_batch_size,
+            /* rx_batch */ rx_batch_reply,
+            tx_processor,
         );
 
         info!(
@@ -147,6 +242,7 @@ impl Worker {
         let (tx_batch_maker, rx_batch_maker) = channel(CHANNEL_CAPACITY);
         let (tx_quorum_waiter, rx_quorum_waiter) = channel(CHANNEL_CAPACITY);
         let (tx_processor, rx_processor) = channel(CHANNEL_CAPACITY);
+        let pending_batches = Arc::new(AtomicUsize::new(0));
 
         // We first receive clients' transactions from the network.
         let mut address = self
@@ -158,7 +254,16 @@ impl Worker {
         // nhan tu client
         Receiver::spawn(
             address,
-            /* handler */ TxReceiverHandler { tx_batch_maker },
+            /* handler */
+            TxReceiverHandler {
+                tx_batch_maker,
+                commit_notifier: self.commit_notifier.clone(),
+                pending_batches: pending_batches.clone(),
+                max_pending_batches: self.parameters.max_pending_batches,
+                max_transaction_size: self.parameters.max_transaction_size,
+                rate_limiter: RateLimiter::new(self.parameters.max_client_rate),
+                replies: ClientReplies::default(),
+            },
         );
 
 
@@ -178,6 +283,12 @@ impl Worker {
                 .iter()
                 .map(|(name, addresses)| (*name, addresses.worker_to_worker))
                 .collect(),
+            pending_batches.clone(),
+            self.parameters.batch_compression,
+            match self.parameters.batch_dissemination {
+                BatchDissemination::Full => None,
+                BatchDissemination::ErasureCoded => Some(ErasureCoder::new(&self.committee)),
+            },
         );
 
          // 3. Khởi chạy 'bộ phận chờ xác nhận'.
@@ -189,6 +300,7 @@ impl Worker {
             /* stake */ self.committee.stake(&self.name),
             /* rx_message */ rx_quorum_waiter,
             /* tx_batch */ tx_processor,
+            pending_batches,
         );
 
          // 4. Khởi chạy 'bộ phận lưu kho và dán nhãn'.
@@ -198,9 +310,12 @@ impl Worker {
         Processor::spawn(
             self.id,
             self.store.clone(),
+            self.consensus_round.clone(),
             /* rx_batch */ rx_processor,
             /* tx_digest */ tx_primary,
             /* own_batch */ true,
+            /* compression */ BatchCompression::None,
+            self.parameters.max_batch_size,
         );
 
         info!(
@@ -210,9 +325,16 @@ impl Worker {
     }
 
     /// Spawn all tasks responsible to handle messages from other workers.
-    fn handle_workers_messages(&self, tx_primary: Sender<SerializedBatchDigestMessage>) {
+    fn handle_workers_messages(
+        &self,
+        tx_primary: Sender<SerializedBatchDigestMessage>,
+        rx_reconstructor: tokio::sync::mpsc::Receiver<Digest>,
+        tx_batch_reply: Sender<(SerializedBatchMessage, PublicKey)>,
+        tx_processor: Sender<SerializedBatchMessage>,
+        rx_processor: tokio::sync::mpsc::Receiver<SerializedBatchMessage>,
+    ) {
         let (tx_helper, rx_helper) = channel(CHANNEL_CAPACITY);
-        let (tx_processor, rx_processor) = channel(CHANNEL_CAPACITY);
+        let (tx_chunk, rx_chunk) = channel(CHANNEL_CAPACITY);
 
           // 1. Mở 'cổng giao tiếp' để nhận tin nhắn từ các worker ngang hàng.
         // Receive incoming messages from other workers.
@@ -228,30 +350,56 @@ impl Worker {
             // Tùy vào loại tin nhắn, handler sẽ chuyển đến bộ phận phù hợp.
             /* handler */
             WorkerReceiverHandler {
+                store: self.store.clone(),
                 tx_helper,    // Nếu là yêu cầu xin batch -> Helper.
-                tx_processor, // Nếu là batch hoàn chỉnh -> Processor.
+                tx_processor: tx_processor.clone(), // Nếu là batch hoàn chỉnh -> Processor.
+                tx_chunk,     // Nếu là một mảnh của batch -> Reconstructor.
+                tx_batch_reply, // Nếu là batch trả lời yêu cầu của ta -> Synchronizer.
+                max_batch_size: self.parameters.max_batch_size,
             },
         );
                 // 2. Khởi chạy 'bộ phận hỗ trợ'.
         // Chuyên trả lời các yêu cầu xin batch từ các worker khác.
         // The `Helper` is dedicated to reply to batch requests from other workers.
         Helper::spawn(
+            self.name,
             self.id,
             self.committee.clone(),
             self.store.clone(),
             /* rx_request */ rx_helper,
+            self.parameters.batch_compression,
         );
 
          // 3. Khởi chạy 'bộ phận nhập kho'.
         // Xử lý các batch nhận được từ worker khác: băm, lưu, và báo cáo lên Primary.
+        // The `Reconstructor` stores the chunks of the erasure-coded batches we receive (and tells our primary), and
+        // reconstructs the batches we only hold a chunk of when we need them.
+        Reconstructor::spawn(
+            self.name,
+            self.id,
+            self.committee.clone(),
+            self.store.clone(),
+            self.consensus_round.clone(),
+            self.parameters.batch_compression,
+            self.parameters.max_batch_size,
+            self.parameters.sync_retry_delay,
+            rx_chunk,
+            /* rx_request */ rx_reconstructor,
+            tx_processor,
+            tx_primary.clone(),
+        );
+
         // This `Processor` hashes and stores the batches we receive from the other workers. It then forwards the
         // batch's digest to the `PrimaryConnector` that will send it to our primary.
         Processor::spawn(
             self.id,
             self.store.clone(),
+            self.consensus_round.clone(),
             /* rx_batch */ rx_processor,
             /* tx_digest */ tx_primary,
             /* own_batch */ false,
+            self.parameters.batch_compression,
+            self.parameters.max_batch_size,
         );
 
         info!(
@@ -265,17 +413,89 @@ impl Worker {
 /// Defines how the network receiver handles incoming transactions.
 #[derive(Clone)]
 struct TxReceiverHandler {
-    tx_batch_maker: Sender<Transaction>,
+    tx_batch_maker: Sender<(Transaction, Option<oneshot::Sender<Digest>>)>,
+    commit_notifier: CommitNotifier,
+    pending_batches: Arc<AtomicUsize>,
+    max_pending_batches: usize,
+    max_transaction_size: usize,
+    /// Each connection gets its own limiter (see `RateLimiter`).
+    rate_limiter: RateLimiter,
+    /// Each connection gets its own replies (see `ClientReplies`).
+    replies: ClientReplies,
+}
+
+impl TxReceiverHandler {
+    /// Sends the transaction to the batch maker, or returns the reason to turn it down.
+    fn admit(
+        &self,
+        transaction: Transaction,
+        receipt: Option<oneshot::Sender<Digest>>,
+    ) -> Result<(), ClientReply> {
+        if transaction.len() > self.max_transaction_size {
+            debug!("Rejected transaction of {} B", transaction.len());
+            return Err(ClientReply::TransactionTooLarge {
+                size: transaction.len(),
+                max_size: self.max_transaction_size,
+            });
+        }
+        if self.pending_batches.load(Ordering::Relaxed) >= self.max_pending_batches {
+            debug!("Too many batches awaiting acknowledgements, rejected transaction");
+            return Err(ClientReply::Overloaded);
+        }
+        if !self.rate_limiter.try_acquire() {
+            debug!("Client exceeds its rate, rejected transaction");
+            return Err(ClientReply::Overloaded);
+        }
+        match self.tx_batch_maker.try_send((transaction, receipt)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                debug!("Batch maker is busy, rejected transaction");
+                Err(ClientReply::Overloaded)
+            }
+            Err(TrySendError::Closed(_)) => panic!("Failed to send transaction"),
+        }
+    }
 }
 
 #[async_trait]
 impl MessageHandler for TxReceiverHandler {
-    async fn dispatch(&self, _writer: &mut Writer, message: Bytes) -> Result<(), Box<dyn Error>> {
-        // Send the transaction to the batch maker.
-        self.tx_batch_maker
-            .send(message.to_vec())
-            .await
-            .expect("Failed to send transaction");
+    async fn dispatch(&self, writer: &mut Writer, message: Bytes) -> Result<(), Box<dyn Error>> {
+        let replies = self.replies.sender(writer);
+        match bincode::deserialize(&message) {
+            Ok(ClientMessage::Transaction(transaction)) => {
+                // Send the transaction to the batch maker. We only reply if we drop it.
+                if let Err(reply) = self.admit(transaction, None) {
+                    replies.send(reply).await?;
+                }
+            }
+            Ok(ClientMessage::SubmitTransaction(transaction)) => {
+                // Send the transaction to the batch maker and reply once it seals the batch.
+                let digest = transaction_digest(&transaction);
+                let (sender, receiver) = oneshot::channel();
+                match self.admit(transaction, Some(sender)) {
+                    Ok(()) => {
+                        tokio::spawn(async move {
+                            if let Ok(batch) = receiver.await {
+                                let transaction = digest;
+                                let reply = ClientReply::Receipt { transaction, batch };
+                                let _ = replies.send(reply).await;
+                            }
+                        });
+                    }
+                    Err(reply) => replies.send(reply).await?,
+                }
+            }
+            Ok(ClientMessage::WaitForCommit(batch)) => {
+                // Reply once the batch is committed (if it is within `gc_depth` rounds).
+                let commit_notifier = self.commit_notifier.clone();
+                tokio::spawn(async move {
+                    if let Ok(round) = commit_notifier.wait(batch.clone()).await {
+                        let _ = replies.send(ClientReply::Committed { batch, round }).await;
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to deserialize client message: {}", e),
+        }
 
         // Nhường quyền thực thi cho các task khác, tránh block luồng.
         // Give the change to schedule other tasks.
@@ -284,24 +504,84 @@ impl MessageHandler for TxReceiverHandler {
     }
 }
 
+/// The replies to a client connection. A task owning the writer of the connection sends them, so
+/// that we keep reading (and admitting) the client's messages while some replies wait for their
+/// batch to be sealed or committed. The replies may thus come out of order. Cloning yields an
+/// unused instance: since the network receiver clones its handler for every connection, each
+/// connection gets its own task.
+#[derive(Default)]
+struct ClientReplies(Mutex<Option<Sender<ClientReply>>>);
+
+impl ClientReplies {
+    /// Returns the channel to the task sending the replies, spawning it (and handing it the
+    /// writer of the connection) on the first call.
+    fn sender(&self, writer: &mut Writer) -> Sender<ClientReply> {
+        let mut sender = self.0.lock().unwrap();
+        sender
+            .get_or_insert_with(|| {
+                let (tx_reply, mut rx_reply) = channel(CHANNEL_CAPACITY);
+                let unused = futures::sink::drain().sink_map_err(|e| -> io::Error { match e {} });
+                let mut writer = std::mem::replace(writer, Box::pin(unused));
+                tokio::spawn(async move {
+                    while let Some(reply) = rx_reply.recv().await {
+                        let bytes = bincode::serialize(&reply).expect("Failed to serialize reply");
+                        if let Err(e) = writer.send(Bytes::from(bytes)).await {
+                            warn!("Failed to reply to client: {}", e);
+                            break;
+                        }
+                    }
+                });
+                tx_reply
+            })
+            .clone()
+    }
+}
+
+impl Clone for ClientReplies {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
 /// Defines how the network receiver handles incoming workers messages.
 #[derive(Clone)]
 struct WorkerReceiverHandler {
+    store: Store,
     tx_helper: Sender<(Vec<Digest>, PublicKey)>,
     tx_processor: Sender<SerializedBatchMessage>,
+    tx_chunk: Sender<Chunk>,
+    tx_batch_reply: Sender<(SerializedBatchMessage, PublicKey)>,
+    max_batch_size: usize,
 }
 
 #[async_trait]
 impl MessageHandler for WorkerReceiverHandler {
     async fn dispatch(&self, writer: &mut Writer, serialized: Bytes) -> Result<(), Box<dyn Error>> {
+        // Batch requests are answered with the batch itself.
+        let message = bincode::deserialize(&serialized);
+        if let Ok(WorkerMessage::RequestBatch(digest)) = message {
+            return serve_batch(&self.store, writer, &digest).await.map(|_| ());
+        }
+
+        // Refuse oversized batches (without acknowledging them), so that peers cannot flood our
+        // storage.
+        let size = match &message {
+            Ok(WorkerMessage::BatchReply(batch, _)) => batch.len(),
+            _ => serialized.len(),
+        };
+        if size > self.max_batch_size {
+            warn!("Received an oversized worker message ({} B)", size);
+            return Ok(());
+        }
+
         // Reply with an ACK.
           // Gửi lại một tin "Ack" để xác nhận đã nhận.
         let _ = writer.send(Bytes::from("Ack")).await;
         // Giải mã tin nhắn và phân loại.
         // Deserialize and parse the message.
-        match bincode::deserialize(&serialized) {
+        match message {
             // Nếu là một batch hoàn chỉnh, gửi đến Processor để xử lý.
-            Ok(WorkerMessage::Batch(..)) => self
+            Ok(WorkerMessage::Batch(..)) | Ok(WorkerMessage::CompressedBatch(..)) => self
                 .tx_processor
                 .send(serialized.to_vec())
                 .await
@@ -312,6 +592,19 @@ impl MessageHandler for WorkerReceiverHandler {
                 .send((missing, requestor))
                 .await
                 .expect("Failed to send batch request"),
+            // Nếu là batch trả lời yêu cầu đồng bộ, gửi đến Synchronizer để kiểm tra.
+            Ok(WorkerMessage::BatchReply(batch, origin)) => self
+                .tx_batch_reply
+                .send((batch, origin))
+                .await
+                .expect("Failed to send batch reply"),
+            // Nếu là một mảnh của batch, gửi đến Reconstructor.
+            Ok(WorkerMessage::Chunk(chunk)) => self
+                .tx_chunk
+                .send(chunk)
+                .await
+                .expect("Failed to send chunk"),
+            Ok(WorkerMessage::RequestBatch(..)) => unreachable!(),
             Err(e) => warn!("Serialization error: {}", e),
         }
         Ok(())
@@ -321,20 +614,49 @@ impl MessageHandler for WorkerReceiverHandler {
 /// Defines how the network receiver handles incoming primary messages.
 #[derive(Clone)]
 struct PrimaryReceiverHandler {
+    store: Store,
+    commit_notifier: CommitNotifier,
+    consensus_round: Arc<AtomicU64>,
     tx_synchronizer: Sender<PrimaryWorkerMessage>,
+    tx_reconstructor: Sender<Digest>,
+    tx_pruner: Sender<Round>,
 }
 
 #[async_trait]
 impl MessageHandler for PrimaryReceiverHandler {
     async fn dispatch(
         &self,
-        _writer: &mut Writer,
+        writer: &mut Writer,
         serialized: Bytes,
     ) -> Result<(), Box<dyn Error>> {
         // Deserialize the message and send it to the synchronizer.
          // Giải mã lệnh và gửi nó đến Synchronizer.
         match bincode::deserialize(&serialized) {
             Err(e) => error!("Failed to deserialize primary message: {}", e),
+            Ok(PrimaryWorkerMessage::RequestBatch(digest)) => {
+                // If we only hold a chunk of the batch, reconstruct it for the next request.
+                if !serve_batch(&self.store, writer, &digest).await? {
+                    self.tx_reconstructor
+                        .send(digest)
+                        .await
+                        .expect("Failed to send reconstruction request");
+                }
+            }
+            Ok(PrimaryWorkerMessage::Committed(digests, round)) => {
+                self.commit_notifier.commit(digests, round)
+            }
+            Ok(PrimaryWorkerMessage::Cleanup(round)) => {
+                // Index the batches we store from now on under this round, and prune the old ones.
+                self.consensus_round.store(round, Ordering::Relaxed);
+                self.tx_pruner
+                    .send(round)
+                    .await
+                    .expect("Failed to send round to the pruner");
+                self.tx_synchronizer
+                    .send(PrimaryWorkerMessage::Cleanup(round))
+                    .await
+                    .expect("Failed to send cleanup message");
+            }
             Ok(message) => self
                 .tx_synchronizer
                 .send(message)
@@ -344,3 +666,16 @@ impl MessageHandler for PrimaryReceiverHandler {
         Ok(())
     }
 }
+
+/// Replies to a batch request with the serialized batch (or `None` if we do not have it). Returns
+/// whether we had the batch.
+async fn serve_batch(
+    store: &Store,
+    writer: &mut Writer,
+    digest: &Digest,
+) -> Result<bool, Box<dyn Error>> {
+    let batch = store.clone().read(digest.to_vec()).await?;
+    let reply = bincode::serialize(&batch)?;
+    writer.send(Bytes::from(reply)).await?;
+    Ok(batch.is_some())
+}